@@ -0,0 +1,27 @@
+#!/bin/sh
+# Starts a small process tree like games with launchers and crash handlers do.
+# Usage: process_tree.sh <polite|stubborn|orphans> <pid file>
+# The pid file lists every process of the tree once all of them started.
+mode="$1"
+pids="$2"
+
+if [ "$mode" = stubborn ]; then
+    trap '' TERM
+fi
+
+echo $$ > "$pids.tmp"
+sleep 300 &
+echo $! >> "$pids.tmp"
+# A child with a child of its own, the signal disposition is inherited
+sh -c 'sleep 300 & echo $! >> "$1"; wait' sh "$pids.tmp" &
+echo $! >> "$pids.tmp"
+
+while [ "$(wc -l < "$pids.tmp")" -lt 4 ]; do
+    sleep 0.05
+done
+mv "$pids.tmp" "$pids"
+
+if [ "$mode" = orphans ]; then
+    exit 0
+fi
+wait
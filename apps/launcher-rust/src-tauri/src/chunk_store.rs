@@ -0,0 +1,268 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::hashing::{HashAlgorithm, StreamingHasher};
+
+const COMPRESSION_LEVEL: i32 = 3;
+
+// Lets several installs add the same chunk at once without sharing a temp file
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+const REFS_DIR: &str = "refs";
+
+/// Chunks one install uses, stored under `refs` in the store
+#[derive(Serialize, Deserialize)]
+struct InstallReferences {
+    install_dir: String,
+    chunks: Vec<String>,
+}
+
+/// Content addressed store of zstd compressed chunks downloaded for installs.
+/// Chunks of installed files are read from the files themselves, so the store
+/// only holds chunks until the install that downloaded them is assembled.
+/// Every install in progress records the chunks it takes from the store,
+/// chunks no install uses are removed by `collect_garbage`.
+#[derive(Clone)]
+pub struct ChunkStore {
+    root: PathBuf,
+    // Held while references change or garbage is collected, so a chunk an
+    // install just started to use is never collected
+    refs_lock: Arc<Mutex<()>>,
+}
+
+impl ChunkStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            refs_lock: Arc::default(),
+        }
+    }
+
+    fn refs_path(&self, install_dir: &Path) -> PathBuf {
+        let key = blake3::hash(install_dir.to_string_lossy().as_bytes()).to_hex();
+        self.root
+            .join(REFS_DIR)
+            .join(format!("{}.json", &key[..32]))
+    }
+
+    fn read_references(path: &Path) -> Result<Vec<String>, String> {
+        let json = match std::fs::read(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(format!("Failed to read chunk references: {}", e)),
+        };
+        let references: InstallReferences = serde_json::from_slice(&json)
+            .map_err(|e| format!("Failed to parse chunk references: {}", e))?;
+        Ok(references.chunks)
+    }
+
+    fn write_references(&self, install_dir: &Path, chunks: Vec<String>) -> Result<(), String> {
+        let path = self.refs_path(install_dir);
+        let parent = path
+            .parent()
+            .ok_or_else(|| format!("Invalid references path: {}", path.display()))?;
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create references directory: {}", e))?;
+
+        let json = serde_json::to_vec(&InstallReferences {
+            install_dir: install_dir.to_string_lossy().to_string(),
+            chunks,
+        })
+        .map_err(|e| format!("Failed to serialize chunk references: {}", e))?;
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, json)
+            .and_then(|_| std::fs::rename(&temp_path, &path))
+            .map_err(|e| format!("Failed to write chunk references: {}", e))
+    }
+
+    /// Adds chunks to the ones the install uses. Called before an install
+    /// looks for chunks in the store, so none of them is collected meanwhile.
+    pub fn add_references<'a>(
+        &self,
+        install_dir: &Path,
+        chunks: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), String> {
+        let _lock = self.refs_lock.lock().unwrap();
+        let mut all: HashSet<String> = Self::read_references(&self.refs_path(install_dir))?
+            .into_iter()
+            .collect();
+        all.extend(chunks.into_iter().map(str::to_string));
+        self.write_references(install_dir, all.into_iter().collect())
+    }
+
+    /// Forgets the chunks of an install that was assembled or removed
+    pub fn remove_references(&self, install_dir: &Path) -> Result<(), String> {
+        let _lock = self.refs_lock.lock().unwrap();
+        match std::fs::remove_file(self.refs_path(install_dir)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Failed to remove chunk references: {}", e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes every chunk no install references. Returns the number of
+    /// chunks and bytes freed.
+    pub fn collect_garbage(&self) -> Result<(usize, u64), String> {
+        let _lock = self.refs_lock.lock().unwrap();
+
+        let mut referenced = HashSet::new();
+        if let Ok(entries) = std::fs::read_dir(self.root.join(REFS_DIR)) {
+            for entry in entries.flatten() {
+                if entry.path().extension().is_some_and(|ext| ext == "json") {
+                    referenced.extend(Self::read_references(&entry.path())?);
+                }
+            }
+        }
+
+        let (mut removed, mut removed_bytes) = (0, 0);
+        for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+            let Ok(prefixes) = std::fs::read_dir(self.root.join(algorithm.name())) else {
+                continue;
+            };
+            for chunk in prefixes
+                .flatten()
+                .filter_map(|prefix| std::fs::read_dir(prefix.path()).ok())
+                .flatten()
+                .flatten()
+            {
+                let name = chunk.file_name().to_string_lossy().to_string();
+                // Temp files of chunks being inserted
+                if name.starts_with('.') {
+                    continue;
+                }
+                if referenced.contains(&format!("{}:{}", algorithm.name(), name)) {
+                    continue;
+                }
+
+                let size = chunk.metadata().map(|m| m.len()).unwrap_or(0);
+                if std::fs::remove_file(chunk.path()).is_ok() {
+                    removed += 1;
+                    removed_bytes += size;
+                }
+            }
+        }
+
+        Ok((removed, removed_bytes))
+    }
+
+    fn chunk_path(&self, hash: &str) -> Result<PathBuf, String> {
+        let (algorithm, digest) = HashAlgorithm::parse_tagged(hash)
+            .filter(|(_, digest)| {
+                digest.len() == 64 && digest.bytes().all(|b| b.is_ascii_hexdigit())
+            })
+            .ok_or_else(|| format!("Invalid chunk hash: {}", hash))?;
+        let digest = digest.to_ascii_lowercase();
+
+        Ok(self
+            .root
+            .join(algorithm.name())
+            .join(&digest[..2])
+            .join(digest))
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.chunk_path(hash).is_ok_and(|path| path.is_file())
+    }
+
+    /// Adds a chunk whose hash the caller already checked. Returns the number
+    /// of bytes written, zero when the chunk was already stored.
+    pub fn insert(&self, hash: &str, data: &[u8]) -> Result<u64, String> {
+        let path = self.chunk_path(hash)?;
+        if path.is_file() {
+            return Ok(0);
+        }
+
+        let parent = path
+            .parent()
+            .ok_or_else(|| format!("Invalid chunk path: {}", path.display()))?;
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create chunk directory: {}", e))?;
+
+        let compressed = zstd::bulk::compress(data, COMPRESSION_LEVEL)
+            .map_err(|e| format!("Failed to compress chunk: {}", e))?;
+
+        let temp_path = parent.join(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = std::fs::write(&temp_path, &compressed)
+            .and_then(|_| std::fs::rename(&temp_path, &path));
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(format!("Failed to store chunk {}: {}", hash, e));
+        }
+
+        Ok(compressed.len() as u64)
+    }
+
+    /// Reads a chunk back. A damaged chunk is dropped from the store so the
+    /// next install fetches it again.
+    pub fn read(&self, hash: &str) -> Result<Vec<u8>, String> {
+        let path = self.chunk_path(hash)?;
+        let compressed =
+            std::fs::read(&path).map_err(|e| format!("Failed to read chunk {}: {}", hash, e))?;
+        let data = zstd::stream::decode_all(compressed.as_slice());
+
+        match data {
+            Ok(data) if chunk_hash_matches(hash, &data) => Ok(data),
+            _ => {
+                let _ = std::fs::remove_file(&path);
+                Err(format!("Chunk {} is damaged", hash))
+            }
+        }
+    }
+}
+
+/// Hashes the data with the algorithm of `hash` and compares the digests
+pub fn chunk_hash_matches(hash: &str, data: &[u8]) -> bool {
+    let Some((algorithm, _)) = HashAlgorithm::parse_tagged(hash) else {
+        return false;
+    };
+
+    let mut hasher = StreamingHasher::new(algorithm);
+    hasher.update(data);
+    hasher.finalize().eq_ignore_ascii_case(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn hash(data: &[u8]) -> String {
+        let mut hasher = StreamingHasher::new(HashAlgorithm::Sha256);
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    #[test]
+    fn collects_chunks_no_install_references() {
+        let root = TempDir::new("chunk_store", "gc");
+        let store = ChunkStore::new(root.0.join("chunks"));
+        let (shared, old, new) = (hash(b"shared"), hash(b"old"), hash(b"new"));
+        for (hash, data) in [(&shared, &b"shared"[..]), (&old, b"old"), (&new, b"new")] {
+            store.insert(hash, data).unwrap();
+        }
+        let (game_a, game_b) = (Path::new("/games/a"), Path::new("/games/b"));
+
+        store
+            .add_references(game_a, [shared.as_str(), old.as_str()])
+            .unwrap();
+        store.add_references(game_b, [shared.as_str()]).unwrap();
+        store.add_references(game_a, [new.as_str()]).unwrap();
+        assert_eq!(store.collect_garbage().unwrap().0, 0);
+
+        // Once `a` is assembled only the chunks `b` still needs are kept
+        store.remove_references(game_a).unwrap();
+        assert_eq!(store.collect_garbage().unwrap().0, 2);
+        assert!(store.contains(&shared));
+        assert!(!store.contains(&old));
+        assert!(!store.contains(&new));
+    }
+}
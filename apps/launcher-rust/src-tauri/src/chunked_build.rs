@@ -0,0 +1,859 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+use futures_util::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use tar::Header;
+
+use crate::chunk_store::{chunk_hash_matches, ChunkStore};
+use crate::chunking::{ChunkParams, Chunker};
+use crate::delta::Rollback;
+use crate::hashing::{HashAlgorithm, StreamingHasher};
+use crate::http_client::HttpClient;
+use crate::launch_profiles::validate_build_profiles;
+use crate::manifest::{
+    local_path, manifest_path, Manifest, ManifestChunk, ManifestEntry, ManifestHasher,
+    MANIFEST_FILE_NAME,
+};
+use crate::pack::{collect_files, file_mode, validate_source_folder};
+use crate::process_manager::{CommandError, ProcessManager};
+use crate::rate_meter::RateMeter;
+use crate::repair::{sibling_path, stage_verified};
+use crate::ProgressCallbackData;
+
+// Chunks are named by SHA-256 so the backend can check uploads without extra dependencies
+const CHUNK_HASH: HashAlgorithm = HashAlgorithm::Sha256;
+const TRANSFER_CONCURRENCY: usize = 8;
+// Hashes per request when asking the backend which chunks it lacks
+const MISSING_BATCH_SIZE: usize = 1000;
+
+#[derive(Serialize)]
+pub struct ChunkedBuild {
+    manifest_path: String,
+    store_path: String,
+    total_bytes: u64,
+    chunk_count: usize,
+    new_chunks: usize,
+    // Compressed size of the chunks this build added to the store
+    new_bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct ChunkUploadReport {
+    uploaded: usize,
+    skipped: usize,
+}
+
+#[derive(Serialize)]
+pub struct ChunkInstallReport {
+    downloaded_chunks: usize,
+    downloaded_bytes: u64,
+    reused_chunks: usize,
+    written_files: usize,
+    unchanged_files: usize,
+}
+
+#[derive(Serialize)]
+struct MissingChunksRequest<'a> {
+    hashes: Vec<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct MissingChunksResponse {
+    missing: Vec<String>,
+}
+
+/// Progress over a known number of bytes, shared by concurrent transfers
+struct ProgressReporter {
+    channel: tauri::ipc::Channel<ProgressCallbackData>,
+    current_bytes: AtomicU64,
+    total_bytes: u64,
+    rate: Mutex<RateMeter>,
+}
+
+impl ProgressReporter {
+    fn new(
+        channel: tauri::ipc::Channel<ProgressCallbackData>,
+        total_bytes: u64,
+        speed_update_interval: Option<f64>,
+    ) -> Self {
+        Self {
+            channel,
+            current_bytes: AtomicU64::new(0),
+            total_bytes,
+            rate: Mutex::new(RateMeter::new(Duration::from_secs_f64(
+                speed_update_interval.unwrap_or(1.0),
+            ))),
+        }
+    }
+
+    fn add(&self, delta: u64) {
+        let current_bytes = self.current_bytes.fetch_add(delta, Ordering::Relaxed) + delta;
+        let mut rate = self.rate.lock().unwrap();
+        rate.add_value(delta);
+
+        let res = self
+            .channel
+            .send(ProgressCallbackData {
+                current_bytes,
+                total_bytes: self.total_bytes.max(current_bytes),
+                delta_per_second: rate.get_rate() as u64,
+            })
+            .map_err(|e| format!("Failed to emit chunk progress info: {}", e));
+        if let Err(e) = res {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+/// Hex digest of a chunk hash, the form the backend names chunks by
+fn chunk_digest(hash: &str) -> Result<&str, String> {
+    match HashAlgorithm::parse_tagged(hash) {
+        Some((CHUNK_HASH, digest)) => Ok(digest),
+        _ => Err(format!("Unsupported chunk hash: {}", hash)),
+    }
+}
+
+/// Unique chunks of the manifest with their sizes
+fn unique_chunks(manifest: &Manifest) -> Result<HashMap<&str, u64>, String> {
+    if manifest.chunking.is_none() {
+        return Err("Build was not packed into chunks".to_string());
+    }
+
+    let mut chunks = HashMap::new();
+    for chunk in manifest.files.iter().flat_map(|f| &f.chunks) {
+        chunk_digest(&chunk.hash)?;
+        chunks.insert(chunk.hash.as_str(), chunk.size);
+    }
+    Ok(chunks)
+}
+
+/// Cuts every file of the folder into content-defined chunks, adds the new
+/// ones to the store and returns the manifest describing the build
+pub fn pack_chunks(
+    source_path: &Path,
+    store: &ChunkStore,
+    params: ChunkParams,
+    on_progress: impl Fn(u64),
+) -> Result<(Manifest, usize, u64), String> {
+    let (entries, _) = collect_files(source_path)?;
+    let mut manifest = Manifest::new(None);
+    manifest.chunking = Some(params);
+    let (mut new_chunks, mut new_bytes) = (0, 0);
+
+    for entry in entries {
+        let entry_path = entry.path();
+        let relative_path = entry_path
+            .strip_prefix(source_path)
+            .map_err(|e| format!("Failed to calculate relative path: {}", e))?;
+        if relative_path == Path::new(MANIFEST_FILE_NAME) {
+            continue;
+        }
+
+        let file = File::open(&entry_path)
+            .map_err(|e| format!("Failed to open {}: {}", entry_path.display(), e))?;
+        let metadata = file
+            .metadata()
+            .map_err(|e| format!("Failed to get file metadata: {}", e))?;
+
+        let mut header = Header::new_gnu();
+        header.set_metadata(&metadata);
+
+        let mut chunker = Chunker::new(ManifestHasher::new(file, None), params);
+        let mut chunks = vec![];
+        let mut offset = 0;
+
+        while let Some(data) = chunker
+            .next_chunk()
+            .map_err(|e| format!("Failed to read {}: {}", entry_path.display(), e))?
+        {
+            let mut hasher = StreamingHasher::new(CHUNK_HASH);
+            hasher.update(&data);
+            let hash = hasher.finalize();
+
+            let written = store.insert(&hash, &data)?;
+            if written > 0 {
+                new_chunks += 1;
+                new_bytes += written;
+            }
+
+            chunks.push(ManifestChunk {
+                offset,
+                size: data.len() as u64,
+                hash,
+            });
+            offset += data.len() as u64;
+            on_progress(data.len() as u64);
+        }
+
+        let (hash, _) = chunker.into_inner().finalize();
+        manifest.push(ManifestEntry {
+            path: manifest_path(relative_path),
+            size: metadata.len(),
+            mode: file_mode(&header)?,
+            mtime: header.mtime().ok(),
+            hash,
+            chunks,
+        });
+    }
+
+    Ok((manifest, new_chunks, new_bytes))
+}
+
+/// Where a chunk of the installed build can be read back
+pub struct InstalledChunk {
+    path: PathBuf,
+    offset: u64,
+}
+
+/// Chunks of the installed build that can be read from its files instead of
+/// being downloaded. Files whose size or modification time changed since
+/// they were installed are left out.
+pub fn installed_chunks(
+    install_dir: &Path,
+    installed: &Manifest,
+) -> HashMap<String, InstalledChunk> {
+    let mut chunks = HashMap::new();
+    for entry in &installed.files {
+        let Ok(path) = local_path(install_dir, &entry.path) else {
+            continue;
+        };
+        let is_intact = std::fs::metadata(&path).is_ok_and(|m| {
+            let mtime = m
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs());
+            m.is_file() && m.len() == entry.size && (entry.mtime.is_none() || mtime == entry.mtime)
+        });
+        if !is_intact {
+            continue;
+        }
+
+        for chunk in &entry.chunks {
+            chunks
+                .entry(chunk.hash.clone())
+                .or_insert_with(|| InstalledChunk {
+                    path: path.clone(),
+                    offset: chunk.offset,
+                });
+        }
+    }
+    chunks
+}
+
+/// Chunks a new build is assembled from: the installed files first, then
+/// the chunks downloaded into the store
+struct ChunkSources<'a> {
+    installed: &'a HashMap<String, InstalledChunk>,
+    store: &'a ChunkStore,
+}
+
+impl ChunkSources<'_> {
+    fn read(&self, chunk: &ManifestChunk) -> Result<Vec<u8>, String> {
+        if let Some(source) = self.installed.get(&chunk.hash) {
+            let read_installed = || {
+                let mut file = File::open(&source.path)?;
+                file.seek(SeekFrom::Start(source.offset))?;
+                let mut data = vec![0; chunk.size as usize];
+                file.read_exact(&mut data)?;
+                Ok::<_, std::io::Error>(data)
+            };
+            let data = read_installed()
+                .ok()
+                .filter(|data| chunk_hash_matches(&chunk.hash, data));
+            if let Some(data) = data {
+                return Ok(data);
+            }
+            if !self.store.contains(&chunk.hash) {
+                return Err(format!(
+                    "Chunk {} is damaged in {}, repair the installation first",
+                    chunk.hash,
+                    source.path.display()
+                ));
+            }
+        }
+
+        self.store.read(&chunk.hash)
+    }
+}
+
+/// Reads a file back from its chunks
+struct ChunkedFileReader<'a> {
+    sources: &'a ChunkSources<'a>,
+    chunks: std::slice::Iter<'a, ManifestChunk>,
+    current: Cursor<Vec<u8>>,
+}
+
+impl Read for ChunkedFileReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let read = self.current.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+
+            let Some(chunk) = self.chunks.next() else {
+                return Ok(0);
+            };
+            let data = self.sources.read(chunk).map_err(std::io::Error::other)?;
+            if data.len() as u64 != chunk.size {
+                return Err(std::io::Error::other(format!(
+                    "Chunk {} has the wrong size",
+                    chunk.hash
+                )));
+            }
+            self.current = Cursor::new(data);
+        }
+    }
+}
+
+/// Writes every file that differs from the installed build, then drops files
+/// the new build no longer has. The new files are staged next to their
+/// targets until all of them are written, since their chunks may be read
+/// from the files they replace, and committed through a rollback journal.
+/// Unchanged files take the new mode and modification time. Returns written
+/// and unchanged file counts.
+fn assemble_files(
+    install_dir: &Path,
+    installed: Option<&Manifest>,
+    sources: &ChunkSources,
+    manifest: &Manifest,
+    on_write: Arc<dyn Fn(u64) + Send + Sync>,
+) -> Result<(usize, usize), String> {
+    let installed_files: HashMap<&str, &ManifestEntry> = installed
+        .iter()
+        .flat_map(|m| &m.files)
+        .map(|f| (f.path.as_str(), f))
+        .collect();
+
+    let mut staged = vec![];
+    let mut unchanged = vec![];
+    let mut rollback = Rollback::default();
+    let result = (|| {
+        for entry in &manifest.files {
+            let target = local_path(install_dir, &entry.path)?;
+            let is_unchanged = installed_files
+                .get(entry.path.as_str())
+                .is_some_and(|old| old.hash == entry.hash)
+                && std::fs::metadata(&target).is_ok_and(|m| m.is_file() && m.len() == entry.size);
+            if is_unchanged {
+                unchanged.push((target, entry));
+                on_write(entry.size);
+                continue;
+            }
+
+            let reader = ChunkedFileReader {
+                sources,
+                chunks: entry.chunks.iter(),
+                current: Cursor::new(vec![]),
+            };
+            staged.push((
+                stage_verified(install_dir, entry, reader, &on_write)?,
+                target,
+            ));
+        }
+
+        for (temp_path, target) in &staged {
+            rollback
+                .replace(Some(temp_path), target)
+                .map_err(|e| format!("Failed to replace {}: {}", target.display(), e))?;
+        }
+        for (target, entry) in &unchanged {
+            rollback
+                .set_metadata(target, entry)
+                .map_err(|e| format!("Failed to update {}: {}", entry.path, e))?;
+        }
+
+        let expected: HashSet<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+        for path in installed_files
+            .keys()
+            .filter(|path| !expected.contains(*path))
+        {
+            let target = local_path(install_dir, path)?;
+            rollback
+                .replace(None, &target)
+                .map_err(|e| format!("Failed to remove {}: {}", path, e))?;
+        }
+
+        let manifest_path = install_dir.join(MANIFEST_FILE_NAME);
+        let temp_manifest = sibling_path(&manifest_path, "repair");
+        std::fs::write(&temp_manifest, manifest.to_json()?)
+            .and_then(|_| rollback.replace(Some(temp_manifest.as_path()), &manifest_path))
+            .map_err(|e| {
+                let _ = std::fs::remove_file(&temp_manifest);
+                format!("Failed to write manifest: {}", e)
+            })
+    })();
+
+    match result {
+        Ok(()) => {
+            rollback.finish();
+            Ok((staged.len(), unchanged.len()))
+        }
+        Err(e) => {
+            rollback.restore();
+            for (temp_path, _) in &staged {
+                let _ = std::fs::remove_file(temp_path);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Packs a build folder into a chunk store for uploads with `upload_chunks`.
+/// Reusing the store of earlier builds keeps only new chunks to upload.
+#[tauri::command]
+pub async fn pack_chunked_build(
+    folder_path: String,
+    store_path: Option<String>,
+    progress_channel: tauri::ipc::Channel<ProgressCallbackData>,
+    speed_update_interval: Option<f64>,
+) -> Result<ChunkedBuild, String> {
+    let source_path = validate_source_folder(&folder_path)?.to_path_buf();
+    validate_build_profiles(&source_path)?;
+    let folder_name = source_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| "Invalid folder name".to_string())?
+        .to_string();
+    let parent_dir = source_path
+        .parent()
+        .ok_or_else(|| "Cannot get parent directory".to_string())?
+        .to_path_buf();
+
+    let store_path = store_path
+        .map(PathBuf::from)
+        .unwrap_or_else(|| parent_dir.join(format!("{}.chunks", folder_name)));
+    let manifest_path = parent_dir.join(format!("{}.chunks.json", folder_name));
+
+    let (_, total_bytes) = collect_files(&source_path)?;
+    let progress = ProgressReporter::new(progress_channel, total_bytes, speed_update_interval);
+    let store = ChunkStore::new(store_path.clone());
+
+    let (manifest, new_chunks, new_bytes) = tokio::task::spawn_blocking(move || {
+        pack_chunks(&source_path, &store, ChunkParams::default(), |delta| {
+            progress.add(delta)
+        })
+    })
+    .await
+    .map_err(|e| format!("Chunking task failed: {}", e))??;
+
+    std::fs::write(&manifest_path, manifest.to_json()?)
+        .map_err(|e| format!("Failed to write manifest: {}", e))?;
+
+    Ok(ChunkedBuild {
+        manifest_path: manifest_path.to_string_lossy().to_string(),
+        store_path: store_path.to_string_lossy().to_string(),
+        total_bytes: manifest.total_bytes,
+        chunk_count: unique_chunks(&manifest)?.len(),
+        new_chunks,
+        new_bytes,
+    })
+}
+
+/// Uploads the chunks of a manifest the backend does not have yet.
+/// `url` is the chunk endpoint, chunks go to `<url>/<sha256 hex>`.
+#[tauri::command]
+pub async fn upload_chunks(
+    http: tauri::State<'_, HttpClient>,
+    url: String,
+    manifest_path: String,
+    store_path: String,
+    auth_token: Option<String>,
+    progress_channel: tauri::ipc::Channel<ProgressCallbackData>,
+    speed_update_interval: Option<f64>,
+) -> Result<ChunkUploadReport, String> {
+    let url = url.trim_end_matches('/').to_string();
+    let manifest = Manifest::load(Path::new(&manifest_path))?;
+    let chunks = unique_chunks(&manifest)?;
+    let store = ChunkStore::new(PathBuf::from(store_path));
+    let http = http.inner();
+    let client = http.client();
+
+    let with_auth = |request: reqwest::RequestBuilder| match &auth_token {
+        Some(token) => request.header("Authorization", format!("Bearer {}", token)),
+        None => request,
+    };
+
+    let hashes: Vec<&str> = chunks.keys().copied().collect();
+    let mut missing = vec![];
+    for batch in hashes.chunks(MISSING_BATCH_SIZE) {
+        let digests = batch
+            .iter()
+            .map(|hash| chunk_digest(hash))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let request = with_auth(client.post(format!("{}/missing", url)))
+            .json(&MissingChunksRequest { hashes: digests });
+        let response = http
+            .send(request)
+            .await
+            .map_err(|e| format!("Failed to send request: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "Failed to check chunks with status {}",
+                response.status()
+            ));
+        }
+
+        let body: MissingChunksResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse response: {}", e))?;
+        let requested: HashSet<&str> = batch.iter().copied().collect();
+        missing.extend(
+            body.missing
+                .into_iter()
+                .map(|digest| format!("{}:{}", CHUNK_HASH.name(), digest.to_ascii_lowercase()))
+                .filter(|hash| requested.contains(hash.as_str())),
+        );
+    }
+
+    let total_bytes = missing.iter().map(|hash| chunks[hash.as_str()]).sum();
+    let progress = Arc::new(ProgressReporter::new(
+        progress_channel,
+        total_bytes,
+        speed_update_interval,
+    ));
+
+    let uploads = missing.iter().map(|hash| {
+        let store = store.clone();
+        let progress = progress.clone();
+        let request = with_auth(client.put(format!("{}/{}", url, chunk_digest(hash)?)));
+        let hash = hash.clone();
+
+        Ok::<_, String>(async move {
+            let data = tokio::task::spawn_blocking(move || store.read(&hash))
+                .await
+                .map_err(|e| format!("Chunk task failed: {}", e))??;
+            let size = data.len() as u64;
+
+            let request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+                .body(data);
+            let response = http
+                .send(request)
+                .await
+                .map_err(|e| format!("Failed to upload chunk: {}", e))?;
+            if !response.status().is_success() {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                return Err(format!(
+                    "Chunk upload failed with status {}: {}",
+                    status, body
+                ));
+            }
+
+            progress.add(size);
+            Ok(())
+        })
+    });
+
+    futures_util::stream::iter(uploads.collect::<Result<Vec<_>, _>>()?)
+        .buffer_unordered(TRANSFER_CONCURRENCY)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    Ok(ChunkUploadReport {
+        uploaded: missing.len(),
+        skipped: chunks.len() - missing.len(),
+    })
+}
+
+/// Installs or updates a chunked build in place. Chunks of the installed
+/// build are read from its files, only the others are downloaded into the
+/// launcher's chunk store and dropped from it once the files are written.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub async fn install_chunked_build(
+    http: tauri::State<'_, HttpClient>,
+    store: tauri::State<'_, ChunkStore>,
+    manager: tauri::State<'_, ProcessManager>,
+    install_dir: String,
+    manifest_url: String,
+    chunks_url: String,
+    auth_token: Option<String>,
+    progress_channel: tauri::ipc::Channel<ProgressCallbackData>,
+    speed_update_interval: Option<f64>,
+) -> Result<ChunkInstallReport, CommandError> {
+    let install_dir = PathBuf::from(install_dir);
+    let _reservation = manager.reserve_install(&install_dir)?;
+    let chunks_url = chunks_url.trim_end_matches('/').to_string();
+    let store = store.inner().clone();
+    let http = http.inner();
+    let client = http.client();
+
+    let response = http
+        .send(client.get(&manifest_url))
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Manifest download failed with status {}", response.status()).into());
+    }
+    let json = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to download manifest: {}", e))?;
+    let manifest = Manifest::parse(&json)?;
+
+    let chunks = unique_chunks(&manifest)?;
+    let installed = Manifest::load(&install_dir.join(MANIFEST_FILE_NAME)).ok();
+    let installed_chunks = installed
+        .as_ref()
+        .map(|installed| installed_chunks(&install_dir, installed))
+        .unwrap_or_default();
+
+    // Referenced before the store is searched, so a collection running
+    // meanwhile keeps the chunks this install takes from it
+    let stored_chunks: Vec<(&str, u64)> = chunks
+        .iter()
+        .filter(|(hash, _)| !installed_chunks.contains_key(**hash))
+        .map(|(hash, size)| (*hash, *size))
+        .collect();
+    store.add_references(&install_dir, stored_chunks.iter().map(|(hash, _)| *hash))?;
+    let missing: Vec<(String, u64)> = stored_chunks
+        .iter()
+        .filter(|(hash, _)| !store.contains(hash))
+        .map(|(hash, size)| (hash.to_string(), *size))
+        .collect();
+    let downloaded_bytes: u64 = missing.iter().map(|(_, size)| size).sum();
+
+    // Downloads and file writes share one progress bar
+    let progress = Arc::new(ProgressReporter::new(
+        progress_channel,
+        downloaded_bytes + manifest.total_bytes,
+        speed_update_interval,
+    ));
+
+    let downloaded_chunks = missing.len();
+    let reused_chunks = chunks.len() - downloaded_chunks;
+    // Chunks referenced above are released when the install fails, the ones
+    // downloaded so far stay in the store until the next collection
+    let result = {
+        let (store, install_dir) = (store.clone(), install_dir.clone());
+        async move {
+            let downloads = missing.iter().map(|(hash, size)| {
+                let store = store.clone();
+                let progress = progress.clone();
+                let mut request = client.get(format!("{}/{}", chunks_url, chunk_digest(hash)?));
+                if let Some(token) = &auth_token {
+                    request = request.header("Authorization", format!("Bearer {}", token));
+                }
+                let (hash, size) = (hash.clone(), *size);
+
+                Ok::<_, String>(async move {
+                    let response = http
+                        .send(request)
+                        .await
+                        .map_err(|e| format!("Failed to download chunk: {}", e))?;
+                    if !response.status().is_success() {
+                        return Err(format!(
+                            "Chunk download failed with status {}",
+                            response.status()
+                        ));
+                    }
+                    let data = response
+                        .bytes()
+                        .await
+                        .map_err(|e| format!("Failed to download chunk: {}", e))?;
+
+                    tokio::task::spawn_blocking(move || {
+                        if data.len() as u64 != size || !chunk_hash_matches(&hash, &data) {
+                            return Err(format!("Integrity check failed for chunk {}", hash));
+                        }
+                        store.insert(&hash, &data)
+                    })
+                    .await
+                    .map_err(|e| format!("Chunk task failed: {}", e))??;
+
+                    progress.add(size);
+                    Ok(())
+                })
+            });
+
+            futures_util::stream::iter(downloads.collect::<Result<Vec<_>, _>>()?)
+                .buffer_unordered(TRANSFER_CONCURRENCY)
+                .try_collect::<Vec<_>>()
+                .await?;
+
+            tokio::task::spawn_blocking(move || {
+                std::fs::create_dir_all(&install_dir)
+                    .map_err(|e| format!("Failed to create install directory: {}", e))?;
+                let sources = ChunkSources {
+                    installed: &installed_chunks,
+                    store: &store,
+                };
+                let counts = assemble_files(
+                    &install_dir,
+                    installed.as_ref(),
+                    &sources,
+                    &manifest,
+                    Arc::new(move |delta| progress.add(delta)),
+                )?;
+
+                // The files hold every chunk now, the store keeps the ones other
+                // installs in progress still use
+                store.remove_references(&install_dir)?;
+                if let Err(e) = store.collect_garbage() {
+                    eprintln!("Failed to collect unused chunks: {}", e);
+                }
+
+                Ok::<_, String>(counts)
+            })
+            .await
+            .map_err(|e| format!("Install task failed: {}", e))?
+        }
+        .await
+    };
+    if result.is_err() {
+        if let Err(e) = store.remove_references(&install_dir) {
+            eprintln!("Failed to release chunks of a failed install: {}", e);
+        }
+    }
+    let (written_files, unchanged_files) = result?;
+
+    Ok(ChunkInstallReport {
+        downloaded_chunks,
+        downloaded_bytes,
+        reused_chunks,
+        written_files,
+        unchanged_files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TempDir, MTIME};
+
+    fn write_files(dir: &Path, files: &[(&str, &str)]) {
+        std::fs::create_dir_all(dir).unwrap();
+        for (path, data) in files {
+            std::fs::write(dir.join(path), data).unwrap();
+        }
+    }
+
+    #[test]
+    fn reuses_chunks_of_the_files_it_replaces() {
+        let root = TempDir::new("chunked_build", "assemble");
+        let (install, next) = (root.0.join("install"), root.0.join("next"));
+        let packed = ChunkStore::new(root.0.join("packed"));
+        let store = ChunkStore::new(root.0.join("store"));
+        write_files(&install, &[("a", "first"), ("same", "same")]);
+        write_files(
+            &next,
+            &[("a", "replaced"), ("b", "first"), ("same", "same")],
+        );
+        // Unchanged content, but a new modification time
+        filetime::set_file_mtime(
+            next.join("same"),
+            filetime::FileTime::from_unix_time(MTIME as i64, 0),
+        )
+        .unwrap();
+
+        let (installed, _, _) =
+            pack_chunks(&install, &packed, ChunkParams::default(), |_| {}).unwrap();
+        std::fs::write(
+            install.join(MANIFEST_FILE_NAME),
+            installed.to_json().unwrap(),
+        )
+        .unwrap();
+        let (manifest, _, _) = pack_chunks(&next, &packed, ChunkParams::default(), |_| {}).unwrap();
+
+        // Only the chunk the installed files lack is downloaded
+        let installed_chunks = installed_chunks(&install, &installed);
+        for chunk in manifest.files.iter().flat_map(|f| &f.chunks) {
+            if !installed_chunks.contains_key(&chunk.hash) {
+                let data = packed.read(&chunk.hash).unwrap();
+                store.insert(&chunk.hash, &data).unwrap();
+            }
+        }
+        let sources = ChunkSources {
+            installed: &installed_chunks,
+            store: &store,
+        };
+
+        let counts = assemble_files(
+            &install,
+            Some(&installed),
+            &sources,
+            &manifest,
+            Arc::new(|_| {}),
+        )
+        .unwrap();
+
+        assert_eq!(counts, (2, 1));
+        assert_eq!(std::fs::read(install.join("a")).unwrap(), b"replaced");
+        assert_eq!(std::fs::read(install.join("b")).unwrap(), b"first");
+        // The unchanged file took the new metadata, so its chunks count as installed
+        let modified = std::fs::metadata(install.join("same")).unwrap().modified();
+        assert_eq!(modified.unwrap(), UNIX_EPOCH + Duration::from_secs(MTIME));
+        let reusable = installed_chunks(&install, &manifest);
+        assert!(manifest
+            .files
+            .iter()
+            .flat_map(|f| &f.chunks)
+            .all(|chunk| reusable.contains_key(&chunk.hash)));
+    }
+
+    #[test]
+    fn failed_assembly_keeps_the_installed_files() {
+        let root = TempDir::new("chunked_build", "assemble_rollback");
+        let (install, next) = (root.0.join("install"), root.0.join("next"));
+        let packed = ChunkStore::new(root.0.join("packed"));
+        let store = ChunkStore::new(root.0.join("store"));
+        write_files(&install, &[("a", "first")]);
+        write_files(&next, &[("a", "replaced"), ("b", "missing")]);
+
+        let (installed, _, _) =
+            pack_chunks(&install, &packed, ChunkParams::default(), |_| {}).unwrap();
+        std::fs::write(
+            install.join(MANIFEST_FILE_NAME),
+            installed.to_json().unwrap(),
+        )
+        .unwrap();
+        let (manifest, _, _) = pack_chunks(&next, &packed, ChunkParams::default(), |_| {}).unwrap();
+        // Every chunk but the ones of "b" is available
+        for entry in manifest.files.iter().filter(|f| f.path == "a") {
+            for chunk in &entry.chunks {
+                let data = packed.read(&chunk.hash).unwrap();
+                store.insert(&chunk.hash, &data).unwrap();
+            }
+        }
+        let installed_chunks = installed_chunks(&install, &installed);
+        let sources = ChunkSources {
+            installed: &installed_chunks,
+            store: &store,
+        };
+
+        let result = assemble_files(
+            &install,
+            Some(&installed),
+            &sources,
+            &manifest,
+            Arc::new(|_| {}),
+        );
+
+        assert!(result.is_err());
+        assert_eq!(std::fs::read(install.join("a")).unwrap(), b"first");
+        assert!(!install.join("b").exists());
+        assert_eq!(
+            Manifest::load(&install.join(MANIFEST_FILE_NAME))
+                .unwrap()
+                .content_hash(),
+            installed.content_hash()
+        );
+        let leftovers: Vec<_> = std::fs::read_dir(&install)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .filter(|name| name.to_string_lossy().starts_with('.'))
+            .collect();
+        assert!(leftovers.is_empty(), "{:?}", leftovers);
+    }
+}
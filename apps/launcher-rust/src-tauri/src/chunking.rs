@@ -0,0 +1,240 @@
+use std::io::Read;
+
+use serde::{Deserialize, Serialize};
+
+/// Bounds of content-defined chunks. `avg_size` must be a power of two.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkParams {
+    pub min_size: usize,
+    pub avg_size: usize,
+    pub max_size: usize,
+}
+
+impl Default for ChunkParams {
+    fn default() -> Self {
+        // The backend refuses chunks above `max_size`, keep both in sync
+        Self {
+            min_size: 256 * 1024,
+            avg_size: 1024 * 1024,
+            max_size: 4 * 1024 * 1024,
+        }
+    }
+}
+
+// Random values for every byte, generated with splitmix64 so the table and
+// with it every chunk boundary stay the same across launcher versions
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut state: u64 = 0x5a50_5553_4b41_4c4b;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Mask over the top bits, the gear hash shifts left so those depend on
+/// the last 64 bytes
+const fn top_bits(bits: u32) -> u64 {
+    !0 << (64 - bits)
+}
+
+impl ChunkParams {
+    /// Length of the chunk at the start of `data`. `data` has to hold at
+    /// least `max_size` bytes unless it is the end of the file.
+    ///
+    /// FastCDC with normalized chunking: a harder mask before `avg_size` and
+    /// an easier one after it keep most chunks close to the average.
+    pub fn cut_point(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min_size {
+            return data.len();
+        }
+
+        let bits = self.avg_size.max(16).ilog2();
+        let (mask_small, mask_large) = (top_bits(bits + 2), top_bits(bits - 2));
+        let max = data.len().min(self.max_size);
+        let normal = max.min(self.avg_size);
+
+        let mut hash = 0_u64;
+        for (i, byte) in data.iter().enumerate().take(max).skip(self.min_size) {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            let mask = if i < normal { mask_small } else { mask_large };
+            if hash & mask == 0 {
+                return i + 1;
+            }
+        }
+
+        max
+    }
+}
+
+/// Splits a reader into content-defined chunks, so an edit inside a file
+/// only changes the chunks around it
+pub struct Chunker<R> {
+    inner: R,
+    params: ChunkParams,
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    pub fn new(inner: R, params: ChunkParams) -> Self {
+        Self {
+            inner,
+            params,
+            buf: Vec::with_capacity(params.max_size),
+            eof: false,
+        }
+    }
+
+    pub fn next_chunk(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        while !self.eof && self.buf.len() < self.params.max_size {
+            let start = self.buf.len();
+            self.buf.resize(self.params.max_size, 0);
+            match self.inner.read(&mut self.buf[start..]) {
+                Ok(read) => {
+                    self.buf.truncate(start + read);
+                    self.eof = read == 0;
+                }
+                Err(e) => {
+                    self.buf.truncate(start);
+                    if e.kind() != std::io::ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+
+        let cut = self.params.cut_point(&self.buf);
+        let rest = self.buf.split_off(cut);
+        Ok(Some(std::mem::replace(&mut self.buf, rest)))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: ChunkParams = ChunkParams {
+        min_size: 1024,
+        avg_size: 4096,
+        max_size: 16384,
+    };
+
+    /// Pseudo-random bytes, content-defined cuts need varied data
+    fn test_data(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 33) as u8
+            })
+            .collect()
+    }
+
+    fn chunks(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut chunker = Chunker::new(data, PARAMS);
+        let mut chunks = vec![];
+        while let Some(chunk) = chunker.next_chunk().unwrap() {
+            chunks.push(chunk);
+        }
+        chunks
+    }
+
+    #[test]
+    fn cut_point_stays_within_bounds() {
+        assert_eq!(PARAMS.cut_point(&[7; 100]), 100);
+        assert_eq!(PARAMS.cut_point(&[7; 1024]), 1024);
+        // Same bytes never match the mask, the chunk is cut at the maximum
+        assert_eq!(PARAMS.cut_point(&[0; 100_000]), PARAMS.max_size);
+
+        let data = test_data(100_000, 1);
+        let cut = PARAMS.cut_point(&data);
+        assert!(cut > PARAMS.min_size && cut <= PARAMS.max_size, "{}", cut);
+        assert_eq!(PARAMS.cut_point(&data[..cut + 10]), cut);
+    }
+
+    #[test]
+    fn chunks_cover_the_data_and_average_out() {
+        let data = test_data(1 << 20, 2);
+        let chunks = chunks(&data);
+
+        assert_eq!(chunks.concat(), data);
+        assert!(chunks[..chunks.len() - 1]
+            .iter()
+            .all(|c| c.len() > PARAMS.min_size && c.len() <= PARAMS.max_size));
+        let average = data.len() / chunks.len();
+        assert!(
+            average > PARAMS.avg_size / 2 && average < PARAMS.avg_size * 2,
+            "{}",
+            average
+        );
+    }
+
+    #[test]
+    fn boundaries_after_an_insert_stay_the_same() {
+        let data = test_data(1 << 20, 3);
+        let mut edited = data.clone();
+        edited.splice(100_000..100_000, test_data(50, 4));
+
+        let before = chunks(&data);
+        let after = chunks(&edited);
+
+        // Only the chunks around the edit change
+        let shared = after.iter().filter(|c| before.contains(c)).count();
+        assert!(shared >= before.len() - 3, "{} of {}", shared, before.len());
+        assert_eq!(before.last(), after.last());
+    }
+
+    #[test]
+    fn boundaries_do_not_depend_on_read_sizes() {
+        /// Hands out a few bytes per read
+        struct Trickle<'a>(&'a [u8]);
+
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let len = buf.len().min(self.0.len()).min(777);
+                buf[..len].copy_from_slice(&self.0[..len]);
+                self.0 = &self.0[len..];
+                Ok(len)
+            }
+        }
+
+        let data = test_data(200_000, 5);
+        let mut chunker = Chunker::new(Trickle(&data), PARAMS);
+        let mut trickled = vec![];
+        while let Some(chunk) = chunker.next_chunk().unwrap() {
+            trickled.push(chunk);
+        }
+
+        assert_eq!(trickled, chunks(&data));
+    }
+
+    #[test]
+    fn boundaries_match_earlier_versions() {
+        // Changing the table or the masks moves every boundary, chunks stored
+        // by earlier launchers would no longer be reused
+        assert_eq!(GEAR[0], 0xc508e654ec60685b);
+        assert_eq!(GEAR[255], 0xd079edd796cdd924);
+
+        let sizes: Vec<usize> = chunks(&test_data(30_000, 6)).iter().map(Vec::len).collect();
+        assert_eq!(sizes, [3321, 4194, 4443, 4174, 2958, 5490, 4877, 543]);
+    }
+}
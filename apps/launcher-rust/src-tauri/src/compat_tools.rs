@@ -0,0 +1,352 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::launch_wrappers::find_program;
+use crate::process_manager::validate_app_id;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompatToolKind {
+    Wine,
+    Proton,
+}
+
+/// Runner that starts Windows builds on other systems, the way Steam Play does
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CompatTool {
+    pub kind: CompatToolKind,
+    // Wine binary, looked up in PATH unless it is a path. For Proton the
+    // `proton` script or the folder of a Proton release.
+    pub path: String,
+}
+
+/// Build of a branch as listed by the backend
+#[derive(Deserialize)]
+pub struct BuildCandidate {
+    pub id: String,
+    pub os: String,
+    pub arch: String,
+}
+
+#[derive(Serialize)]
+pub struct SelectedBuild {
+    pub id: String,
+    // Runs through the compatibility tool
+    pub compatibility: bool,
+}
+
+/// What the command line of a Windows build becomes
+pub struct CompatLaunch {
+    pub command: Vec<OsString>,
+    pub env: Vec<(&'static str, OsString)>,
+}
+
+/// Whether the executable is a Windows build that needs a compatibility tool here
+pub fn needs_compat_tool(executable: &Path) -> bool {
+    !cfg!(windows)
+        && executable
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("exe"))
+}
+
+/// Windows architectures the compatibility tools run on this machine
+fn compat_archs(arch: &str) -> &'static [&'static str] {
+    match arch {
+        "x86_64" => &["x86_64", "x86"],
+        "x86" => &["x86"],
+        _ => &[],
+    }
+}
+
+/// Picks the first native build, falling back to a Windows build on Linux.
+/// `builds` are expected newest first.
+pub fn pick_build(builds: &[BuildCandidate], os: &str, arch: &str) -> Option<SelectedBuild> {
+    let native = builds.iter().find(|build| {
+        build.os == os && (build.arch == arch || (os == "macos" && build.arch == "universal"))
+    });
+    if let Some(build) = native {
+        return Some(SelectedBuild {
+            id: build.id.clone(),
+            compatibility: false,
+        });
+    }
+
+    if os != "linux" {
+        return None;
+    }
+    builds
+        .iter()
+        .find(|build| build.os == "windows" && compat_archs(arch).contains(&build.arch.as_str()))
+        .map(|build| SelectedBuild {
+            id: build.id.clone(),
+            compatibility: true,
+        })
+}
+
+/// Folder holding the Wine prefix of an app, created when missing. Proton
+/// keeps its prefix in `pfx` inside it, Wine uses the same layout.
+pub fn prepare_prefix(compat_root: &Path, app_id: &str) -> Result<PathBuf, String> {
+    let prefix = prefix_path(compat_root, app_id)?;
+    std::fs::create_dir_all(prefix.join("pfx"))
+        .map_err(|e| format!("Failed to create compatibility prefix: {}", e))?;
+    Ok(prefix)
+}
+
+fn prefix_path(compat_root: &Path, app_id: &str) -> Result<PathBuf, String> {
+    validate_app_id(app_id)?;
+    Ok(compat_root.join(app_id))
+}
+
+pub fn validate_compat_tool(tool: &CompatTool) -> Result<(), String> {
+    if tool.path.trim().is_empty() {
+        return Err("Compatibility tool path is empty".to_string());
+    }
+    Ok(())
+}
+
+fn find_tool(tool: &CompatTool, path: Option<&OsStr>) -> Option<PathBuf> {
+    match tool.kind {
+        CompatToolKind::Wine => find_program(&tool.path, path),
+        CompatToolKind::Proton => {
+            let proton = Path::new(&tool.path);
+            let script = if proton.is_dir() {
+                proton.join("proton")
+            } else {
+                proton.to_path_buf()
+            };
+            find_program(script.to_str()?, path)
+        }
+    }
+}
+
+/// Runs `command` through the tool with the prefix in `prefix`
+pub fn compat_command(
+    tool: &CompatTool,
+    compat_root: &Path,
+    prefix: &Path,
+    command: Vec<OsString>,
+    path: Option<&OsStr>,
+) -> Result<CompatLaunch, String> {
+    let runner = find_tool(tool, path)
+        .ok_or_else(|| format!("Compatibility tool not found: {}", tool.path))?;
+
+    let launch = match tool.kind {
+        CompatToolKind::Wine => CompatLaunch {
+            command: std::iter::once(runner.into_os_string())
+                .chain(command)
+                .collect(),
+            env: vec![("WINEPREFIX", prefix.join("pfx").into_os_string())],
+        },
+        CompatToolKind::Proton => CompatLaunch {
+            // Keeps Proton in the process tree until the game exits
+            command: [runner.into_os_string(), "waitforexitandrun".into()]
+                .into_iter()
+                .chain(command)
+                .collect(),
+            env: vec![
+                ("STEAM_COMPAT_DATA_PATH", prefix.as_os_str().to_owned()),
+                // Proton refuses to start without it, there is no Steam here
+                (
+                    "STEAM_COMPAT_CLIENT_INSTALL_PATH",
+                    compat_root.as_os_str().to_owned(),
+                ),
+            ],
+        },
+    };
+
+    Ok(launch)
+}
+
+pub fn compat_root(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
+    Ok(app_data_dir.join("compat"))
+}
+
+/// Picks the build to install on this machine, see `pick_build`
+#[tauri::command]
+pub fn select_build(builds: Vec<BuildCandidate>) -> Option<SelectedBuild> {
+    pick_build(&builds, std::env::consts::OS, std::env::consts::ARCH)
+}
+
+/// Path of the app prefix, none until the app ran through a compatibility tool
+#[tauri::command]
+pub fn get_compat_prefix(app: tauri::AppHandle, app_id: String) -> Result<Option<String>, String> {
+    let prefix = prefix_path(&compat_root(&app)?, &app_id)?;
+    Ok(prefix
+        .exists()
+        .then(|| prefix.to_string_lossy().to_string()))
+}
+
+/// Deletes the app prefix with everything the game stored in it, the next
+/// launch starts with a fresh one
+#[tauri::command]
+pub async fn delete_compat_prefix(app: tauri::AppHandle, app_id: String) -> Result<(), String> {
+    let prefix = prefix_path(&compat_root(&app)?, &app_id)?;
+    match std::fs::remove_dir_all(&prefix) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("Failed to delete compatibility prefix: {}", e))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::ffi::OsString;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+
+    use crate::test_support::TempDir;
+
+    // Logs what a real runner would get instead of starting the game
+    const FAKE_RUNNER: &str = "#!/bin/sh
+    {
+        echo \"args $*\"
+        echo \"WINEPREFIX=$WINEPREFIX\"
+        echo \"STEAM_COMPAT_DATA_PATH=$STEAM_COMPAT_DATA_PATH\"
+    } > \"$RUNNER_LOG\"
+    ";
+
+    fn add_runner(dir: &TempDir, relative: &str) -> PathBuf {
+        let path = dir.0.join(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, FAKE_RUNNER).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn build(id: &str, os: &str, arch: &str) -> BuildCandidate {
+        BuildCandidate {
+            id: id.to_string(),
+            os: os.to_string(),
+            arch: arch.to_string(),
+        }
+    }
+
+    /// Runs the command line the way the launcher would and returns the runner log
+    fn run(dir: &TempDir, tool: &CompatTool, app_id: &str, game: &str) -> String {
+        let root = dir.0.join("compat");
+        let prefix = prepare_prefix(&root, app_id).unwrap();
+        let command = vec![OsString::from(game), OsString::from("-windowed")];
+        let launch =
+            compat_command(tool, &root, &prefix, command, Some(dir.0.as_os_str())).unwrap();
+
+        let log = dir.0.join("log");
+        let status = std::process::Command::new(&launch.command[0])
+            .args(&launch.command[1..])
+            .envs(launch.env)
+            .env("RUNNER_LOG", &log)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        std::fs::read_to_string(log).unwrap()
+    }
+
+    #[test]
+    fn native_build_wins_over_windows() {
+        let builds = [
+            build("win", "windows", "x86_64"),
+            build("linux", "linux", "x86_64"),
+        ];
+
+        let selected = pick_build(&builds, "linux", "x86_64").unwrap();
+        assert_eq!(selected.id, "linux");
+        assert!(!selected.compatibility);
+    }
+
+    #[test]
+    fn windows_build_is_picked_without_a_native_one() {
+        let builds = [
+            build("arm", "windows", "aarch64"),
+            build("win32", "windows", "x86"),
+            build("mac", "macos", "universal"),
+        ];
+
+        let selected = pick_build(&builds, "linux", "x86_64").unwrap();
+        assert_eq!(selected.id, "win32");
+        assert!(selected.compatibility);
+
+        // Only Linux runs Windows builds
+        assert_eq!(pick_build(&builds, "macos", "aarch64").unwrap().id, "mac");
+        assert!(pick_build(&builds[..2], "macos", "aarch64").is_none());
+    }
+
+    #[test]
+    fn only_exe_entrypoints_need_a_tool() {
+        assert!(needs_compat_tool(Path::new("/games/a/Game.EXE")));
+        assert!(!needs_compat_tool(Path::new("/games/a/game.x86_64")));
+        assert!(!needs_compat_tool(Path::new("/games/a/game")));
+    }
+
+    #[test]
+    fn wine_gets_a_prefix_per_game() {
+        let dir = TempDir::new("compat_tools", "wine");
+        add_runner(&dir, "wine");
+        let tool = CompatTool {
+            kind: CompatToolKind::Wine,
+            path: "wine".to_string(),
+        };
+
+        let log = run(&dir, &tool, "app1", "Game.exe");
+        let prefix = dir.0.join("compat/app1/pfx");
+        assert!(prefix.is_dir());
+        assert!(log.contains("args Game.exe -windowed\n"), "{}", log);
+        assert!(
+            log.contains(&format!("WINEPREFIX={}\n", prefix.display())),
+            "{}",
+            log
+        );
+
+        // Another game does not share the prefix
+        let log = run(&dir, &tool, "app2", "Other.exe");
+        assert!(log.contains("compat/app2/pfx"), "{}", log);
+    }
+
+    #[test]
+    fn proton_release_folder_runs_its_script() {
+        let dir = TempDir::new("compat_tools", "proton");
+        add_runner(&dir, "Proton 9.0/proton");
+        let tool = CompatTool {
+            kind: CompatToolKind::Proton,
+            path: dir.0.join("Proton 9.0").to_string_lossy().to_string(),
+        };
+
+        let log = run(&dir, &tool, "app1", "Game.exe");
+        assert!(
+            log.contains("args waitforexitandrun Game.exe -windowed\n"),
+            "{}",
+            log
+        );
+        assert!(
+            log.contains(&format!(
+                "STEAM_COMPAT_DATA_PATH={}\n",
+                dir.0.join("compat/app1").display()
+            )),
+            "{}",
+            log
+        );
+    }
+
+    #[test]
+    fn missing_tool_and_unsafe_app_id_fail() {
+        let dir = TempDir::new("compat_tools", "missing");
+        let root = dir.0.join("compat");
+        let tool = CompatTool {
+            kind: CompatToolKind::Wine,
+            path: "wine".to_string(),
+        };
+
+        let prefix = prepare_prefix(&root, "app1").unwrap();
+        let command = vec![OsString::from("Game.exe")];
+        assert!(compat_command(&tool, &root, &prefix, command, Some(dir.0.as_os_str())).is_err());
+        assert!(prepare_prefix(&root, "../app1").is_err());
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::process_manager::{validate_app_id, AppExited, RunningApp};
+
+pub const GAME_CRASHED_EVENT: &str = "game_crashed";
+
+// A clean exit this soon after the start still means the game failed to start
+const SHORT_SESSION_SECONDS: u64 = 10;
+const LOG_LINES: usize = 200;
+const LOG_TAIL_BYTES: u64 = 256 * 1024;
+// Bigger dumps stay where the game wrote them, the report lists their paths
+const MAX_DUMP_SIZE: u64 = 100 * 1024 * 1024;
+const DUMP_SEARCH_DEPTH: usize = 3;
+// Reports kept per game, dumps make them large
+const KEEP_REPORTS: usize = 5;
+const SUBMIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CrashReason {
+    ExitCode {
+        code: i32,
+    },
+    Signal {
+        signal: i32,
+        name: Option<&'static str>,
+    },
+    ShortSession {
+        seconds: u64,
+    },
+}
+
+impl CrashReason {
+    fn kind(&self) -> &'static str {
+        match self {
+            CrashReason::ExitCode { .. } => "exit_code",
+            CrashReason::Signal { .. } => "signal",
+            CrashReason::ShortSession { .. } => "short_session",
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            CrashReason::ExitCode { code } => format!("exited with code {}", code),
+            CrashReason::Signal { signal, name } => match name {
+                Some(name) => format!("killed by {}", name),
+                None => format!("killed by signal {}", signal),
+            },
+            CrashReason::ShortSession { seconds } => format!("exited after {}s", seconds),
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct GameCrashed {
+    app_id: String,
+    pid: u32,
+    build_id: Option<String>,
+    reason: CrashReason,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    started_at: u64,
+    ended_at: u64,
+    // Zip with the report, none when it could not be written
+    report: Option<String>,
+    // Sent to the publisher of the game
+    submitted: bool,
+}
+
+#[derive(Serialize)]
+struct CrashReport<'a> {
+    app_id: &'a str,
+    build_id: Option<&'a str>,
+    pid: u32,
+    reason: &'a CrashReason,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    started_at: u64,
+    ended_at: u64,
+    os: String,
+    arch: &'static str,
+    launcher_version: &'static str,
+    log_file: Option<&'a str>,
+    // Every dump the game wrote during the session, bundled when small enough
+    dumps: Vec<String>,
+}
+
+#[cfg(unix)]
+fn signal_name(signal: i32) -> Option<&'static str> {
+    nix::sys::signal::Signal::try_from(signal)
+        .ok()
+        .map(|s| s.as_str())
+}
+
+#[cfg(not(unix))]
+fn signal_name(_signal: i32) -> Option<&'static str> {
+    None
+}
+
+/// Signals a process gets for its own faults rather than from outside
+fn is_fault_signal(signal: i32) -> bool {
+    matches!(
+        signal_name(signal),
+        Some("SIGSEGV" | "SIGABRT" | "SIGBUS" | "SIGFPE" | "SIGILL" | "SIGSYS" | "SIGTRAP")
+    )
+}
+
+/// Why the exit counts as a crash, none for a normal one. A game stopped from
+/// the launcher is expected to exit any way, unless it faulted while doing so.
+pub fn classify_exit(exited: &AppExited, stopped: bool) -> Option<CrashReason> {
+    if let Some(signal) = exited.signal {
+        if !stopped || is_fault_signal(signal) {
+            return Some(CrashReason::Signal {
+                signal,
+                name: signal_name(signal),
+            });
+        }
+    }
+    if stopped {
+        return None;
+    }
+
+    match exited.exit_code {
+        Some(0) => {}
+        Some(code) => return Some(CrashReason::ExitCode { code }),
+        // The exit could not be read
+        None => return None,
+    }
+
+    let seconds = exited.ended_at.saturating_sub(exited.started_at);
+    (seconds < SHORT_SESSION_SECONDS).then_some(CrashReason::ShortSession { seconds })
+}
+
+fn is_minidump(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("dmp") || ext.eq_ignore_ascii_case("mdmp"))
+}
+
+fn is_dump(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let is_core = name == "core"
+        || name
+            .strip_prefix("core.")
+            .is_some_and(|pid| pid.chars().all(|c| c.is_ascii_digit()));
+    is_minidump(path) || is_core
+}
+
+/// Minidumps and core files in `dir` written since `since` (seconds since the epoch)
+fn find_dumps(dir: &Path, since: u64, depth: usize, dumps: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        if file_type.is_dir() && depth > 0 {
+            find_dumps(&path, since, depth - 1, dumps);
+        } else if file_type.is_file() && is_dump(&path) {
+            let modified = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs());
+            if modified.is_some_and(|modified| modified >= since) {
+                dumps.push(path);
+            }
+        }
+    }
+}
+
+fn dump_size(path: &Path) -> u64 {
+    path.metadata().map(|m| m.len()).unwrap_or(u64::MAX)
+}
+
+/// Last lines the game wrote, the log header included when it is that short
+fn recent_log_lines(log_file: &Path) -> Result<String, String> {
+    let mut file = File::open(log_file).map_err(|e| format!("Failed to open game log: {}", e))?;
+    let len = file
+        .metadata()
+        .map_err(|e| format!("Failed to read game log: {}", e))?
+        .len();
+    let start = len.saturating_sub(LOG_TAIL_BYTES);
+    file.seek(SeekFrom::Start(start))
+        .map_err(|e| format!("Failed to read game log: {}", e))?;
+
+    let mut data = vec![];
+    file.read_to_end(&mut data)
+        .map_err(|e| format!("Failed to read game log: {}", e))?;
+    let text = String::from_utf8_lossy(&data);
+
+    let mut lines: Vec<&str> = text.lines().collect();
+    // Cut in the middle
+    if start > 0 && !lines.is_empty() {
+        lines.remove(0);
+    }
+    let skip = lines.len().saturating_sub(LOG_LINES);
+    Ok(lines[skip..].join("\n"))
+}
+
+fn reports_dir(app: &AppHandle, app_id: &str) -> Result<PathBuf, String> {
+    validate_app_id(app_id)?;
+    let app_log_dir = app
+        .path()
+        .app_log_dir()
+        .map_err(|e| format!("Failed to get app log dir: {}", e))?;
+    Ok(app_log_dir.join("crashes").join(app_id))
+}
+
+/// Deletes the oldest reports past `KEEP_REPORTS`
+fn rotate_reports(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    let mut reports: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    reports.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    for (_, path) in reports.into_iter().skip(KEEP_REPORTS) {
+        if let Err(e) = std::fs::remove_file(&path) {
+            eprintln!(
+                "Failed to remove old crash report {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+fn write_bundle(
+    path: &Path,
+    report: &CrashReport,
+    log_lines: &str,
+    dumps: &[PathBuf],
+) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create crash report: {}", e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .large_file(true);
+
+    let report = serde_json::to_vec_pretty(report)
+        .map_err(|e| format!("Failed to serialize crash report: {}", e))?;
+    let files: [(&str, &[u8]); 2] = [("report.json", &report), ("log.txt", log_lines.as_bytes())];
+    for (name, data) in files {
+        zip.start_file(name, options)
+            .map_err(|e| format!("Failed to write crash report: {}", e))?;
+        zip.write_all(data)
+            .map_err(|e| format!("Failed to write crash report: {}", e))?;
+    }
+
+    for dump in dumps {
+        let Some(name) = dump.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let mut source = File::open(dump).map_err(|e| format!("Failed to open dump: {}", e))?;
+        zip.start_file(format!("dumps/{}", name), options)
+            .map_err(|e| format!("Failed to write crash report: {}", e))?;
+        std::io::copy(&mut source, &mut zip)
+            .map_err(|e| format!("Failed to write crash report: {}", e))?;
+    }
+
+    zip.finish()
+        .map_err(|e| format!("Failed to finalize crash report: {}", e))?;
+    Ok(())
+}
+
+/// Sends the report to the publisher's Sentry or GlitchTip project. A client
+/// of its own keeps game crashes out of the launcher's project.
+fn submit(
+    dsn: &str,
+    report: &CrashReport,
+    log_lines: &str,
+    dumps: &[PathBuf],
+) -> Result<(), String> {
+    let dsn: sentry::types::Dsn = dsn
+        .parse()
+        .map_err(|e| format!("Invalid crash report DSN: {}", e))?;
+    // Without the defaults the client has no transport and drops every event.
+    // Integrations stay off, the panic handler belongs to the launcher's client.
+    let client = Arc::new(sentry::Client::from(sentry::apply_defaults(
+        sentry::ClientOptions {
+            dsn: Some(dsn),
+            release: report.build_id.map(|id| id.to_string().into()),
+            default_integrations: false,
+            ..Default::default()
+        },
+    )));
+    let hub = sentry::Hub::new(Some(client.clone()), Arc::new(sentry::Scope::default()));
+
+    let mut attachments = vec![sentry::protocol::Attachment {
+        buffer: log_lines.as_bytes().to_vec(),
+        filename: "log.txt".to_string(),
+        content_type: Some("text/plain".to_string()),
+        ty: None,
+    }];
+    // Core files are not understood by Sentry
+    for dump in dumps.iter().filter(|dump| is_minidump(dump)) {
+        let buffer = std::fs::read(dump).map_err(|e| format!("Failed to read dump: {}", e))?;
+        attachments.push(sentry::protocol::Attachment {
+            buffer,
+            filename: dump
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            content_type: None,
+            ty: Some(sentry::protocol::AttachmentType::Minidump),
+        });
+    }
+    hub.configure_scope(|scope| {
+        for attachment in attachments {
+            scope.add_attachment(attachment);
+        }
+    });
+
+    let tags = BTreeMap::from([
+        ("app_id".to_string(), report.app_id.to_string()),
+        ("os".to_string(), report.os.clone()),
+        ("arch".to_string(), report.arch.to_string()),
+        ("crash_reason".to_string(), report.reason.kind().to_string()),
+        (
+            "launcher_version".to_string(),
+            report.launcher_version.to_string(),
+        ),
+    ]);
+    let extra = BTreeMap::from([
+        ("pid".to_string(), report.pid.into()),
+        ("exit_code".to_string(), report.exit_code.into()),
+        ("signal".to_string(), report.signal.into()),
+        (
+            "duration_seconds".to_string(),
+            report.ended_at.saturating_sub(report.started_at).into(),
+        ),
+    ]);
+    hub.capture_event(sentry::protocol::Event {
+        level: sentry::Level::Fatal,
+        message: Some(format!("Game {}", report.reason.describe())),
+        tags,
+        extra,
+        ..Default::default()
+    });
+
+    if !client.flush(Some(SUBMIT_TIMEOUT)) {
+        return Err("Timed out sending the crash report".to_string());
+    }
+    Ok(())
+}
+
+/// Bundles what is known about the crash into a zip in the log dir, sends it
+/// to `dsn` when the game has one and tells the frontend with `game_crashed`
+pub async fn report_crash(
+    app: AppHandle,
+    info: RunningApp,
+    exited: AppExited,
+    reason: CrashReason,
+    dsn: Option<String>,
+) {
+    let dir = reports_dir(&app, &info.app_id);
+    let collected = tauri::async_runtime::spawn_blocking({
+        let (info, exited, reason) = (info.clone(), exited.clone(), reason.clone());
+        move || {
+            let log_lines = match &info.log_file {
+                Some(log_file) => recent_log_lines(Path::new(log_file)).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    String::new()
+                }),
+                None => String::new(),
+            };
+
+            let mut dumps = vec![];
+            find_dumps(
+                Path::new(&info.install_dir),
+                info.started_at,
+                DUMP_SEARCH_DEPTH,
+                &mut dumps,
+            );
+
+            let report = CrashReport {
+                app_id: &info.app_id,
+                build_id: info.build_id.as_deref(),
+                pid: info.pid,
+                reason: &reason,
+                exit_code: exited.exit_code,
+                signal: exited.signal,
+                started_at: info.started_at,
+                ended_at: exited.ended_at,
+                os: os_info::get().to_string(),
+                arch: std::env::consts::ARCH,
+                launcher_version: env!("CARGO_PKG_VERSION"),
+                log_file: info.log_file.as_deref(),
+                dumps: dumps
+                    .iter()
+                    .map(|d| d.to_string_lossy().to_string())
+                    .collect(),
+            };
+            dumps.retain(|dump| dump_size(dump) <= MAX_DUMP_SIZE);
+
+            let bundle = dir.and_then(|dir| {
+                std::fs::create_dir_all(&dir)
+                    .map_err(|e| format!("Failed to create crash report dir: {}", e))?;
+                let path = dir.join(format!("{}-{}.zip", exited.ended_at, info.pid));
+                write_bundle(&path, &report, &log_lines, &dumps)?;
+                rotate_reports(&dir);
+                Ok(path.to_string_lossy().to_string())
+            });
+            let bundle = bundle.map_err(|e| eprintln!("{}", e)).ok();
+
+            let submitted = match dsn.as_deref().filter(|dsn| !dsn.is_empty()) {
+                Some(dsn) => submit(dsn, &report, &log_lines, &dumps)
+                    .map_err(|e| eprintln!("Failed to submit crash of {}: {}", info.app_id, e))
+                    .is_ok(),
+                None => false,
+            };
+
+            (bundle, submitted)
+        }
+    })
+    .await;
+
+    let (report, submitted) = collected.unwrap_or_else(|e| {
+        eprintln!("Failed to collect crash report of {}: {}", info.app_id, e);
+        (None, false)
+    });
+    let crashed = GameCrashed {
+        app_id: info.app_id,
+        pid: info.pid,
+        build_id: info.build_id,
+        reason,
+        exit_code: exited.exit_code,
+        signal: exited.signal,
+        started_at: info.started_at,
+        ended_at: exited.ended_at,
+        report,
+        submitted,
+    };
+    if let Err(e) = app.emit(GAME_CRASHED_EVENT, crashed) {
+        eprintln!("Failed to emit game crash event: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exited(exit_code: Option<i32>, signal: Option<i32>, seconds: u64) -> AppExited {
+        AppExited {
+            app_id: "game".to_string(),
+            pid: 1,
+            exit_code,
+            signal,
+            started_at: 1000,
+            ended_at: 1000 + seconds,
+        }
+    }
+
+    fn kind(exited: &AppExited, stopped: bool) -> Option<&'static str> {
+        classify_exit(exited, stopped).map(|reason| reason.kind())
+    }
+
+    #[test]
+    fn classifies_exits_of_running_games() {
+        assert_eq!(kind(&exited(Some(0), None, 3600), false), None);
+        assert_eq!(
+            kind(&exited(Some(0), None, 2), false),
+            Some("short_session")
+        );
+        assert_eq!(kind(&exited(Some(3), None, 3600), false), Some("exit_code"));
+        assert_eq!(kind(&exited(None, None, 2), false), None);
+        // Killed from outside the launcher
+        assert_eq!(kind(&exited(None, Some(9), 3600), false), Some("signal"));
+    }
+
+    #[test]
+    fn games_stopped_from_the_launcher_only_crash_on_faults() {
+        assert_eq!(kind(&exited(Some(1), None, 2), true), None);
+        assert_eq!(kind(&exited(None, Some(15), 3600), true), None);
+        #[cfg(unix)]
+        assert_eq!(
+            kind(&exited(None, Some(nix::libc::SIGSEGV), 3600), true),
+            Some("signal")
+        );
+    }
+}
@@ -0,0 +1,813 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use filetime::FileTime;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tar::{Builder, Header};
+use tokio_util::io::{StreamReader, SyncIoBridge};
+
+use crate::http_client::HttpClient;
+use crate::launch_profiles::validate_build_profiles;
+use crate::manifest::{local_path, Manifest, ManifestEntry, MANIFEST_FILE_NAME};
+use crate::pack::{hash_folder, validate_source_folder};
+use crate::process_manager::{GameRunning, ProcessManager};
+use crate::rate_meter::RateMeter;
+use crate::repair::{sibling_path, stage_verified, ProgressFn};
+use crate::verify::verify_files;
+use crate::ProgressCallbackData;
+
+pub const PATCH_VERSION: u32 = 1;
+
+const PATCH_HEADER_NAME: &str = "patch.json";
+const COMPRESSION_LEVEL: i32 = 9;
+// Diffs keep the old file in memory on both sides, bigger files are added whole
+const MAX_DIFF_SIZE: u64 = 128 * 1024 * 1024;
+// Window covering a reference prefix and new data of up to MAX_DIFF_SIZE
+const MAX_DIFF_WINDOW_LOG: u32 = 28;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PatchOperation {
+    // Whole new file, zstd compressed
+    Add { path: String },
+    // Changed file, zstd compressed with the old file as reference prefix
+    Diff { path: String },
+    Remove { path: String },
+}
+
+/// First entry of a patch. Data entries follow in the order of `operations`,
+/// one for every add and diff.
+#[derive(Serialize, Deserialize)]
+pub struct PatchHeader {
+    pub version: u32,
+    // `Manifest::content_hash` of the build the patch applies to
+    pub base: String,
+    pub target: Manifest,
+    pub operations: Vec<PatchOperation>,
+}
+
+#[derive(Serialize)]
+pub struct PatchSummary {
+    patch_path: String,
+    patch_size: u64,
+    added: usize,
+    changed: usize,
+    removed: usize,
+}
+
+#[derive(Serialize)]
+pub struct DeltaUpdateError {
+    message: String,
+    // The installed files are not the base build, a full install is needed
+    base_mismatch: bool,
+    // Set when a game runs from the install dir
+    game_running: Option<GameRunning>,
+}
+
+impl DeltaUpdateError {
+    fn failed(message: String) -> Self {
+        Self {
+            message,
+            base_mismatch: false,
+            game_running: None,
+        }
+    }
+
+    fn base_mismatch(message: String) -> Self {
+        Self {
+            message,
+            base_mismatch: true,
+            game_running: None,
+        }
+    }
+
+    fn game_running(error: GameRunning) -> Self {
+        Self {
+            message: error.to_string(),
+            base_mismatch: false,
+            game_running: Some(error),
+        }
+    }
+}
+
+/// zstd window big enough for the reference prefix and the new data
+fn diff_window_log(base_size: u64, target_size: u64) -> u32 {
+    let size = base_size.max(target_size).max(1);
+    (64 - (size - 1).leading_zeros()).clamp(10, MAX_DIFF_WINDOW_LOG - 1) + 1
+}
+
+fn compress_file(
+    path: &Path,
+    base: Option<&[u8]>,
+    target_size: u64,
+    output: &Path,
+) -> Result<(), String> {
+    let mut input =
+        File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let output = File::create(output).map_err(|e| format!("Failed to create patch data: {}", e))?;
+
+    let mut encoder = match base {
+        Some(base) => {
+            let mut encoder =
+                zstd::stream::write::Encoder::with_ref_prefix(output, COMPRESSION_LEVEL, base)
+                    .map_err(|e| format!("Failed to create diff encoder: {}", e))?;
+            encoder
+                .window_log(diff_window_log(base.len() as u64, target_size))
+                .and_then(|_| encoder.long_distance_matching(true))
+                .map_err(|e| format!("Failed to configure diff encoder: {}", e))?;
+            encoder
+        }
+        None => zstd::stream::write::Encoder::new(output, COMPRESSION_LEVEL)
+            .map_err(|e| format!("Failed to create encoder: {}", e))?,
+    };
+
+    std::io::copy(&mut input, &mut encoder)
+        .map_err(|e| format!("Failed to compress {}: {}", path.display(), e))?;
+    encoder
+        .finish()
+        .map_err(|e| format!("Failed to finalize compression: {}", e))?;
+
+    Ok(())
+}
+
+fn append_blob<W: Write>(
+    tar_builder: &mut Builder<W>,
+    index: usize,
+    blob_path: &Path,
+) -> Result<(), String> {
+    let mut blob =
+        File::open(blob_path).map_err(|e| format!("Failed to open patch data: {}", e))?;
+    let size = blob
+        .metadata()
+        .map_err(|e| format!("Failed to get file metadata: {}", e))?
+        .len();
+
+    let mut header = Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(0o644);
+    tar_builder
+        .append_data(&mut header, format!("data/{}", index), &mut blob)
+        .map_err(|e| format!("Failed to add patch data: {}", e))
+}
+
+/// Writes a patch that turns the build in `base_folder` into the one in
+/// `target_folder`
+pub fn create_patch(
+    base_folder: &Path,
+    target_folder: &Path,
+    output_path: &Path,
+) -> Result<PatchSummary, String> {
+    let base = hash_folder(base_folder)?;
+    let target = hash_folder(target_folder)?;
+
+    let base_files: HashMap<&str, &ManifestEntry> =
+        base.files.iter().map(|f| (f.path.as_str(), f)).collect();
+    let target_paths: HashMap<&str, &ManifestEntry> =
+        target.files.iter().map(|f| (f.path.as_str(), f)).collect();
+
+    let mut operations = vec![];
+    let (mut added, mut changed, mut removed) = (0, 0, 0);
+
+    for file in &target.files {
+        match base_files.get(file.path.as_str()) {
+            Some(old) if old.hash == file.hash => {}
+            Some(old) if old.size <= MAX_DIFF_SIZE && file.size <= MAX_DIFF_SIZE => {
+                operations.push(PatchOperation::Diff {
+                    path: file.path.clone(),
+                });
+                changed += 1;
+            }
+            _ => {
+                operations.push(PatchOperation::Add {
+                    path: file.path.clone(),
+                });
+                added += 1;
+            }
+        }
+    }
+    for file in &base.files {
+        if !target_paths.contains_key(file.path.as_str()) {
+            operations.push(PatchOperation::Remove {
+                path: file.path.clone(),
+            });
+            removed += 1;
+        }
+    }
+
+    let header = PatchHeader {
+        version: PATCH_VERSION,
+        base: base.content_hash(),
+        target,
+        operations,
+    };
+    let header_json =
+        serde_json::to_vec(&header).map_err(|e| format!("Failed to serialize patch: {}", e))?;
+
+    let output =
+        File::create(output_path).map_err(|e| format!("Failed to create patch file: {}", e))?;
+    let mut tar_builder = Builder::new(BufWriter::new(output));
+
+    let mut tar_header = Header::new_gnu();
+    tar_header.set_size(header_json.len() as u64);
+    tar_header.set_mode(0o644);
+    tar_builder
+        .append_data(&mut tar_header, PATCH_HEADER_NAME, header_json.as_slice())
+        .map_err(|e| format!("Failed to add patch header: {}", e))?;
+
+    // Every blob is compressed into a scratch file first, tar needs the size up front
+    let blob_path = output_path.with_extension("blob.tmp");
+    let result = header
+        .operations
+        .iter()
+        .filter_map(|op| match op {
+            PatchOperation::Add { path } => Some((path, false)),
+            PatchOperation::Diff { path } => Some((path, true)),
+            PatchOperation::Remove { .. } => None,
+        })
+        .enumerate()
+        .try_for_each(|(index, (path, is_diff))| {
+            let source = local_path(target_folder, path)?;
+            let size = header.target.get(path).map(|f| f.size).unwrap_or_default();
+
+            let base_data = if is_diff {
+                let base_path = local_path(base_folder, path)?;
+                Some(
+                    std::fs::read(&base_path)
+                        .map_err(|e| format!("Failed to read {}: {}", base_path.display(), e))?,
+                )
+            } else {
+                None
+            };
+
+            compress_file(&source, base_data.as_deref(), size, &blob_path)?;
+            append_blob(&mut tar_builder, index, &blob_path)
+        });
+    let _ = std::fs::remove_file(&blob_path);
+    result?;
+
+    tar_builder
+        .into_inner()
+        .map_err(|e| format!("Failed to finalize patch: {}", e))?
+        .flush()
+        .map_err(|e| format!("Failed to write patch file: {}", e))?;
+
+    let patch_size = std::fs::metadata(output_path)
+        .map_err(|e| format!("Failed to get file metadata: {}", e))?
+        .len();
+
+    Ok(PatchSummary {
+        patch_path: output_path.to_string_lossy().to_string(),
+        patch_size,
+        added,
+        changed,
+        removed,
+    })
+}
+
+/// Decodes one file of the patch next to its target and checks it against
+/// the target manifest. Returns the temporary path to rename later.
+fn stage_file(
+    install_dir: &Path,
+    entry: &ManifestEntry,
+    data: impl Read,
+    is_diff: bool,
+) -> Result<PathBuf, DeltaUpdateError> {
+    let base = if is_diff {
+        let read_base = || {
+            let target = local_path(install_dir, &entry.path).map_err(std::io::Error::other)?;
+            let size = std::fs::metadata(&target)?.len();
+            if size > MAX_DIFF_SIZE {
+                return Err(std::io::Error::other(
+                    "file is too large to be a patch base",
+                ));
+            }
+            std::fs::read(&target)
+        };
+        Some(read_base().map_err(|e| {
+            DeltaUpdateError::base_mismatch(format!("Failed to read {}: {}", entry.path, e))
+        })?)
+    } else {
+        None
+    };
+
+    let decoder: Box<dyn Read + '_> = match &base {
+        Some(base) => {
+            let mut decoder =
+                zstd::stream::read::Decoder::with_ref_prefix(BufReader::new(data), base.as_slice())
+                    .map_err(|e| {
+                        DeltaUpdateError::failed(format!("Failed to create diff decoder: {}", e))
+                    })?;
+            decoder.window_log_max(MAX_DIFF_WINDOW_LOG).map_err(|e| {
+                DeltaUpdateError::failed(format!("Failed to configure diff decoder: {}", e))
+            })?;
+            Box::new(decoder)
+        }
+        None => {
+            Box::new(zstd::stream::read::Decoder::new(data).map_err(|e| {
+                DeltaUpdateError::failed(format!("Failed to create decoder: {}", e))
+            })?)
+        }
+    };
+
+    let on_write: ProgressFn = Arc::new(|_| {});
+    stage_verified(install_dir, entry, decoder, &on_write).map_err(|message| {
+        // A diff against a damaged base file cannot produce the target
+        if is_diff {
+            DeltaUpdateError::base_mismatch(message)
+        } else {
+            DeltaUpdateError::failed(message)
+        }
+    })
+}
+
+/// Sets the modification time and mode of the target manifest. The time is
+/// set by path before the mode, files may already be or become read-only.
+fn apply_metadata(path: &Path, entry: &ManifestEntry) -> std::io::Result<()> {
+    if let Some(mtime) = entry.mtime {
+        filetime::set_file_mtime(path, FileTime::from_unix_time(mtime as i64, 0))?;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(entry.mode))?;
+    }
+
+    Ok(())
+}
+
+/// Journal of files being committed into an installation. Replaced and
+/// removed files and folders are moved aside until everything is in place,
+/// so a failure can put them back.
+#[derive(Default)]
+pub(crate) struct Rollback {
+    // Target and where its old file or folder was moved
+    moved: Vec<(PathBuf, PathBuf)>,
+    // Targets that had no old file
+    created: Vec<PathBuf>,
+    // Old modification time and permissions of files that only get new metadata
+    metadata: Vec<(PathBuf, FileTime, std::fs::Permissions)>,
+}
+
+impl Rollback {
+    /// Moves the old file at `target` aside and `source`, if any, in its place
+    pub(crate) fn replace(&mut self, source: Option<&Path>, target: &Path) -> std::io::Result<()> {
+        match std::fs::symlink_metadata(target) {
+            Ok(_) => {
+                let backup = sibling_path(target, "orig");
+                std::fs::rename(target, &backup)?;
+                self.moved.push((target.to_path_buf(), backup));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                if source.is_some() {
+                    self.created.push(target.to_path_buf());
+                }
+            }
+            Err(e) => return Err(e),
+        }
+
+        match source {
+            Some(source) => std::fs::rename(source, target),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn set_metadata(
+        &mut self,
+        path: &Path,
+        entry: &ManifestEntry,
+    ) -> std::io::Result<()> {
+        let old = std::fs::metadata(path)?;
+        self.metadata.push((
+            path.to_path_buf(),
+            FileTime::from_last_modification_time(&old),
+            old.permissions(),
+        ));
+        apply_metadata(path, entry)
+    }
+
+    /// Puts the old files back as far as possible
+    pub(crate) fn restore(self) {
+        for (path, mtime, permissions) in self.metadata.into_iter().rev() {
+            let _ = filetime::set_file_mtime(&path, mtime);
+            let _ = std::fs::set_permissions(&path, permissions);
+        }
+        for target in self.created {
+            let _ = remove_path(&target);
+        }
+        for (target, backup) in self.moved.into_iter().rev() {
+            let _ = std::fs::rename(backup, target);
+        }
+    }
+
+    pub(crate) fn finish(self) {
+        for (_, backup) in self.moved {
+            let _ = remove_path(&backup);
+        }
+    }
+}
+
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+/// Moves the staged files in, removes the dropped ones and writes the target
+/// manifest, recording every change in `rollback`
+fn commit_patch(
+    install_dir: &Path,
+    header: &PatchHeader,
+    staged: &[(PathBuf, &ManifestEntry)],
+    rollback: &mut Rollback,
+) -> Result<(), String> {
+    for (temp_path, entry) in staged {
+        let target = local_path(install_dir, &entry.path)?;
+        rollback
+            .replace(Some(temp_path.as_path()), &target)
+            .map_err(|e| format!("{}: {}", entry.path, e))?;
+        apply_metadata(&target, entry).map_err(|e| format!("{}: {}", entry.path, e))?;
+    }
+
+    for operation in &header.operations {
+        if let PatchOperation::Remove { path } = operation {
+            let target = local_path(install_dir, path)?;
+            rollback
+                .replace(None, &target)
+                .map_err(|e| format!("{}: {}", path, e))?;
+        }
+    }
+
+    // Unchanged files keep their content but take the new mode and mtime
+    let staged_paths: HashSet<&str> = staged.iter().map(|(_, e)| e.path.as_str()).collect();
+    for entry in &header.target.files {
+        if staged_paths.contains(entry.path.as_str()) {
+            continue;
+        }
+        let target = local_path(install_dir, &entry.path)?;
+        rollback
+            .set_metadata(&target, entry)
+            .map_err(|e| format!("{}: {}", entry.path, e))?;
+    }
+
+    let manifest_path = install_dir.join(MANIFEST_FILE_NAME);
+    let temp_manifest = sibling_path(&manifest_path, "patch");
+    std::fs::write(&temp_manifest, header.target.to_json()?)
+        .and_then(|_| rollback.replace(Some(temp_manifest.as_path()), &manifest_path))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&temp_manifest);
+            format!("{}: {}", MANIFEST_FILE_NAME, e)
+        })
+}
+
+/// Applies a patch read from `reader` to the installation. Every changed
+/// file is decoded and verified and the untouched ones are checked before
+/// the first one replaces its target. The old files are kept until the
+/// patch is in place, so a failed patch leaves the installation as it was.
+fn apply_patch(install_dir: &Path, reader: impl Read) -> Result<(), DeltaUpdateError> {
+    let read_error =
+        |e: std::io::Error| DeltaUpdateError::failed(format!("Failed to read patch: {}", e));
+
+    let installed = Manifest::load(&install_dir.join(MANIFEST_FILE_NAME))
+        .map_err(DeltaUpdateError::base_mismatch)?;
+
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = archive.entries().map_err(read_error)?;
+
+    let mut header_entry = entries
+        .next()
+        .ok_or_else(|| DeltaUpdateError::failed("Patch is empty".to_string()))?
+        .map_err(read_error)?;
+    let mut header_json = vec![];
+    header_entry
+        .read_to_end(&mut header_json)
+        .map_err(read_error)?;
+    drop(header_entry);
+
+    let header: PatchHeader = serde_json::from_slice(&header_json)
+        .map_err(|e| DeltaUpdateError::failed(format!("Failed to parse patch: {}", e)))?;
+    if header.version > PATCH_VERSION {
+        return Err(DeltaUpdateError::failed(format!(
+            "Unsupported patch version {}, update the launcher",
+            header.version
+        )));
+    }
+    if header.base != installed.content_hash() {
+        return Err(DeltaUpdateError::base_mismatch(
+            "Installed build does not match the patch base".to_string(),
+        ));
+    }
+
+    let mut staged = vec![];
+    let result = (|| {
+        for operation in &header.operations {
+            let (path, is_diff) = match operation {
+                PatchOperation::Add { path } => (path, false),
+                PatchOperation::Diff { path } => (path, true),
+                PatchOperation::Remove { .. } => continue,
+            };
+
+            let entry = header.target.get(path).ok_or_else(|| {
+                DeltaUpdateError::failed(format!("{} is missing from the target manifest", path))
+            })?;
+            let data = entries
+                .next()
+                .ok_or_else(|| DeltaUpdateError::failed("Patch is truncated".to_string()))?
+                .map_err(read_error)?;
+
+            let temp_path = stage_file(install_dir, entry, data, is_diff)?;
+            staged.push((temp_path, entry));
+        }
+        Ok(())
+    })();
+
+    // Files the patch doesn't touch have to be the target ones already
+    let result = result.and_then(|_| {
+        let staged_paths: HashSet<&str> = staged.iter().map(|(_, e)| e.path.as_str()).collect();
+        let mut untouched = Manifest::new(None);
+        for entry in &header.target.files {
+            if !staged_paths.contains(entry.path.as_str()) {
+                untouched.push(entry.clone());
+            }
+        }
+
+        let report = verify_files(
+            install_dir,
+            &untouched,
+            false,
+            Duration::from_secs(1),
+            |_| {},
+        )
+        .map_err(DeltaUpdateError::failed)?;
+        match report.missing.iter().chain(&report.modified).next() {
+            Some(path) => Err(DeltaUpdateError::base_mismatch(format!(
+                "{} does not match the patch base",
+                path
+            ))),
+            None => Ok(()),
+        }
+    });
+
+    let mut rollback = Rollback::default();
+    let result = result.and_then(|_| {
+        commit_patch(install_dir, &header, &staged, &mut rollback)
+            .map_err(|e| DeltaUpdateError::failed(format!("Failed to apply patch: {}", e)))
+    });
+
+    match result {
+        Ok(()) => {
+            rollback.finish();
+            Ok(())
+        }
+        Err(e) => {
+            rollback.restore();
+            for (temp_path, _) in &staged {
+                let _ = std::fs::remove_file(temp_path);
+            }
+            Err(e)
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn create_delta_patch(
+    base_folder_path: String,
+    target_folder_path: String,
+    output_path: Option<String>,
+) -> Result<PatchSummary, String> {
+    let base_folder = validate_source_folder(&base_folder_path)?.to_path_buf();
+    let target_folder = validate_source_folder(&target_folder_path)?.to_path_buf();
+    validate_build_profiles(&target_folder)?;
+
+    // Next to the new build folder by default, like packed archives
+    let output_path = match output_path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let folder_name = target_folder
+                .file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| "Invalid folder name".to_string())?;
+            let parent_dir = target_folder
+                .parent()
+                .ok_or_else(|| "Cannot get parent directory".to_string())?;
+            parent_dir.join(format!("{}.patch.tar", folder_name))
+        }
+    };
+
+    tokio::task::spawn_blocking(move || create_patch(&base_folder, &target_folder, &output_path))
+        .await
+        .map_err(|e| format!("Patch task failed: {}", e))?
+}
+
+/// Downloads a patch and applies it to the installation on the fly.
+/// When `base_mismatch` is set in the error the caller should do a full install.
+#[tauri::command]
+pub async fn apply_delta_update(
+    http: tauri::State<'_, HttpClient>,
+    manager: tauri::State<'_, ProcessManager>,
+    url: String,
+    install_dir: String,
+    progress_channel: tauri::ipc::Channel<ProgressCallbackData>,
+    speed_update_interval: Option<f64>,
+) -> Result<(), DeltaUpdateError> {
+    let install_dir = PathBuf::from(install_dir);
+    let _reservation = manager
+        .reserve_install(&install_dir)
+        .map_err(DeltaUpdateError::game_running)?;
+
+    let response = http
+        .send(http.client().get(&url))
+        .await
+        .map_err(|e| DeltaUpdateError::failed(format!("Failed to send request: {}", e)))?;
+    if !response.status().is_success() {
+        return Err(DeltaUpdateError::failed(format!(
+            "Download failed with status {}",
+            response.status()
+        )));
+    }
+
+    let total_bytes = response.content_length().unwrap_or(0);
+    let received_bytes = Arc::new(AtomicU64::new(0));
+    let mut download_rate = RateMeter::new(Duration::from_secs_f64(
+        speed_update_interval.unwrap_or(1.0),
+    ));
+    let body = response.bytes_stream().map(move |chunk| {
+        let bytes = chunk.map_err(std::io::Error::other)?;
+        let delta = bytes.len() as u64;
+        let current_bytes = received_bytes.fetch_add(delta, Ordering::Relaxed) + delta;
+        download_rate.add_value(delta);
+
+        let res = progress_channel
+            .send(ProgressCallbackData {
+                current_bytes,
+                total_bytes: total_bytes.max(current_bytes),
+                delta_per_second: download_rate.get_rate() as u64,
+            })
+            .map_err(|e| format!("Failed to emit patch progress info: {}", e));
+        if let Err(e) = res {
+            eprintln!("{}", e);
+        }
+
+        Ok::<_, std::io::Error>(bytes)
+    });
+
+    let reader = SyncIoBridge::new(StreamReader::new(Box::pin(body)));
+    tokio::task::spawn_blocking(move || apply_patch(&install_dir, reader))
+        .await
+        .map_err(|e| DeltaUpdateError::failed(format!("Patch task failed: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use std::time::UNIX_EPOCH;
+
+    fn write_files(dir: &Path, files: &[(&str, &[u8])]) {
+        for (path, data) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        }
+    }
+
+    /// Base and target builds, the base installed with its manifest and a
+    /// patch between them
+    fn patch_fixture(name: &str) -> (TempDir, PathBuf, PathBuf) {
+        let root = TempDir::new("delta", name);
+        let (base, target, install) = (
+            root.0.join("base"),
+            root.0.join("target"),
+            root.0.join("install"),
+        );
+        let base_files: &[(&str, &[u8])] = &[
+            ("game", b"v1 binary"),
+            ("data/same", b"same"),
+            ("data/old", b"old"),
+        ];
+        write_files(&base, base_files);
+        write_files(&install, base_files);
+        write_files(
+            &target,
+            &[
+                ("game", b"v2 binary"),
+                ("data/same", b"same"),
+                ("data/new", b"new"),
+            ],
+        );
+        let manifest = hash_folder(&base).unwrap();
+        std::fs::write(
+            install.join(MANIFEST_FILE_NAME),
+            manifest.to_json().unwrap(),
+        )
+        .unwrap();
+
+        let patch = root.0.join("update.patch.tar");
+        create_patch(&base, &target, &patch).unwrap();
+        (root, install, patch)
+    }
+
+    #[test]
+    fn applies_a_patch_in_place() {
+        let (root, install, patch) = patch_fixture("apply");
+
+        apply_patch(&install, File::open(&patch).unwrap())
+            .map_err(|e| e.message)
+            .unwrap();
+
+        assert_eq!(
+            hash_folder(&install).unwrap().content_hash(),
+            hash_folder(&root.0.join("target")).unwrap().content_hash()
+        );
+    }
+
+    #[test]
+    fn untouched_files_have_to_match_the_target() {
+        let (_root, install, patch) = patch_fixture("untouched");
+        std::fs::write(install.join("data/same"), "damaged").unwrap();
+
+        let error = apply_patch(&install, File::open(&patch).unwrap())
+            .err()
+            .unwrap();
+
+        assert!(error.base_mismatch, "{}", error.message);
+        assert_eq!(std::fs::read(install.join("game")).unwrap(), b"v1 binary");
+        assert!(install.join("data/old").exists());
+        assert!(!install.join(".game.patch").exists());
+    }
+
+    #[test]
+    fn rollback_puts_old_files_back() {
+        let root = TempDir::new("delta", "rollback");
+        let dir = &root.0;
+        write_files(
+            dir,
+            &[
+                ("replaced", b"old"),
+                ("removed", b"old"),
+                (".replaced.patch", b"new"),
+                (".added.patch", b"new"),
+            ],
+        );
+
+        let mut rollback = Rollback::default();
+        rollback
+            .replace(
+                Some(dir.join(".replaced.patch").as_path()),
+                &dir.join("replaced"),
+            )
+            .unwrap();
+        rollback
+            .replace(Some(dir.join(".added.patch").as_path()), &dir.join("added"))
+            .unwrap();
+        rollback.replace(None, &dir.join("removed")).unwrap();
+        assert_eq!(std::fs::read(dir.join("replaced")).unwrap(), b"new");
+        assert!(!dir.join("removed").exists());
+
+        rollback.restore();
+
+        assert_eq!(std::fs::read(dir.join("replaced")).unwrap(), b"old");
+        assert_eq!(std::fs::read(dir.join("removed")).unwrap(), b"old");
+        assert!(!dir.join("added").exists());
+        assert!(!dir.join(".replaced.orig").exists());
+    }
+
+    #[test]
+    fn applies_metadata_to_read_only_files() {
+        let dir = TempDir::new("delta", "metadata");
+        let path = dir.0.join("game.dat");
+        std::fs::write(&path, "data").unwrap();
+
+        let mut entry = ManifestEntry {
+            path: "game.dat".to_string(),
+            size: 4,
+            mode: 0o444,
+            mtime: Some(1_700_000_000),
+            hash: String::new(),
+            chunks: vec![],
+        };
+        apply_metadata(&path, &entry).unwrap();
+        // The next update finds the file read-only
+        entry.mtime = Some(1_800_000_000);
+        apply_metadata(&path, &entry).unwrap();
+
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(
+            metadata.modified().unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_800_000_000)
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o444);
+        }
+    }
+}
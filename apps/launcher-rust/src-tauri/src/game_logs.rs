@@ -0,0 +1,500 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tauri::Manager;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::process::Command;
+
+use crate::process_manager::{validate_app_id, ProcessManager};
+use crate::process_tree::ProcessTree;
+
+// Sessions kept per game, older logs are deleted when a game starts
+const KEEP_SESSIONS: usize = 10;
+const DEFAULT_TAIL_BYTES: u64 = 64 * 1024;
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+// A game flooding its output keeps the newest part of it, across the log
+// and its rotated older half
+const MAX_LOG_BYTES: u64 = 16 * 1024 * 1024;
+// The header written by `create_session_log` is kept when a log is rotated
+const HEADER_LINES: usize = 2;
+// Argument the launcher binary is started with to run a log writer
+const LOG_WRITER_ARG: &str = "--game-log-writer";
+
+#[derive(Serialize)]
+pub struct GameLog {
+    file_name: String,
+    path: String,
+    size: u64,
+    // Milliseconds since the epoch, when the session started
+    started_at: u64,
+}
+
+#[derive(Serialize)]
+pub struct GameLogChunk {
+    // Position of the text in the file
+    offset: u64,
+    text: String,
+}
+
+fn game_logs_dir(app: &tauri::AppHandle, app_id: &str) -> Result<PathBuf, String> {
+    validate_app_id(app_id)?;
+    let app_log_dir = app
+        .path()
+        .app_log_dir()
+        .map_err(|e| format!("Failed to get app log dir: {}", e))?;
+    Ok(app_log_dir.join("games").join(app_id))
+}
+
+/// Logs of a game, newest first. Their names are the start time in milliseconds.
+fn list_logs(dir: &Path) -> Vec<GameLog> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut logs: Vec<GameLog> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_str()?.to_string();
+            let started_at = file_name.strip_suffix(".log")?.parse().ok()?;
+            Some(GameLog {
+                path: entry.path().to_string_lossy().to_string(),
+                size: entry.metadata().ok()?.len(),
+                file_name,
+                started_at,
+            })
+        })
+        .collect();
+    logs.sort_by_key(|log| std::cmp::Reverse(log.started_at));
+    logs
+}
+
+/// Creates the log of a new session and deletes the ones past `KEEP_SESSIONS`.
+/// The output of the game is added by the process `spawn_log_writer` starts.
+pub fn create_session_log(
+    app: &tauri::AppHandle,
+    app_id: &str,
+    command_line: &[OsString],
+) -> Result<PathBuf, String> {
+    let dir = game_logs_dir(app, app_id)?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create log dir: {}", e))?;
+
+    let started_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let path = dir.join(format!("{}.log", started_at));
+    let mut file = File::options()
+        .append(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to create game log: {}", e))?;
+
+    let command_line: Vec<_> = command_line
+        .iter()
+        .map(|arg| arg.to_string_lossy())
+        .collect();
+    writeln!(
+        file,
+        "# {} {} on {}, {}\n# {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        os_info::get(),
+        std::env::consts::ARCH,
+        command_line.join(" ")
+    )
+    .map_err(|e| format!("Failed to write game log: {}", e))?;
+
+    for old in list_logs(&dir).into_iter().skip(KEEP_SESSIONS) {
+        if let Err(e) = std::fs::remove_file(&old.path) {
+            eprintln!("Failed to remove old game log {}: {}", old.path, e);
+        }
+        let _ = std::fs::remove_file(rotated_log_path(Path::new(&old.path)));
+    }
+
+    Ok(path)
+}
+
+/// Where the older half of a rotated log is kept
+fn rotated_log_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".old");
+    PathBuf::from(rotated)
+}
+
+/// Moves the log aside, replacing its previous older half, and starts it
+/// again with the same header
+fn rotate_log(path: &Path) -> std::io::Result<File> {
+    let rotated = rotated_log_path(path);
+    std::fs::rename(path, &rotated)?;
+
+    let mut head = vec![0; READ_BUFFER_SIZE];
+    let read = File::open(&rotated)?.read(&mut head)?;
+    let header_len = head[..read]
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| **b == b'\n')
+        .nth(HEADER_LINES - 1)
+        .map_or(0, |(i, _)| i + 1);
+
+    let mut file = File::options().append(true).create_new(true).open(path)?;
+    file.write_all(&head[..header_len])?;
+    writeln!(
+        file,
+        "# Earlier output is in {}, older output was dropped",
+        rotated
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default()
+    )?;
+    Ok(file)
+}
+
+/// Appends `output` to the log until it is closed, rotating the log once it
+/// reaches half of `max_bytes`. Output is drained even when the log can't be
+/// written, so the game never blocks or fails on a full or closed pipe.
+fn write_log(mut output: impl Read, path: &Path, max_bytes: u64) {
+    let mut file = File::options()
+        .append(true)
+        .open(path)
+        .inspect_err(|e| eprintln!("Failed to open game log: {}", e))
+        .ok();
+    let mut len = file
+        .as_ref()
+        .and_then(|file| file.metadata().ok())
+        .map_or(0, |metadata| metadata.len());
+    let mut buf = vec![0; READ_BUFFER_SIZE];
+
+    loop {
+        let read = match output.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        if file.is_some() && len >= max_bytes / 2 {
+            file = rotate_log(path)
+                .inspect_err(|e| eprintln!("Failed to rotate game log: {}", e))
+                .ok();
+            len = file
+                .as_ref()
+                .and_then(|file| file.metadata().ok())
+                .map_or(0, |metadata| metadata.len());
+        }
+        let Some(log) = &mut file else {
+            continue;
+        };
+        if let Err(e) = log.write_all(&buf[..read]) {
+            eprintln!("Failed to write game log: {}", e);
+            file = None;
+            continue;
+        }
+        len += read as u64;
+    }
+}
+
+/// Starts the process that writes the output of a game to `path` and returns
+/// the stdout and stderr to give the game. The writer runs in its own session
+/// like the game, so both keep running when the launcher quits, and exits
+/// once every process holding the output has exited.
+pub fn spawn_log_writer(path: &Path) -> std::io::Result<(Stdio, Stdio)> {
+    let (reader, writer) = std::io::pipe()?;
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .arg(LOG_WRITER_ARG)
+        .arg(path)
+        .stdin(reader)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    ProcessTree::prepare(&mut command);
+    // Not waited for, the runtime reaps it once it exits
+    command.spawn()?;
+
+    let stderr = writer.try_clone()?;
+    Ok((writer.into(), stderr.into()))
+}
+
+/// Runs the log writer started by `spawn_log_writer` when these are its
+/// arguments. Returns false for any other command line.
+pub fn run_log_writer(mut args: impl Iterator<Item = OsString>) -> bool {
+    let (Some(arg), Some(path)) = (args.nth(1), args.next()) else {
+        return false;
+    };
+    if arg != LOG_WRITER_ARG {
+        return false;
+    }
+
+    write_log(std::io::stdin().lock(), Path::new(&path), MAX_LOG_BYTES);
+    true
+}
+
+/// Valid UTF-8 at the start of `buf`, an incomplete character at the end is
+/// left for the next read
+fn take_text(buf: &mut Vec<u8>) -> String {
+    let valid = match std::str::from_utf8(buf) {
+        Ok(_) => buf.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        // Not UTF-8 at all, shown with replacement characters
+        Err(_) => buf.len(),
+    };
+    let text = String::from_utf8_lossy(&buf[..valid]).to_string();
+    buf.drain(..valid);
+    text
+}
+
+#[tauri::command]
+pub fn list_game_logs(app: tauri::AppHandle, app_id: String) -> Result<Vec<GameLog>, String> {
+    Ok(list_logs(&game_logs_dir(&app, &app_id)?))
+}
+
+/// Sends the last `tail_bytes` of a log, the newest one by default. With
+/// `follow` it keeps sending what the game writes until the game exits.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub async fn read_game_log(
+    app: tauri::AppHandle,
+    manager: tauri::State<'_, ProcessManager>,
+    app_id: String,
+    file_name: Option<String>,
+    tail_bytes: Option<u64>,
+    follow: Option<bool>,
+    channel: tauri::ipc::Channel<GameLogChunk>,
+) -> Result<(), String> {
+    let dir = game_logs_dir(&app, &app_id)?;
+    let log = match file_name {
+        Some(name) => list_logs(&dir)
+            .into_iter()
+            .find(|log| log.file_name == name)
+            .ok_or_else(|| format!("Game log not found: {}", name))?,
+        None => list_logs(&dir)
+            .into_iter()
+            .next()
+            .ok_or_else(|| "The game has no logs yet".to_string())?,
+    };
+
+    let mut file = tokio::fs::File::open(&log.path)
+        .await
+        .map_err(|e| format!("Failed to open game log: {}", e))?;
+    let mut offset = log
+        .size
+        .saturating_sub(tail_bytes.unwrap_or(DEFAULT_TAIL_BYTES));
+    file.seek(std::io::SeekFrom::Start(offset))
+        .await
+        .map_err(|e| format!("Failed to read game log: {}", e))?;
+
+    let mut pending = vec![];
+    let mut buf = vec![0; READ_BUFFER_SIZE];
+    // The tail starts at a line, not in the middle of one
+    let mut skip_partial_line = offset > 0;
+    loop {
+        let read = file
+            .read(&mut buf)
+            .await
+            .map_err(|e| format!("Failed to read game log: {}", e))?;
+
+        if read == 0 {
+            if !follow.unwrap_or(false) || !manager.is_logging_to(Path::new(&log.path)) {
+                break;
+            }
+            tokio::time::sleep(FOLLOW_INTERVAL).await;
+
+            // The log was rotated, the new one is sent from its start
+            let len = tokio::fs::metadata(&log.path)
+                .await
+                .map_err(|e| format!("Failed to read game log: {}", e))?
+                .len();
+            if len < offset + pending.len() as u64 {
+                file = tokio::fs::File::open(&log.path)
+                    .await
+                    .map_err(|e| format!("Failed to open game log: {}", e))?;
+                offset = 0;
+                pending.clear();
+                skip_partial_line = false;
+            }
+            continue;
+        }
+
+        let mut data = &buf[..read];
+        if skip_partial_line {
+            let Some(newline) = data.iter().position(|b| *b == b'\n') else {
+                offset += read as u64;
+                continue;
+            };
+            offset += newline as u64 + 1;
+            data = &data[newline + 1..];
+            skip_partial_line = false;
+        }
+
+        pending.extend_from_slice(data);
+        let chunk_offset = offset;
+        let text = take_text(&mut pending);
+        offset += text.len() as u64;
+        if text.is_empty() {
+            continue;
+        }
+
+        // The reader is gone
+        if channel
+            .send(GameLogChunk {
+                offset: chunk_offset,
+                text,
+            })
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_export(destination: &Path, logs: &[GameLog]) -> Result<(), String> {
+    let file = File::create(destination).map_err(|e| format!("Failed to create export: {}", e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    let system = format!(
+        "{} {}\n{}\n{}\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        os_info::get(),
+        std::env::consts::ARCH
+    );
+    zip.start_file("system.txt", options)
+        .and_then(|_| zip.write_all(system.as_bytes()).map_err(Into::into))
+        .map_err(|e| format!("Failed to write export: {}", e))?;
+
+    for log in logs {
+        let path = Path::new(&log.path);
+        let rotated = rotated_log_path(path);
+        let parts = [
+            (format!("{}.old", log.file_name), rotated.as_path()),
+            (log.file_name.clone(), path),
+        ];
+        for (name, path) in parts {
+            // Only logs of long sessions have an older half
+            if name.ends_with(".old") && !path.exists() {
+                continue;
+            }
+            let mut source =
+                File::open(path).map_err(|e| format!("Failed to open game log: {}", e))?;
+            zip.start_file(name, options)
+                .map_err(|e| format!("Failed to write export: {}", e))?;
+            std::io::copy(&mut source, &mut zip)
+                .map_err(|e| format!("Failed to write export: {}", e))?;
+        }
+    }
+
+    zip.finish()
+        .map_err(|e| format!("Failed to finalize export: {}", e))?;
+    Ok(())
+}
+
+/// Zips every kept log of the game with a description of this machine, for
+/// attaching to a bug report. Written to the downloads folder by default.
+#[tauri::command]
+pub async fn export_game_logs(
+    app: tauri::AppHandle,
+    app_id: String,
+    destination: Option<String>,
+) -> Result<String, String> {
+    let logs = list_logs(&game_logs_dir(&app, &app_id)?);
+    if logs.is_empty() {
+        return Err("The game has no logs yet".to_string());
+    }
+
+    let destination = match destination {
+        Some(destination) => PathBuf::from(destination),
+        None => {
+            let download_dir = app
+                .path()
+                .download_dir()
+                .map_err(|e| format!("Failed to get downloads dir: {}", e))?;
+            download_dir.join(format!("{}-logs-{}.zip", app_id, logs[0].started_at))
+        }
+    };
+
+    let export_path = destination.clone();
+    tokio::task::spawn_blocking(move || write_export(&export_path, &logs))
+        .await
+        .map_err(|e| format!("Export task failed: {}", e))??;
+
+    Ok(destination.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    /// Output that arrives one line per read, like a game printing slowly
+    struct Lines<'a>(&'a [u8]);
+
+    impl Read for Lines<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let line = self
+                .0
+                .iter()
+                .position(|b| *b == b'\n')
+                .map_or(self.0.len(), |i| i + 1);
+            let read = line.min(buf.len());
+            buf[..read].copy_from_slice(&self.0[..read]);
+            self.0 = &self.0[read..];
+            Ok(read)
+        }
+    }
+
+    #[test]
+    fn rotates_long_logs_and_keeps_the_header() {
+        let dir = TempDir::new("game_logs", "rotate");
+        let path = dir.0.join("1.log");
+        std::fs::write(&path, "# launcher 1.0\n# ./game\n").unwrap();
+
+        let mut output = String::new();
+        for line in 0..1000 {
+            output.push_str(&format!("line {}\n", line));
+        }
+        write_log(Lines(output.as_bytes()), &path, 2000);
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let older = std::fs::read_to_string(rotated_log_path(&path)).unwrap();
+        assert!(log.len() + older.len() <= 2000 + 200, "{}", log.len());
+        assert!(log.starts_with("# launcher 1.0\n# ./game\n# Earlier output is in 1.log.old"));
+        assert!(older.starts_with("# launcher 1.0\n# ./game\n"));
+        assert!(log.ends_with("line 998\nline 999\n"));
+        // Nothing is lost between the halves
+        let last_older = older.lines().last().unwrap();
+        let first_newer = log.lines().nth(3).unwrap();
+        let number = |line: &str| line["line ".len()..].parse::<u32>().unwrap();
+        assert_eq!(number(last_older) + 1, number(first_newer));
+    }
+
+    #[test]
+    fn appends_the_output_to_the_log() {
+        let dir = TempDir::new("game_logs", "output");
+        let path = dir.0.join("1.log");
+        std::fs::write(&path, "# launcher 1.0\n# ./game\n").unwrap();
+
+        write_log(&b"stdout\nstderr\n"[..], &path, MAX_LOG_BYTES);
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(log, "# launcher 1.0\n# ./game\nstdout\nstderr\n");
+        assert!(!rotated_log_path(&path).exists());
+    }
+
+    #[test]
+    fn only_the_writer_arguments_run_the_writer() {
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+
+        assert!(!run_log_writer(args(&["launcher"]).into_iter()));
+        assert!(!run_log_writer(
+            args(&["launcher", "--other", "x"]).into_iter()
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+use std::io::Read;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use serde::Deserialize;
+use sha2::Digest;
+use tokio::io::{AsyncRead, ReadBuf};
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// Splits `<name>:<hex digest>` into the algorithm and the digest
+    pub fn parse_tagged(value: &str) -> Option<(Self, &str)> {
+        let (name, digest) = value.split_once(':')?;
+        let algorithm = match name.to_ascii_lowercase().as_str() {
+            "sha256" => HashAlgorithm::Sha256,
+            "blake3" => HashAlgorithm::Blake3,
+            _ => return None,
+        };
+        Some((algorithm, digest))
+    }
+}
+
+pub enum StreamingHasher {
+    Sha256(sha2::Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl StreamingHasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => StreamingHasher::Sha256(sha2::Sha256::new()),
+            HashAlgorithm::Blake3 => StreamingHasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    pub fn update(&mut self, buf: &[u8]) {
+        match self {
+            StreamingHasher::Sha256(hasher) => hasher.update(buf),
+            StreamingHasher::Blake3(hasher) => {
+                hasher.update(buf);
+            }
+        }
+    }
+
+    /// Returns the digest as `<name>:<lowercase hex>`
+    pub fn finalize(self) -> String {
+        match self {
+            StreamingHasher::Sha256(hasher) => format!("sha256:{:x}", hasher.finalize()),
+            StreamingHasher::Blake3(hasher) => format!("blake3:{}", hasher.finalize().to_hex()),
+        }
+    }
+}
+
+/// Slot the digest lands in once the reader reaches the end of its source
+pub type HashSlot = Arc<Mutex<Option<String>>>;
+
+/// Async reader that hashes everything that passes through it
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Option<StreamingHasher>,
+    slot: HashSlot,
+}
+
+impl<R: AsyncRead + Unpin> HashingReader<R> {
+    pub fn new(inner: R, algorithm: HashAlgorithm) -> (Self, HashSlot) {
+        let slot = HashSlot::default();
+        let reader = Self {
+            inner,
+            hasher: Some(StreamingHasher::new(algorithm)),
+            slot: slot.clone(),
+        };
+        (reader, slot)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for HashingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = result {
+            let filled = &buf.filled()[before..];
+            if filled.is_empty() {
+                if let Some(hasher) = self.hasher.take() {
+                    *self.slot.lock().unwrap() = Some(hasher.finalize());
+                }
+            } else if let Some(hasher) = self.hasher.as_mut() {
+                hasher.update(filled);
+            }
+        }
+
+        result
+    }
+}
+
+/// Hashes a file on disk, returns `<name>:<lowercase hex>`
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = StreamingHasher::new(algorithm);
+    let mut buf = vec![0; 256 * 1024];
+
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+
+    Ok(hasher.finalize())
+}
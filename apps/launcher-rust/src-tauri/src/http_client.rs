@@ -0,0 +1,275 @@
+use std::sync::RwLock;
+use std::time::Duration;
+
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+
+use crate::network_settings::NetworkSettings;
+
+pub struct HttpClientConfig {
+    pub connect_timeout: Duration,
+    // Applies to every read, so large transfers are not cut off as a whole
+    pub read_timeout: Duration,
+    pub max_retries: u32,
+    pub retry_delay: Duration,
+    pub max_retry_delay: Duration,
+    pub pool_idle_timeout: Duration,
+    pub pool_max_idle_per_host: usize,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(15),
+            read_timeout: Duration::from_secs(60),
+            max_retries: 3,
+            retry_delay: Duration::from_millis(500),
+            max_retry_delay: Duration::from_secs(30),
+            pool_idle_timeout: Duration::from_secs(90),
+            pool_max_idle_per_host: 8,
+        }
+    }
+}
+
+/// Shared HTTP client kept in managed state, used by every Rust network feature
+pub struct HttpClient {
+    // Replaced as a whole when network settings change
+    client: RwLock<reqwest::Client>,
+    config: HttpClientConfig,
+}
+
+/// `Zapuskalka/<version> (<os>; <arch>)`
+pub fn user_agent() -> String {
+    format!(
+        "Zapuskalka/{} ({}; {})",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+}
+
+impl HttpClient {
+    pub fn new(config: HttpClientConfig, settings: &NetworkSettings) -> Result<Self, String> {
+        let client = build_client(&config, settings)?;
+
+        Ok(Self {
+            client: RwLock::new(client),
+            config,
+        })
+    }
+
+    /// Returns the pooled client, cloning it is cheap
+    pub fn client(&self) -> reqwest::Client {
+        self.client.read().unwrap().clone()
+    }
+
+    /// Builds a standalone client with this configuration and the given settings
+    pub fn build_client(&self, settings: &NetworkSettings) -> Result<reqwest::Client, String> {
+        build_client(&self.config, settings)
+    }
+
+    /// Swaps the shared client for one built with new settings.
+    /// Requests that are already running keep the old connection pool.
+    pub fn configure(&self, settings: &NetworkSettings) -> Result<(), String> {
+        let client = self.build_client(settings)?;
+        *self.client.write().unwrap() = client;
+        Ok(())
+    }
+
+    /// Exponential backoff delay before the given retry attempt (starting at 1)
+    pub fn retry_delay(&self, attempt: u32) -> Duration {
+        self.config
+            .retry_delay
+            .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.config.max_retry_delay)
+    }
+
+    /// Sends the request, retrying idempotent requests on connection errors,
+    /// timeouts, 5xx and 429 responses. Requests with streaming bodies
+    /// cannot be replayed and are sent once.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, reqwest::Error> {
+        let request = request.build()?;
+
+        let client = self.client();
+
+        if !is_idempotent(request.method()) {
+            return client.execute(request).await;
+        }
+
+        let mut attempt = 0;
+        loop {
+            let Some(retry_request) = request.try_clone() else {
+                return client.execute(request).await;
+            };
+
+            let result = client.execute(retry_request).await;
+            let should_retry = match &result {
+                Ok(response) => is_retryable_status(response.status()),
+                Err(e) => e.is_connect() || e.is_timeout(),
+            };
+
+            attempt += 1;
+            if !should_retry || attempt > self.config.max_retries {
+                return result;
+            }
+
+            let delay = self.retry_delay(attempt);
+            eprintln!(
+                "Request to {} failed, retrying in {:?}",
+                request.url(),
+                delay
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+fn build_client(
+    config: &HttpClientConfig,
+    settings: &NetworkSettings,
+) -> Result<reqwest::Client, String> {
+    let builder = reqwest::Client::builder()
+        .user_agent(user_agent())
+        .connect_timeout(config.connect_timeout)
+        .read_timeout(config.read_timeout)
+        .pool_idle_timeout(config.pool_idle_timeout)
+        .pool_max_idle_per_host(config.pool_max_idle_per_host)
+        .tcp_keepalive(Duration::from_secs(60));
+
+    settings
+        .apply(builder)?
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+    )
+}
+
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use crate::test_support::{Response as TestResponse, TestServer};
+
+    fn test_client() -> HttpClient {
+        let config = HttpClientConfig {
+            read_timeout: Duration::from_millis(300),
+            max_retries: 2,
+            retry_delay: Duration::from_millis(10),
+            max_retry_delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+        HttpClient::new(config, &NetworkSettings::default()).unwrap()
+    }
+
+    /// Answers with `statuses` in order, then with 200. Returns the number of
+    /// requests the server got.
+    fn server(statuses: &[u16]) -> (TestServer, Arc<AtomicUsize>) {
+        let statuses = Mutex::new(statuses.to_vec());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let server = TestServer::start(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let mut statuses = statuses.lock().unwrap();
+            let status = if statuses.is_empty() {
+                200
+            } else {
+                statuses.remove(0)
+            };
+            TestResponse::new(status)
+        });
+        (server, requests)
+    }
+
+    #[tokio::test]
+    async fn retries_idempotent_requests_on_5xx_and_429() {
+        let http = test_client();
+        let (server, requests) = server(&[503, 429]);
+
+        let response = http.send(http.client().get(&server.url)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_retry() {
+        let http = test_client();
+        let (server, requests) = server(&[500, 500, 500, 500]);
+
+        let response = http.send(http.client().put(&server.url)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let http = test_client();
+        let (server, requests) = server(&[404]);
+
+        let response = http.send(http.client().get(&server.url)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn non_idempotent_requests_are_sent_once() {
+        let http = test_client();
+        let (server, requests) = server(&[503]);
+
+        let response = http
+            .send(http.client().post(&server.url).body("data"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_after_a_timeout() {
+        let http = test_client();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        // The first answer comes after the read timeout
+        let server = TestServer::start(move |_| {
+            if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                std::thread::sleep(Duration::from_secs(1));
+            }
+            TestResponse::new(200)
+        });
+
+        let response = http.send(http.client().get(&server.url)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_connection_errors() {
+        let http = test_client();
+        // Nothing listens on the port once the listener is dropped
+        let url = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+
+        let started = std::time::Instant::now();
+        let error = http.send(http.client().get(&url)).await.unwrap_err();
+
+        assert!(error.is_connect());
+        // Both retries waited for their delay
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+}
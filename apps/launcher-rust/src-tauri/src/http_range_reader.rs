@@ -0,0 +1,242 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use reqwest::header::{ACCEPT_RANGES, CONTENT_LENGTH, RANGE};
+use reqwest::StatusCode;
+use tokio::runtime::Handle;
+
+// Bytes fetched per request, zip readers do many small reads
+const READ_AHEAD: u64 = 1024 * 1024;
+
+/// Blocking `Read + Seek` over a remote file using HTTP range requests, so
+/// formats with an index like zip can be read without downloading all of it.
+/// Must be used from a blocking thread, requests run on the given runtime.
+pub struct HttpRangeReader {
+    client: reqwest::Client,
+    url: String,
+    handle: Handle,
+    len: u64,
+    pos: u64,
+    buf: Vec<u8>,
+    buf_start: u64,
+}
+
+impl HttpRangeReader {
+    /// Returns `None` when the server does not support range requests
+    pub async fn open(client: reqwest::Client, url: &str) -> Result<Option<Self>, String> {
+        let response = client
+            .head(url)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to send request: {}", e))?;
+
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+
+        let accepts_ranges = response
+            .headers()
+            .get(ACCEPT_RANGES)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.eq_ignore_ascii_case("bytes"));
+        // `content_length()` is the size of the empty HEAD body, not the header
+        let len = response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        let Some(len) = len.filter(|len| accepts_ranges && *len > 0) else {
+            return Ok(None);
+        };
+
+        Ok(Some(Self {
+            client,
+            url: url.to_string(),
+            handle: Handle::current(),
+            len,
+            pos: 0,
+            buf: vec![],
+            buf_start: 0,
+        }))
+    }
+
+    fn fetch(&mut self, start: u64) -> std::io::Result<()> {
+        let end = (start + READ_AHEAD).min(self.len) - 1;
+        let request = self
+            .client
+            .get(&self.url)
+            .header(RANGE, format!("bytes={}-{}", start, end));
+
+        let bytes = self.handle.block_on(async {
+            let response = request.send().await.map_err(std::io::Error::other)?;
+            if response.status() != StatusCode::PARTIAL_CONTENT {
+                return Err(std::io::Error::other(format!(
+                    "Range request failed with status {}",
+                    response.status()
+                )));
+            }
+            response.bytes().await.map_err(std::io::Error::other)
+        })?;
+
+        self.buf = bytes.to_vec();
+        self.buf_start = start;
+        Ok(())
+    }
+}
+
+impl Read for HttpRangeReader {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.len || out.is_empty() {
+            return Ok(0);
+        }
+
+        let buf_end = self.buf_start + self.buf.len() as u64;
+        if self.pos < self.buf_start || self.pos >= buf_end {
+            self.fetch(self.pos)?;
+        }
+
+        let offset = (self.pos - self.buf_start) as usize;
+        let available = &self.buf[offset..];
+        let read = available.len().min(out.len());
+        if read == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "Server returned an empty range",
+            ));
+        }
+
+        out[..read].copy_from_slice(&available[..read]);
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for HttpRangeReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+
+        self.pos = new_pos.ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid seek position")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    use crate::test_support::{Response, TestServer};
+
+    /// Serves `data` with range support and counts the body bytes sent
+    fn serve(data: Vec<u8>) -> (String, Arc<AtomicU64>) {
+        let sent = Arc::new(AtomicU64::new(0));
+        let sent_clone = sent.clone();
+
+        let server = TestServer::start(move |request| {
+            if request.method == "HEAD" {
+                return Response::new(200)
+                    .header("Accept-Ranges", "bytes")
+                    .header("Content-Length", data.len());
+            }
+
+            let range = request.header("Range").unwrap();
+            let (start, end) = range
+                .strip_prefix("bytes=")
+                .unwrap()
+                .split_once('-')
+                .unwrap();
+            let (start, end): (usize, usize) = (start.parse().unwrap(), end.parse().unwrap());
+            let body = &data[start..=end];
+            sent_clone.fetch_add(body.len() as u64, Ordering::Relaxed);
+            Response::new(206).body(body)
+        });
+
+        (format!("{}/build.zip", server.url), sent)
+    }
+
+    /// Stored zip whose entries don't compress, so their sizes add up
+    fn test_zip(entries: &[(&str, usize)]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for (index, (name, len)) in entries.iter().enumerate() {
+            zip.start_file(*name, options).unwrap();
+            let data: Vec<u8> = (0..*len).map(|i| (i * 31 + index) as u8).collect();
+            zip.write_all(&data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reads_one_zip_entry_without_fetching_the_rest() {
+        let data = test_zip(&[
+            ("big1.bin", 3 << 20),
+            ("small.txt", 1000),
+            ("big2.bin", 3 << 20),
+        ]);
+        let len = data.len() as u64;
+        let (url, sent) = serve(data);
+
+        let reader = HttpRangeReader::open(reqwest::Client::new(), &url)
+            .await
+            .unwrap()
+            .unwrap();
+        let content = tokio::task::spawn_blocking(move || {
+            let mut zip = zip::ZipArchive::new(reader).unwrap();
+            let mut content = vec![];
+            zip.by_name("small.txt")
+                .unwrap()
+                .read_to_end(&mut content)
+                .unwrap();
+            content
+        })
+        .await
+        .unwrap();
+
+        let expected: Vec<u8> = (0..1000).map(|i| (i * 31 + 1) as u8).collect();
+        assert_eq!(content, expected);
+        // The central directory and one read-ahead around the entry
+        let sent = sent.load(Ordering::Relaxed);
+        assert!(sent <= 2 * READ_AHEAD, "fetched {} of {} bytes", sent, len);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn seeks_across_fetched_ranges() {
+        let data: Vec<u8> = (0..(3 * READ_AHEAD as usize))
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let (url, _) = serve(data.clone());
+
+        let mut reader = HttpRangeReader::open(reqwest::Client::new(), &url)
+            .await
+            .unwrap()
+            .unwrap();
+        let parts = tokio::task::spawn_blocking(move || {
+            let mut parts = vec![];
+            for pos in [
+                SeekFrom::End(-10),
+                SeekFrom::Start(5),
+                SeekFrom::Current(READ_AHEAD as i64),
+            ] {
+                reader.seek(pos).unwrap();
+                let mut buf = [0; 10];
+                reader.read_exact(&mut buf).unwrap();
+                parts.push(buf);
+            }
+            parts
+        })
+        .await
+        .unwrap();
+
+        let len = data.len();
+        let at = |start: usize| -> [u8; 10] { data[start..start + 10].try_into().unwrap() };
+        assert_eq!(parts, [at(len - 10), at(5), at(15 + READ_AHEAD as usize)]);
+    }
+}
@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+
+/// Reads a JSON store file, starting empty when it is missing. A file that
+/// cannot be read or parsed is moved aside to `<name>.bak` so the next save
+/// does not overwrite what could still be recovered from it.
+pub fn load_or_backup<T: DeserializeOwned + Default>(path: &Path) -> T {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return T::default(),
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path.display(), e);
+            back_up(path);
+            return T::default();
+        }
+    };
+
+    match serde_json::from_str(&json) {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("Failed to parse {}: {}", path.display(), e);
+            back_up(path);
+            T::default()
+        }
+    }
+}
+
+/// Replaces the store file through a temporary file next to it, so a crash
+/// mid-write leaves the old content instead of a broken file
+pub fn write_atomically(path: &Path, json: &str) -> std::io::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+
+    std::fs::write(&temp_path, json).and_then(|_| std::fs::rename(&temp_path, path))
+}
+
+fn back_up(path: &Path) {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+
+    if let Err(e) = std::fs::rename(path, &backup) {
+        eprintln!("Failed to move {} aside: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn moves_a_corrupt_file_aside() {
+        let dir = TempDir::new("json_store", "corrupt");
+        let path = dir.0.join("store.json");
+
+        let missing: HashMap<String, u32> = load_or_backup(&path);
+        assert!(missing.is_empty());
+
+        std::fs::write(&path, r#"{"a": 1}"#).unwrap();
+        let loaded: HashMap<String, u32> = load_or_backup(&path);
+        assert_eq!(loaded["a"], 1);
+
+        std::fs::write(&path, r#"{"a": 1"#).unwrap();
+        let corrupt: HashMap<String, u32> = load_or_backup(&path);
+        assert!(corrupt.is_empty());
+        assert!(!path.exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.0.join("store.json.bak")).unwrap(),
+            r#"{"a": 1"#
+        );
+
+        write_atomically(&path, r#"{"a": 2}"#).unwrap();
+        let written: HashMap<String, u32> = load_or_backup(&path);
+        assert_eq!(written["a"], 2);
+        assert!(!dir.0.join("store.json.tmp").exists());
+    }
+}
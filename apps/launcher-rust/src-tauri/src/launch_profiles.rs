@@ -0,0 +1,459 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::compat_tools::{validate_compat_tool, CompatTool};
+use crate::json_store::{load_or_backup, write_atomically};
+use crate::launch_wrappers::{validate_wrappers, Wrapper};
+
+/// Default profiles shipped by the developer in the root of the build
+pub const LAUNCH_PROFILES_FILE_NAME: &str = ".zapuskalka-launch.json";
+
+/// Named way to start a game, e.g. "Play (Vulkan)" or "Level editor"
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LaunchProfile {
+    pub name: String,
+    // Relative to the install dir, the build entrypoint when missing
+    #[serde(default)]
+    pub entrypoint: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    // Overrides of the launcher environment, `null` removes a variable
+    #[serde(default)]
+    pub env: BTreeMap<String, Option<String>>,
+    // Relative to the install dir, the entrypoint's folder when missing
+    #[serde(default)]
+    pub working_dir: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BuildProfiles {
+    profiles: Vec<LaunchProfile>,
+}
+
+/// How many copies of a game may run at once
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum InstancePolicy {
+    // Launching again while the game runs fails with `GameRunning`
+    #[default]
+    Single,
+    Multiple,
+}
+
+/// What the player changed for one install
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct InstallProfiles {
+    // Replace the build profile of the same name or add a new one
+    #[serde(default)]
+    pub profiles: Vec<LaunchProfile>,
+    // Profile started by the play button
+    #[serde(default)]
+    pub selected: Option<String>,
+    // Run inside the global wrappers
+    #[serde(default)]
+    pub wrappers: Vec<Wrapper>,
+    #[serde(default)]
+    pub skip_global_wrappers: bool,
+    // Replaces the global compatibility tool
+    #[serde(default)]
+    pub compat_tool: Option<CompatTool>,
+    #[serde(default)]
+    pub instance_policy: InstancePolicy,
+}
+
+impl InstallProfiles {
+    fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+            && self.selected.is_none()
+            && self.wrappers.is_empty()
+            && !self.skip_global_wrappers
+            && self.compat_tool.is_none()
+            && self.instance_policy == InstancePolicy::default()
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileSource {
+    Build,
+    // A build profile the player changed
+    Customized,
+    User,
+}
+
+#[derive(Serialize)]
+pub struct ResolvedProfile {
+    #[serde(flatten)]
+    profile: LaunchProfile,
+    source: ProfileSource,
+}
+
+#[derive(Serialize)]
+pub struct LaunchOptions {
+    profiles: Vec<ResolvedProfile>,
+    selected: Option<String>,
+    wrappers: Vec<Wrapper>,
+    skip_global_wrappers: bool,
+    compat_tool: Option<CompatTool>,
+    instance_policy: InstancePolicy,
+}
+
+pub struct LaunchProfileStore {
+    path: PathBuf,
+    // Keyed by install dir
+    installs: HashMap<String, InstallProfiles>,
+}
+
+impl LaunchProfileStore {
+    /// Loads the store from `path`, starting empty if the file is missing.
+    /// A broken file is kept as `.bak` next to it.
+    pub fn load(path: PathBuf) -> Self {
+        let installs = load_or_backup(&path);
+
+        Self { path, installs }
+    }
+
+    pub fn get(&self, install_dir: &str) -> InstallProfiles {
+        self.installs.get(install_dir).cloned().unwrap_or_default()
+    }
+
+    pub fn update(
+        &mut self,
+        install_dir: String,
+        change: impl FnOnce(&mut InstallProfiles),
+    ) -> Result<(), String> {
+        let mut profiles = self.get(&install_dir);
+        change(&mut profiles);
+
+        if profiles.is_empty() {
+            self.installs.remove(&install_dir);
+        } else {
+            self.installs.insert(install_dir, profiles);
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create launch profiles dir: {}", e))?;
+        }
+
+        let json = serde_json::to_string_pretty(&self.installs)
+            .map_err(|e| format!("Failed to serialize launch profiles: {}", e))?;
+
+        write_atomically(&self.path, &json)
+            .map_err(|e| format!("Failed to write launch profiles: {}", e))
+    }
+}
+
+/// Managed state shared by the launch profile commands and the launcher
+pub type LaunchProfiles = Mutex<LaunchProfileStore>;
+
+/// Profiles shipped with the installed build, none when the build has no file
+fn load_build_profiles(install_dir: &Path) -> Result<Vec<LaunchProfile>, String> {
+    let path = install_dir.join(LAUNCH_PROFILES_FILE_NAME);
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let json = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read build launch profiles: {}", e))?;
+    let build: BuildProfiles = serde_json::from_str(&json)
+        .map_err(|e| format!("Failed to parse build launch profiles: {}", e))?;
+
+    Ok(build.profiles)
+}
+
+fn validate_profiles(profiles: &[LaunchProfile]) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for profile in profiles {
+        if profile.name.trim().is_empty() {
+            return Err("Launch profile name is empty".to_string());
+        }
+        if !names.insert(profile.name.as_str()) {
+            return Err(format!("Duplicate launch profile: {}", profile.name));
+        }
+
+        for key in profile.env.keys() {
+            if key.is_empty() || key.contains(['=', '\0']) {
+                return Err(format!("Invalid environment variable name: {:?}", key));
+            }
+        }
+        let values = profile.env.values().flatten();
+        if profile.args.iter().chain(values).any(|s| s.contains('\0')) {
+            return Err(format!(
+                "Launch profile {} contains a NUL character",
+                profile.name
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Fails packing a build whose default profiles would not load
+pub fn validate_build_profiles(folder: &Path) -> Result<(), String> {
+    validate_profiles(&load_build_profiles(folder)?)
+}
+
+/// Build profiles in their order with the player's changes applied, followed
+/// by the profiles the player added
+fn merge_profiles(build: Vec<LaunchProfile>, user: &[LaunchProfile]) -> Vec<ResolvedProfile> {
+    let mut user: Vec<Option<&LaunchProfile>> = user.iter().map(Some).collect();
+
+    let mut merged: Vec<ResolvedProfile> = build
+        .into_iter()
+        .map(|profile| {
+            let custom = user
+                .iter_mut()
+                .find(|p| p.is_some_and(|p| p.name == profile.name))
+                .and_then(Option::take);
+            match custom {
+                Some(custom) => ResolvedProfile {
+                    profile: custom.clone(),
+                    source: ProfileSource::Customized,
+                },
+                None => ResolvedProfile {
+                    profile,
+                    source: ProfileSource::Build,
+                },
+            }
+        })
+        .collect();
+
+    merged.extend(user.into_iter().flatten().map(|profile| ResolvedProfile {
+        profile: profile.clone(),
+        source: ProfileSource::User,
+    }));
+    merged
+}
+
+fn launch_options(store: &LaunchProfiles, install_dir: &str) -> Result<LaunchOptions, String> {
+    let build = load_build_profiles(Path::new(install_dir))?;
+    let install = store.lock().unwrap().get(install_dir);
+
+    Ok(LaunchOptions {
+        profiles: merge_profiles(build, &install.profiles),
+        selected: install.selected,
+        wrappers: install.wrappers,
+        skip_global_wrappers: install.skip_global_wrappers,
+        compat_tool: install.compat_tool,
+        instance_policy: install.instance_policy,
+    })
+}
+
+/// Picks the profile to launch: the one asked for, then the player's choice,
+/// then the first one. None when the install has no profiles at all.
+pub fn resolve_profile(
+    store: &LaunchProfiles,
+    install_dir: &str,
+    name: Option<&str>,
+) -> Result<Option<LaunchProfile>, String> {
+    let options = launch_options(store, install_dir)?;
+    let find = |name: &str| {
+        options
+            .profiles
+            .iter()
+            .find(|p| p.profile.name == name)
+            .map(|p| p.profile.clone())
+    };
+
+    if let Some(name) = name {
+        return find(name)
+            .map(Some)
+            .ok_or_else(|| format!("Launch profile not found: {}", name));
+    }
+
+    // A selected profile may have been dropped by an update of the build
+    Ok(options
+        .selected
+        .as_deref()
+        .and_then(find)
+        .or_else(|| options.profiles.first().map(|p| p.profile.clone())))
+}
+
+#[tauri::command]
+pub fn get_launch_profiles(
+    store: tauri::State<'_, LaunchProfiles>,
+    install_dir: String,
+) -> Result<LaunchOptions, String> {
+    launch_options(&store, &install_dir)
+}
+
+/// Stores the player's profiles of an install. Build profiles missing from
+/// `profiles` go back to their defaults.
+#[tauri::command]
+pub fn set_launch_profiles(
+    store: tauri::State<'_, LaunchProfiles>,
+    install_dir: String,
+    profiles: Vec<LaunchProfile>,
+    selected: Option<String>,
+) -> Result<LaunchOptions, String> {
+    validate_profiles(&profiles)?;
+
+    store
+        .lock()
+        .unwrap()
+        .update(install_dir.clone(), |install| {
+            install.profiles = profiles;
+            install.selected = selected.filter(|name| !name.is_empty());
+        })?;
+
+    launch_options(&store, &install_dir)
+}
+
+/// Stores the wrappers of one game, run inside the global ones unless skipped
+#[tauri::command]
+pub fn set_game_wrappers(
+    store: tauri::State<'_, LaunchProfiles>,
+    install_dir: String,
+    wrappers: Vec<Wrapper>,
+    skip_global_wrappers: bool,
+) -> Result<LaunchOptions, String> {
+    validate_wrappers(&wrappers)?;
+
+    store
+        .lock()
+        .unwrap()
+        .update(install_dir.clone(), |install| {
+            install.wrappers = wrappers;
+            install.skip_global_wrappers = skip_global_wrappers;
+        })?;
+
+    launch_options(&store, &install_dir)
+}
+
+/// Stores the compatibility tool of one game, none uses the global one
+#[tauri::command]
+pub fn set_game_compat_tool(
+    store: tauri::State<'_, LaunchProfiles>,
+    install_dir: String,
+    compat_tool: Option<CompatTool>,
+) -> Result<LaunchOptions, String> {
+    if let Some(tool) = &compat_tool {
+        validate_compat_tool(tool)?;
+    }
+
+    store
+        .lock()
+        .unwrap()
+        .update(install_dir.clone(), |install| {
+            install.compat_tool = compat_tool;
+        })?;
+
+    launch_options(&store, &install_dir)
+}
+
+/// Stores whether the game may run more than once at the same time
+#[tauri::command]
+pub fn set_game_instance_policy(
+    store: tauri::State<'_, LaunchProfiles>,
+    install_dir: String,
+    instance_policy: InstancePolicy,
+) -> Result<LaunchOptions, String> {
+    store
+        .lock()
+        .unwrap()
+        .update(install_dir.clone(), |install| {
+            install.instance_policy = instance_policy;
+        })?;
+
+    launch_options(&store, &install_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TempDir, MTIME};
+
+    fn profile(name: &str, args: &[&str]) -> LaunchProfile {
+        LaunchProfile {
+            name: name.to_string(),
+            entrypoint: None,
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            env: BTreeMap::new(),
+            working_dir: None,
+        }
+    }
+
+    fn summary(profiles: &[ResolvedProfile]) -> Vec<(&str, ProfileSource, Vec<String>)> {
+        profiles
+            .iter()
+            .map(|p| (p.profile.name.as_str(), p.source, p.profile.args.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn player_changes_replace_build_profiles_in_place() {
+        let build = vec![profile("Play", &[]), profile("Editor", &["--editor"])];
+        let user = [
+            profile("Mods", &["--mods"]),
+            profile("Editor", &["--editor", "--safe"]),
+        ];
+
+        let merged = merge_profiles(build, &user);
+
+        assert_eq!(
+            summary(&merged),
+            [
+                ("Play", ProfileSource::Build, vec![]),
+                (
+                    "Editor",
+                    ProfileSource::Customized,
+                    vec!["--editor".to_string(), "--safe".to_string()]
+                ),
+                ("Mods", ProfileSource::User, vec!["--mods".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_the_asked_then_the_selected_then_the_first_profile() {
+        let install = TempDir::new("launch_profiles", "resolve");
+        install.write(
+            LAUNCH_PROFILES_FILE_NAME,
+            br#"{"profiles": [{"name": "Play"}, {"name": "Editor", "args": ["--editor"]}]}"#,
+            MTIME,
+        );
+        let install_dir = install.0.to_str().unwrap().to_string();
+        let store: LaunchProfiles =
+            Mutex::new(LaunchProfileStore::load(install.0.join("profiles.json")));
+        let resolve = |name: Option<&str>| {
+            resolve_profile(&store, &install_dir, name).map(|p| p.map(|p| (p.name, p.args)))
+        };
+
+        assert_eq!(resolve(None).unwrap(), Some(("Play".to_string(), vec![])));
+
+        store
+            .lock()
+            .unwrap()
+            .update(install_dir.clone(), |install| {
+                install.profiles = vec![profile("Editor", &["--safe"])];
+                install.selected = Some("Editor".to_string());
+            })
+            .unwrap();
+        assert_eq!(
+            resolve(None).unwrap(),
+            Some(("Editor".to_string(), vec!["--safe".to_string()]))
+        );
+        assert_eq!(
+            resolve(Some("Play")).unwrap(),
+            Some(("Play".to_string(), vec![]))
+        );
+        assert!(resolve(Some("Missing")).is_err());
+
+        // The build dropped the selected profile
+        store
+            .lock()
+            .unwrap()
+            .update(install_dir.clone(), |install| {
+                install.selected = Some("Benchmark".to_string());
+            })
+            .unwrap();
+        assert_eq!(resolve(None).unwrap(), Some(("Play".to_string(), vec![])));
+    }
+}
@@ -0,0 +1,71 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::compat_tools::{validate_compat_tool, CompatTool};
+use crate::launch_wrappers::{validate_wrappers, Wrapper};
+
+/// Launch settings shared by every game
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct LaunchSettings {
+    // Outermost first, games may opt out in their launch options
+    #[serde(default)]
+    pub wrappers: Vec<Wrapper>,
+    // Runs Windows builds when there is no native one
+    #[serde(default)]
+    pub compat_tool: Option<CompatTool>,
+    // Crash reports leave the machine only when the player turned this on
+    #[serde(default)]
+    pub send_crash_reports: bool,
+}
+
+fn get_launch_settings_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let app_config_dir = app
+        .path()
+        .app_config_dir()
+        .map_err(|e| format!("Failed to get app config dir: {}", e))?;
+    std::fs::create_dir_all(&app_config_dir)
+        .map_err(|e| format!("Failed to create config dir: {}", e))?;
+    Ok(app_config_dir.join("launch_settings.json"))
+}
+
+pub fn load_launch_settings(app: &tauri::AppHandle) -> Result<LaunchSettings, String> {
+    let settings_path = get_launch_settings_path(app)?;
+
+    if !settings_path.exists() {
+        return Ok(LaunchSettings::default());
+    }
+
+    let json = std::fs::read_to_string(&settings_path)
+        .map_err(|e| format!("Failed to read launch settings: {}", e))?;
+
+    serde_json::from_str(&json).map_err(|e| format!("Failed to parse launch settings: {}", e))
+}
+
+fn save_launch_settings(app: &tauri::AppHandle, settings: &LaunchSettings) -> Result<(), String> {
+    let settings_path = get_launch_settings_path(app)?;
+    let json = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize launch settings: {}", e))?;
+
+    std::fs::write(&settings_path, json)
+        .map_err(|e| format!("Failed to write launch settings: {}", e))
+}
+
+#[tauri::command]
+pub async fn get_launch_settings(app: tauri::AppHandle) -> Result<LaunchSettings, String> {
+    load_launch_settings(&app)
+}
+
+/// Wrappers and tools missing from PATH are accepted, they are looked up on every launch
+#[tauri::command]
+pub async fn set_launch_settings(
+    app: tauri::AppHandle,
+    settings: LaunchSettings,
+) -> Result<(), String> {
+    validate_wrappers(&settings.wrappers)?;
+    if let Some(tool) = &settings.compat_tool {
+        validate_compat_tool(tool)?;
+    }
+    save_launch_settings(&app, &settings)
+}
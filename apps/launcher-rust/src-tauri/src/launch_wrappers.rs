@@ -0,0 +1,264 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Argument replaced by the wrapped command line, which goes last without it
+pub const COMMAND_PLACEHOLDER: &str = "%command%";
+
+/// Program the game is started through, e.g. `gamemoderun` or
+/// `mangohud --dlsym %command%`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Wrapper {
+    // Looked up in PATH unless it contains a path separator
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Resolves a program the way a shell does, `path` is the PATH the game gets
+pub fn find_program(program: &str, path: Option<&OsStr>) -> Option<PathBuf> {
+    if program.contains(std::path::is_separator) {
+        let program = PathBuf::from(program);
+        return is_executable(&program).then_some(program);
+    }
+
+    std::env::split_paths(path?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| is_executable(candidate))
+}
+
+fn validate_wrapper(wrapper: &Wrapper) -> Result<(), String> {
+    if wrapper.program.trim().is_empty() {
+        return Err("Launch wrapper program is empty".to_string());
+    }
+    if wrapper.program == COMMAND_PLACEHOLDER {
+        return Err(format!(
+            "{} can not be the launch wrapper program",
+            COMMAND_PLACEHOLDER
+        ));
+    }
+
+    let placeholders = wrapper
+        .args
+        .iter()
+        .filter(|arg| *arg == COMMAND_PLACEHOLDER)
+        .count();
+    if placeholders > 1 {
+        return Err(format!(
+            "Launch wrapper {} uses {} more than once",
+            wrapper.program, COMMAND_PLACEHOLDER
+        ));
+    }
+
+    let strings = std::iter::once(&wrapper.program).chain(&wrapper.args);
+    if strings.into_iter().any(|s| s.contains('\0')) {
+        return Err(format!(
+            "Launch wrapper {} contains a NUL character",
+            wrapper.program
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks what can be checked before the wrappers are needed
+pub fn validate_wrappers(wrappers: &[Wrapper]) -> Result<(), String> {
+    wrappers.iter().try_for_each(validate_wrapper)
+}
+
+/// Builds the command line that starts `command` through the wrappers, the
+/// first wrapper is the outermost one. Every wrapper must exist in `path`.
+pub fn wrap_command(
+    wrappers: &[Wrapper],
+    mut command: Vec<OsString>,
+    path: Option<&OsStr>,
+) -> Result<Vec<OsString>, String> {
+    validate_wrappers(wrappers)?;
+
+    for wrapper in wrappers.iter().rev() {
+        let program = find_program(&wrapper.program, path)
+            .ok_or_else(|| format!("Launch wrapper not found: {}", wrapper.program))?;
+
+        let mut wrapped = vec![program.into_os_string()];
+        for arg in &wrapper.args {
+            if arg == COMMAND_PLACEHOLDER {
+                wrapped.append(&mut command);
+            } else {
+                wrapped.push(arg.into());
+            }
+        }
+        // Empty already when the placeholder took it
+        wrapped.append(&mut command);
+
+        command = wrapped;
+    }
+
+    Ok(command)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::ffi::OsString;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+
+    use crate::test_support::TempDir;
+
+    /// Wrapper that logs its name and arguments, then runs the rest of them
+    fn add_wrapper(stubs: &TempDir, name: &str) -> PathBuf {
+        add_script(
+            stubs,
+            name,
+            "#!/bin/sh\necho \"$(basename \"$0\") $*\" >> \"$WRAPPER_LOG\"\nexec \"$@\"\n",
+            0o755,
+        )
+    }
+
+    fn add_script(stubs: &TempDir, name: &str, content: &str, mode: u32) -> PathBuf {
+        let path = stubs.0.join(name);
+        std::fs::write(&path, content).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        path
+    }
+
+    fn wrapper(program: &str, args: &[&str]) -> Wrapper {
+        Wrapper {
+            program: program.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    fn command(game: &Path, args: &[&str]) -> Vec<OsString> {
+        std::iter::once(game.as_os_str().to_owned())
+            .chain(args.iter().map(OsString::from))
+            .collect()
+    }
+
+    #[test]
+    fn chain_runs_outermost_first() {
+        let stubs = TempDir::new("launch_wrappers", "chain");
+        let gamemoderun = add_wrapper(&stubs, "gamemoderun");
+        let mangohud = add_wrapper(&stubs, "mangohud");
+        let game = add_script(
+            &stubs,
+            "game",
+            "#!/bin/sh\necho \"game $*\" >> \"$WRAPPER_LOG\"\n",
+            0o755,
+        );
+        let log = stubs.0.join("log");
+
+        let wrappers = [wrapper("gamemoderun", &[]), wrapper("mangohud", &[])];
+        let command_line = wrap_command(
+            &wrappers,
+            command(&game, &["--fullscreen"]),
+            Some(stubs.0.as_os_str()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            command_line,
+            vec![
+                gamemoderun.clone().into_os_string(),
+                mangohud.clone().into_os_string(),
+                game.clone().into_os_string(),
+                "--fullscreen".into(),
+            ]
+        );
+
+        let status = std::process::Command::new(&command_line[0])
+            .args(&command_line[1..])
+            .env("WRAPPER_LOG", &log)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let log = std::fs::read_to_string(&log).unwrap();
+        let expected = format!(
+            "gamemoderun {} {} --fullscreen\nmangohud {} --fullscreen\ngame --fullscreen\n",
+            mangohud.display(),
+            game.display(),
+            game.display()
+        );
+        assert_eq!(log, expected);
+    }
+
+    #[test]
+    fn placeholder_places_the_command() {
+        let stubs = TempDir::new("launch_wrappers", "placeholder");
+        let runner = add_wrapper(&stubs, "runner");
+        let game = stubs.0.join("game");
+
+        let wrappers = [wrapper("runner", &["--before", "%command%", "--after"])];
+        let command_line = wrap_command(
+            &wrappers,
+            command(&game, &["-x"]),
+            Some(stubs.0.as_os_str()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            command_line,
+            vec![
+                runner.into_os_string(),
+                "--before".into(),
+                game.into_os_string(),
+                "-x".into(),
+                "--after".into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn absolute_wrapper_skips_path_lookup() {
+        let stubs = TempDir::new("launch_wrappers", "absolute");
+        let prime_run = add_wrapper(&stubs, "prime-run");
+        let game = stubs.0.join("game");
+
+        let wrappers = [wrapper(prime_run.to_str().unwrap(), &[])];
+        let command_line = wrap_command(&wrappers, command(&game, &[]), None).unwrap();
+
+        assert_eq!(command_line[0], prime_run.into_os_string());
+    }
+
+    #[test]
+    fn missing_wrapper_fails_the_launch() {
+        let stubs = TempDir::new("launch_wrappers", "missing");
+        // Not executable, so not a program a shell would run
+        add_script(&stubs, "mangohud", "#!/bin/sh\n", 0o644);
+        let game = stubs.0.join("game");
+
+        assert!(find_program("mangohud", Some(stubs.0.as_os_str())).is_none());
+        let error = wrap_command(
+            &[wrapper("mangohud", &[])],
+            command(&game, &[]),
+            Some(stubs.0.as_os_str()),
+        )
+        .unwrap_err();
+        assert!(error.contains("mangohud"), "{}", error);
+    }
+
+    #[test]
+    fn placeholder_twice_is_rejected() {
+        let stubs = TempDir::new("launch_wrappers", "twice");
+        add_wrapper(&stubs, "runner");
+        let game = stubs.0.join("game");
+
+        let wrappers = [wrapper("runner", &["%command%", "%command%"])];
+        assert!(wrap_command(&wrappers, command(&game, &[]), Some(stubs.0.as_os_str())).is_err());
+    }
+}
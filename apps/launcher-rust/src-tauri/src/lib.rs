@@ -1,52 +1,102 @@
+use flate2::write::GzEncoder;
 use flate2::Compression;
-use flate2::{read::GzDecoder, write::GzEncoder};
-use serde::Serialize;
-use std::collections::VecDeque;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::BufWriter;
-use std::path::Path;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tar::{Archive, Builder};
+use tar::Builder;
 use tauri::{
     menu::{Menu, MenuItem},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
     LogicalPosition, LogicalSize, Manager,
 };
+use tokio_util::io::{StreamReader, SyncIoBridge};
 
+use crate::hashing::{hash_file, HashAlgorithm, HashingReader};
+use crate::http_client::{is_retryable_status, HttpClient, HttpClientConfig};
+use crate::launch_profiles::validate_build_profiles;
+use crate::manifest::ManifestFormat;
+use crate::pack::{append_files, append_manifest, collect_files, validate_source_folder};
 use crate::rate_meter::RateMeter;
 use crate::tracking_reader::TrackingReader;
 use crate::tracking_tokio_stream::TrackingTokioStream;
 use crate::tracking_writer::TrackingWriter;
+use crate::unpack::{detect_format, unpack_file, unpack_stream, ArchiveFormat};
+use crate::upload_integrity::{
+    verify_reported_hashes, UploadIntegrityOptions, UploadedFile, UPLOAD_HASH_ALGORITHM,
+};
+use crate::verified_download::{
+    download_unverified, download_verified, move_into, staging_dir, IntegrityError,
+    INTEGRITY_RETRIES,
+};
 
+mod chunk_store;
+mod chunked_build;
+mod chunking;
+mod compat_tools;
+mod crash_reports;
+mod delta;
+mod game_logs;
+mod hashing;
+mod http_client;
+mod http_range_reader;
+mod json_store;
+mod launch_profiles;
+mod launch_settings;
+mod launch_wrappers;
+mod manifest;
+mod network_settings;
+mod pack;
+mod playtime;
+mod process_manager;
+mod process_tree;
 mod rate_meter;
+mod repair;
+mod resource_usage;
+mod s3_multipart;
+#[cfg(test)]
+mod test_support;
 mod tracking_reader;
 mod tracking_tokio_stream;
 mod tracking_writer;
+mod tus;
+mod unpack;
+mod update_preview;
+mod upload_integrity;
+mod verified_download;
+mod verify;
 
 #[derive(Serialize)]
-struct ProgressCallbackData {
+pub struct ProgressCallbackData {
     current_bytes: u64,
     total_bytes: u64,
     delta_per_second: u64,
 }
 
+#[derive(Serialize)]
+struct PackedBuild {
+    archive_path: String,
+    archive_size: u64,
+    // Same manifest that is stored inside the archive, in the requested format
+    manifest_path: String,
+}
+
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
 async fn archive_and_compress_folder(
     folder_path: String,
+    chunk_size: Option<u64>,
+    manifest_format: Option<ManifestFormat>,
     progress_channel: tauri::ipc::Channel<ProgressCallbackData>,
     speed_update_interval: Option<f64>,
-) -> Result<String, String> {
-    let source_path = Path::new(&folder_path);
-
-    // Check if the folder exists
-    if !source_path.exists() {
-        return Err(format!("Folder does not exist: {}", folder_path));
-    }
-
-    if !source_path.is_dir() {
-        return Err(format!("Path is not a directory: {}", folder_path));
-    }
+) -> Result<PackedBuild, String> {
+    let source_path = validate_source_folder(&folder_path)?;
+    validate_build_profiles(source_path)?;
 
     // Get the folder name for the archive name
     let folder_name = source_path
@@ -66,34 +116,10 @@ async fn archive_and_compress_folder(
     let output_file =
         File::create(&output_path).map_err(|e| format!("Failed to create output file: {}", e))?;
 
-    let mut total_bytes = 0_u64;
     let mut packed_bytes = 0_u64;
 
     // Find all files
-    let mut to_visit = VecDeque::new();
-    let mut all_entries = vec![];
-
-    to_visit.push_front(source_path.to_path_buf());
-
-    while let Some(dir_path) = to_visit.pop_front() {
-        let entries = std::fs::read_dir(dir_path)
-            .map_err(|e| format!("Failed to read directory contents: {}", e))?;
-
-        for entry in entries {
-            let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
-            let entry_path = entry.path();
-
-            if entry_path.is_dir() {
-                to_visit.push_front(entry_path);
-            } else {
-                let meta = entry
-                    .metadata()
-                    .map_err(|e| format!("Failed to get file metadata: {}", e))?;
-                total_bytes += meta.len();
-                all_entries.push(entry);
-            }
-        }
-    }
+    let (all_entries, total_bytes) = collect_files(source_path)?;
 
     // Create gzip encoder
     let gz_encoder = GzEncoder::new(output_file, Compression::default());
@@ -129,22 +155,21 @@ async fn archive_and_compress_folder(
         })
         .map_err(|e| format!("Failed to emit packing progress event: {}", e))?;
 
-    for entry in all_entries {
-        let entry_path = entry.path();
-        let relative_path = entry_path
-            .strip_prefix(source_path)
-            .map_err(|e| format!("Failed to calculate relative path: {}", e))?;
+    let manifest = append_files(&mut tar_builder, &all_entries, source_path, chunk_size)?;
+    let manifest_json = append_manifest(&mut tar_builder, &manifest)?;
 
-        if entry_path.is_dir() {
-            tar_builder
-                .append_dir_all(relative_path, &entry_path)
-                .map_err(|e| format!("Failed to add directory to archive: {}", e))?;
-        } else {
-            tar_builder
-                .append_path_with_name(&entry_path, relative_path)
-                .map_err(|e| format!("Failed to add file to archive: {}", e))?;
-        }
-    }
+    let manifest_format = manifest_format.unwrap_or_default();
+    let manifest_data = match manifest_format {
+        ManifestFormat::Json => manifest_json,
+        format => manifest.encode(format)?,
+    };
+    let manifest_path = parent_dir.join(format!(
+        "{}.manifest.{}",
+        folder_name,
+        manifest_format.extension()
+    ));
+    std::fs::write(&manifest_path, manifest_data)
+        .map_err(|e| format!("Failed to write manifest: {}", e))?;
 
     // Finish writing the archive
     // into_inner() returns the TrackingWriter, then we need to get the BufWriter from it
@@ -162,11 +187,29 @@ async fn archive_and_compress_folder(
         .finish()
         .map_err(|e| format!("Failed to finalize compression: {}", e))?;
 
-    // Return the path to the compressed archive
-    output_path
-        .to_str()
-        .ok_or_else(|| "Failed to convert path to string".to_string())
-        .map(|s| s.to_string())
+    // Return the paths to the compressed archive and its manifest
+    let path_to_string = |path: PathBuf| {
+        path.to_str()
+            .ok_or_else(|| "Failed to convert path to string".to_string())
+            .map(|s| s.to_string())
+    };
+
+    let archive_size = std::fs::metadata(&output_path)
+        .map_err(|e| format!("Failed to get archive metadata: {}", e))?
+        .len();
+
+    Ok(PackedBuild {
+        archive_size,
+        archive_path: path_to_string(output_path)?,
+        manifest_path: path_to_string(manifest_path)?,
+    })
+}
+
+/// Size of the files a folder would be packed from, used to pick an upload method
+#[tauri::command]
+async fn get_folder_size(folder_path: String) -> Result<u64, String> {
+    let source_path = validate_source_folder(&folder_path)?;
+    collect_files(source_path).map(|(_, total_bytes)| total_bytes)
 }
 
 #[tauri::command]
@@ -177,20 +220,37 @@ async fn read_file_bytes(file_path: String) -> Result<Vec<u8>, String> {
 
 #[tauri::command]
 async fn extract_archive(
+    manager: tauri::State<'_, process_manager::ProcessManager>,
     archive_path: String,
     destination_path: String,
+    expected_hash: Option<String>,
     progress_channel: tauri::ipc::Channel<ProgressCallbackData>,
     speed_update_interval: Option<f64>,
-) -> Result<(), String> {
+) -> Result<(), process_manager::CommandError> {
     let archive_path = Path::new(&archive_path);
     if !archive_path.exists() {
-        return Err(format!(
-            "Archive does not exist: {}",
-            archive_path.display()
-        ));
+        return Err(format!("Archive does not exist: {}", archive_path.display()).into());
+    }
+
+    if let Some(expected_hash) = expected_hash {
+        let (algorithm, _) = HashAlgorithm::parse_tagged(&expected_hash)
+            .ok_or_else(|| format!("Unsupported hash: {}", expected_hash))?;
+        let digest = hash_file(archive_path, algorithm)
+            .map_err(|e| format!("Failed to hash archive: {}", e))?;
+
+        if !digest.eq_ignore_ascii_case(&expected_hash) {
+            return Err(format!(
+                "Integrity check failed for {}: expected {}, downloaded {}",
+                archive_path.display(),
+                expected_hash,
+                digest
+            )
+            .into());
+        }
     }
 
     let destination_path = Path::new(&destination_path);
+    let _reservation = manager.reserve_install(destination_path)?;
     std::fs::create_dir_all(destination_path)
         .map_err(|e| format!("Failed to create destination directory: {}", e))?;
 
@@ -221,97 +281,468 @@ async fn extract_archive(
             eprintln!("{}", e);
         }
     });
-    let decoder = GzDecoder::new(tracker);
-    let mut archive = Archive::new(decoder);
+    let mut reader = BufReader::new(tracker);
+    let format = detect_format(&archive_path.to_string_lossy(), &mut reader)
+        .map_err(|e| format!("Failed to detect archive format: {}", e))?;
 
-    archive
-        .unpack(destination_path)
+    unpack_file(reader, format, destination_path)
         .map_err(|e| format!("Failed to extract archive: {}", e))?;
 
     Ok(())
 }
 
+#[derive(Serialize)]
+struct StreamingInstallError {
+    message: String,
+    // Tells the caller that the same archive may still install in buffered mode
+    retryable: bool,
+    // Set when the archive did not match the published hash
+    integrity: Option<IntegrityError>,
+    // Set when a game runs from the destination
+    game_running: Option<process_manager::GameRunning>,
+}
+
+impl StreamingInstallError {
+    fn fatal(message: String) -> Self {
+        Self {
+            message,
+            retryable: false,
+            integrity: None,
+            game_running: None,
+        }
+    }
+
+    fn retryable(message: String) -> Self {
+        Self {
+            message,
+            retryable: true,
+            integrity: None,
+            game_running: None,
+        }
+    }
+
+    fn integrity(error: IntegrityError) -> Self {
+        Self {
+            message: error.message(),
+            retryable: false,
+            integrity: Some(error),
+            game_running: None,
+        }
+    }
+
+    fn game_running(error: process_manager::GameRunning) -> Self {
+        Self {
+            message: error.to_string(),
+            retryable: false,
+            integrity: None,
+            game_running: Some(error),
+        }
+    }
+}
+
+/// Downloads an archive to disk for the buffered install, the fallback when
+/// streaming fails. Checked against `expected_hash` while it is written.
 #[tauri::command]
-async fn upload_file_as_form_data(
+async fn download_archive(
+    http: tauri::State<'_, HttpClient>,
     url: String,
     file_path: String,
-    auth_token: Option<String>,
+    expected_hash: Option<String>,
     progress_channel: tauri::ipc::Channel<ProgressCallbackData>,
     speed_update_interval: Option<f64>,
 ) -> Result<(), String> {
-    let file_path = Path::new(&file_path);
-
-    // Check if file exists
-    if !file_path.exists() {
-        return Err(format!("File does not exist: {}", file_path.display()));
-    }
-
-    // Get file metadata
-    let metadata =
-        std::fs::metadata(file_path).map_err(|e| format!("Failed to get file metadata: {}", e))?;
-    let file_size = metadata.len();
-
-    // Get filename
-    let filename = file_path
+    let file_path = PathBuf::from(file_path);
+    let file_name = file_path
         .file_name()
-        .and_then(|n| n.to_str())
+        .map(|n| n.to_string_lossy().to_string())
         .ok_or_else(|| "Invalid filename".to_string())?;
 
-    // Open file
-    let file = tokio::fs::File::open(file_path)
-        .await
-        .map_err(|e| format!("Failed to open file: {}", e))?;
-
-    let file_meta = file
-        .metadata()
-        .await
-        .map_err(|e| format!("Failed to get file metadata: {}", e))?;
-
-    let mut read_bytes = 0_u64;
-    let total_bytes = file_meta.len();
-
-    let progress_channel_clone = progress_channel.clone();
-    let mut uploading_speed_rate = RateMeter::new(Duration::from_secs_f64(
-        speed_update_interval.unwrap_or(0.0),
+    let mut last_bytes = 0_u64;
+    let mut download_rate = RateMeter::new(Duration::from_secs_f64(
+        speed_update_interval.unwrap_or(1.0),
     ));
-    let tracker = TrackingTokioStream::new(file, move |read_len| {
-        read_bytes += read_len;
-        uploading_speed_rate.add_value(read_len);
+    let on_progress = |current_bytes: u64, total_bytes: u64| {
+        // Progress starts over when the download is retried
+        download_rate.add_value(current_bytes.saturating_sub(last_bytes));
+        last_bytes = current_bytes;
 
-        let res = progress_channel_clone
+        let res = progress_channel
             .send(ProgressCallbackData {
-                current_bytes: read_bytes,
+                current_bytes,
                 total_bytes,
-                delta_per_second: uploading_speed_rate.get_rate() as u64,
+                delta_per_second: download_rate.get_rate() as u64,
             })
-            .map_err(|e| format!("Failed to send update progress to channel: {}", e));
+            .map_err(|e| format!("Failed to emit downloading progress info: {}", e));
         if let Err(e) = res {
             eprintln!("{}", e);
         }
+    };
+
+    match expected_hash {
+        Some(expected_hash) => {
+            download_verified(
+                &http,
+                &url,
+                &file_name,
+                &file_path,
+                &expected_hash,
+                on_progress,
+            )
+            .await
+        }
+        None => download_unverified(&http, &url, &file_path, on_progress).await,
+    }
+    .map_err(|e| e.message)
+}
+
+/// Streams the archive from the network into `destination_path`. With an
+/// `algorithm` the archive is hashed on the way and its digest returned.
+async fn stream_archive(
+    http: &HttpClient,
+    url: &str,
+    file_name: &str,
+    destination_path: &Path,
+    algorithm: Option<HashAlgorithm>,
+    progress_channel: tauri::ipc::Channel<ProgressCallbackData>,
+    speed_update_interval: Option<f64>,
+) -> Result<Option<String>, StreamingInstallError> {
+    let response = http
+        .send(http.client().get(url))
+        .await
+        .map_err(|e| StreamingInstallError::retryable(format!("Failed to send request: {}", e)))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let message = format!("Download failed with status {}", status);
+        return Err(if is_retryable_status(status) {
+            StreamingInstallError::retryable(message)
+        } else {
+            StreamingInstallError::fatal(message)
+        });
+    }
+
+    // Progress is measured on network bytes, so it matches the buffered download
+    let total_bytes = response.content_length().unwrap_or(0);
+    let received_bytes = Arc::new(AtomicU64::new(0));
+    let network_failed = Arc::new(AtomicBool::new(false));
+
+    let received_bytes_clone = received_bytes.clone();
+    let network_failed_clone = network_failed.clone();
+    let mut download_rate = RateMeter::new(Duration::from_secs_f64(
+        speed_update_interval.unwrap_or(1.0),
+    ));
+    let body = response.bytes_stream().map(move |chunk| match chunk {
+        Ok(bytes) => {
+            let delta = bytes.len() as u64;
+            let current_bytes = received_bytes_clone.fetch_add(delta, Ordering::Relaxed) + delta;
+            download_rate.add_value(delta);
+
+            let res = progress_channel
+                .send(ProgressCallbackData {
+                    current_bytes,
+                    total_bytes: total_bytes.max(current_bytes),
+                    delta_per_second: download_rate.get_rate() as u64,
+                })
+                .map_err(|e| format!("Failed to emit downloading progress info: {}", e));
+            if let Err(e) = res {
+                eprintln!("{}", e);
+            }
+
+            Ok(bytes)
+        }
+        Err(e) => {
+            network_failed_clone.store(true, Ordering::Relaxed);
+            Err(std::io::Error::other(e))
+        }
     });
 
+    let format_hint = ArchiveFormat::from_name(file_name);
+    let (reader, digest_slot) = HashingReader::new(
+        StreamReader::new(Box::pin(body)),
+        algorithm.unwrap_or_default(),
+    );
+    let reader = SyncIoBridge::new(reader);
+    let file_name = file_name.to_string();
+    let destination_path = destination_path.to_path_buf();
+    let result = tokio::task::spawn_blocking(move || {
+        let mut reader = BufReader::new(reader);
+        let format = detect_format(&file_name, &mut reader)?;
+        unpack_stream(&mut reader, format, &destination_path)?;
+        // The digest covers the whole body, archives may end with padding the unpacker skips
+        std::io::copy(&mut reader, &mut std::io::sink()).map(|_| ())
+    })
+    .await
+    .map_err(|e| StreamingInstallError::fatal(format!("Extraction task failed: {}", e)))?;
+
+    if let Err(e) = result {
+        let message = format!("Failed to extract archive: {}", e);
+        let truncated = total_bytes > 0 && received_bytes.load(Ordering::Relaxed) < total_bytes;
+
+        // Network drops and zip features that need the central directory
+        // can still succeed once the archive is fully on disk
+        return Err(
+            if network_failed.load(Ordering::Relaxed)
+                || truncated
+                || format_hint == Some(ArchiveFormat::Zip)
+            {
+                StreamingInstallError::retryable(message)
+            } else {
+                StreamingInstallError::fatal(message)
+            },
+        );
+    }
+
+    let digest = digest_slot.lock().unwrap().take();
+    Ok(algorithm.and(digest))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+async fn download_and_extract_archive(
+    http: tauri::State<'_, HttpClient>,
+    manager: tauri::State<'_, process_manager::ProcessManager>,
+    url: String,
+    file_name: String,
+    destination_path: String,
+    expected_hash: Option<String>,
+    progress_channel: tauri::ipc::Channel<ProgressCallbackData>,
+    speed_update_interval: Option<f64>,
+) -> Result<(), StreamingInstallError> {
+    let destination_path = PathBuf::from(destination_path);
+    let _reservation = manager
+        .reserve_install(&destination_path)
+        .map_err(StreamingInstallError::game_running)?;
+    std::fs::create_dir_all(&destination_path).map_err(|e| {
+        StreamingInstallError::fatal(format!("Failed to create destination directory: {}", e))
+    })?;
+
+    let Some(expected_hash) = expected_hash else {
+        return stream_archive(
+            &http,
+            &url,
+            &file_name,
+            &destination_path,
+            None,
+            progress_channel,
+            speed_update_interval,
+        )
+        .await
+        .map(|_| ());
+    };
+
+    let (algorithm, _) = HashAlgorithm::parse_tagged(&expected_hash).ok_or_else(|| {
+        StreamingInstallError::fatal(format!("Unsupported hash: {}", expected_hash))
+    })?;
+
+    // Unverified files must never reach the install dir, so the archive is
+    // extracted next to it and moved in once its digest matches
+    let staging = staging_dir(&destination_path, &file_name);
+    let mut attempt = 0;
+    loop {
+        let _ = std::fs::remove_dir_all(&staging);
+        std::fs::create_dir_all(&staging).map_err(|e| {
+            StreamingInstallError::fatal(format!("Failed to create staging directory: {}", e))
+        })?;
+
+        let result = stream_archive(
+            &http,
+            &url,
+            &file_name,
+            &staging,
+            Some(algorithm),
+            progress_channel.clone(),
+            speed_update_interval,
+        )
+        .await;
+        let digest = match result {
+            Ok(digest) => digest.unwrap_or_default(),
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&staging);
+                return Err(e);
+            }
+        };
+
+        if digest.eq_ignore_ascii_case(&expected_hash) {
+            let result = move_into(&staging, &destination_path);
+            let _ = std::fs::remove_dir_all(&staging);
+            return result.map_err(|e| {
+                StreamingInstallError::fatal(format!("Failed to move extracted files: {}", e))
+            });
+        }
+
+        let _ = std::fs::remove_dir_all(&staging);
+        let error = IntegrityError {
+            file_name: file_name.clone(),
+            expected: expected_hash.clone(),
+            actual: digest,
+        };
+
+        attempt += 1;
+        if attempt > INTEGRITY_RETRIES {
+            return Err(StreamingInstallError::integrity(error));
+        }
+
+        eprintln!("{}, downloading again", error.message());
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FormDataFile {
+    path: String,
+    // Defaults to "files" as PocketBase expects
+    field_name: Option<String>,
+    file_name: Option<String>,
+    // Guessed from the file extension when not set
+    mime_type: Option<String>,
+}
+
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+async fn upload_file_as_form_data(
+    http: tauri::State<'_, HttpClient>,
+    url: String,
+    file_path: Option<String>,
+    files: Option<Vec<FormDataFile>>,
+    auth_token: Option<String>,
+    method: Option<String>,
+    fields: Option<HashMap<String, String>>,
+    headers: Option<HashMap<String, String>>,
+    integrity: Option<UploadIntegrityOptions>,
+    progress_channel: tauri::ipc::Channel<ProgressCallbackData>,
+    speed_update_interval: Option<f64>,
+) -> Result<(), String> {
+    // `file_path` is the single-file shorthand used by the build upload
+    let mut files = files.unwrap_or_default();
+    if let Some(file_path) = file_path {
+        files.insert(
+            0,
+            FormDataFile {
+                path: file_path,
+                field_name: None,
+                file_name: None,
+                mime_type: None,
+            },
+        );
+    }
+
+    if files.is_empty() {
+        return Err("No files to upload".to_string());
+    }
+
+    let method = reqwest::Method::from_bytes(method.as_deref().unwrap_or("PATCH").as_bytes())
+        .map_err(|e| format!("Invalid HTTP method: {}", e))?;
+
+    // Collect file sizes first so progress covers the whole request
+    let mut total_bytes = 0_u64;
+    for file in &files {
+        let file_path = Path::new(&file.path);
+
+        // Check if file exists
+        if !file_path.exists() {
+            return Err(format!("File does not exist: {}", file_path.display()));
+        }
+
+        total_bytes += std::fs::metadata(file_path)
+            .map_err(|e| format!("Failed to get file metadata: {}", e))?
+            .len();
+    }
+
+    // Shared by the parts, they are read one after another by the request body
+    let upload_progress = Arc::new(std::sync::Mutex::new((
+        RateMeter::new(Duration::from_secs_f64(
+            speed_update_interval.unwrap_or(0.0),
+        )),
+        0_u64,
+    )));
+
     // Create multipart form
     let mut form = reqwest::multipart::Form::new();
 
-    // Create a part with the file stream
-    let part_body = reqwest::Body::wrap_stream(tracker);
-    let part = reqwest::multipart::Part::stream_with_length(part_body, file_size)
-        .file_name(filename.to_string())
-        .mime_str("application/gzip")
-        .map_err(|e| format!("Failed to create multipart part: {}", e))?;
+    for (name, value) in fields.unwrap_or_default() {
+        form = form.text(name, value);
+    }
+
+    // Digests of the files in upload order, filled while the body is sent
+    let mut digests = vec![];
+
+    for file in files {
+        let file_path = Path::new(&file.path);
+
+        // Get filename
+        let filename = match file.file_name {
+            Some(filename) => filename,
+            None => file_path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| "Invalid filename".to_string())?
+                .to_string(),
+        };
+
+        let mime_type = file.mime_type.unwrap_or_else(|| {
+            mime_guess::from_path(file_path)
+                .first_or_octet_stream()
+                .to_string()
+        });
+
+        // Open file
+        let file_handle = tokio::fs::File::open(file_path)
+            .await
+            .map_err(|e| format!("Failed to open file: {}", e))?;
+
+        let file_size = file_handle
+            .metadata()
+            .await
+            .map_err(|e| format!("Failed to get file metadata: {}", e))?
+            .len();
+
+        let (file_handle, digest_slot) = HashingReader::new(file_handle, UPLOAD_HASH_ALGORITHM);
+
+        let progress_channel_clone = progress_channel.clone();
+        let upload_progress = upload_progress.clone();
+        let tracker = TrackingTokioStream::new(file_handle, move |read_len| {
+            let mut guard = upload_progress.lock().unwrap();
+            let (uploading_speed_rate, read_bytes) = &mut *guard;
+            *read_bytes += read_len;
+            uploading_speed_rate.add_value(read_len);
+
+            let res = progress_channel_clone
+                .send(ProgressCallbackData {
+                    current_bytes: *read_bytes,
+                    total_bytes,
+                    delta_per_second: uploading_speed_rate.get_rate() as u64,
+                })
+                .map_err(|e| format!("Failed to send update progress to channel: {}", e));
+            if let Err(e) = res {
+                eprintln!("{}", e);
+            }
+        });
+
+        // Create a part with the file stream
+        let part_body = reqwest::Body::wrap_stream(tracker);
+        let part = reqwest::multipart::Part::stream_with_length(part_body, file_size)
+            .file_name(filename.clone())
+            .mime_str(&mime_type)
+            .map_err(|e| format!("Failed to create multipart part: {}", e))?;
 
-    // Add the part with field name "files" (as PocketBase expects)
-    form = form.part("files", part);
+        let field_name = file.field_name.unwrap_or_else(|| "files".to_string());
+        form = form.part(field_name.clone(), part);
+
+        digests.push((field_name, filename, digest_slot));
+    }
 
     // Build the request
-    let client = reqwest::Client::new();
-    let mut request = client.patch(&url).multipart(form);
+    let mut request = http.client().request(method, &url).multipart(form);
 
     // Add authorization header if provided
     if let Some(token) = auth_token {
         request = request.header("Authorization", format!("Bearer {}", token));
     }
 
+    for (name, value) in headers.unwrap_or_default() {
+        request = request.header(name, value);
+    }
+
     // Send request with progress tracking
     let response = request
         .send()
@@ -320,8 +751,8 @@ async fn upload_file_as_form_data(
 
     // Send progress update
     let _ = progress_channel.send(ProgressCallbackData {
-        current_bytes: file_size,
-        total_bytes: file_size,
+        current_bytes: total_bytes,
+        total_bytes,
         delta_per_second: 0,
     });
 
@@ -338,6 +769,203 @@ async fn upload_file_as_form_data(
         ));
     }
 
+    if let Some(options) = integrity {
+        let uploaded = digests
+            .into_iter()
+            .map(|(field, file_name, slot)| {
+                let digest = slot.lock().unwrap().clone().ok_or_else(|| {
+                    format!("Integrity check failed: {} was not fully sent", file_name)
+                })?;
+                Ok(UploadedFile {
+                    field,
+                    file_name,
+                    digest,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let body = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read upload response: {}", e))?;
+        verify_reported_hashes(&body, &options, &uploaded)?;
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct PublishProgressData {
+    // Source bytes that went through the packer
+    current_bytes: u64,
+    total_bytes: u64,
+    // Compressed bytes handed to the HTTP client
+    uploaded_bytes: u64,
+    delta_per_second: u64,
+}
+
+// Size of the in-memory pipe between the packer and the request body
+const PUBLISH_PIPE_CAPACITY: usize = 1024 * 1024;
+
+#[tauri::command]
+async fn publish_folder(
+    http: tauri::State<'_, HttpClient>,
+    url: String,
+    folder_path: String,
+    auth_token: Option<String>,
+    integrity: Option<UploadIntegrityOptions>,
+    progress_channel: tauri::ipc::Channel<PublishProgressData>,
+    speed_update_interval: Option<f64>,
+) -> Result<(), String> {
+    let source_path = validate_source_folder(&folder_path)?.to_path_buf();
+    validate_build_profiles(&source_path)?;
+
+    let folder_name = source_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| n.to_string())
+        .ok_or_else(|| "Invalid folder name".to_string())?;
+    let archive_name = format!("{}.tar.gz", folder_name);
+
+    let (all_entries, total_bytes) = collect_files(&source_path)?;
+
+    let packed_bytes = Arc::new(AtomicU64::new(0));
+    let (pipe_writer, pipe_reader) = tokio::io::duplex(PUBLISH_PIPE_CAPACITY);
+
+    // Packing runs on a blocking thread and writes into the pipe
+    let packed_bytes_clone = packed_bytes.clone();
+    let pipe_writer = SyncIoBridge::new(pipe_writer);
+    // Filled by the packing thread, the manifest part is read after the archive
+    let manifest_slot: Arc<Mutex<Option<Vec<u8>>>> = Arc::default();
+    let manifest_slot_clone = manifest_slot.clone();
+    let packing = tokio::task::spawn_blocking(move || -> Result<(), String> {
+        let gz_encoder = GzEncoder::new(pipe_writer, Compression::default());
+        let writer = BufWriter::new(gz_encoder);
+        let tracker = TrackingWriter::new(writer, move |buf| {
+            packed_bytes_clone.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        });
+        let mut tar_builder = Builder::new(tracker);
+
+        let manifest = append_files(&mut tar_builder, &all_entries, &source_path, None)?;
+        let manifest_json = append_manifest(&mut tar_builder, &manifest)?;
+        *manifest_slot_clone.lock().unwrap() = Some(manifest_json);
+
+        let buf_writer = tar_builder
+            .into_inner()
+            .map_err(|e| format!("Failed to finalize archive: {}", e))?
+            .into_inner();
+        let mut pipe_writer = buf_writer
+            .into_inner()
+            .map_err(|e| format!("Failed to get gzip encoder: {}", e))?
+            .finish()
+            .map_err(|e| format!("Failed to finalize compression: {}", e))?;
+
+        // Closing the pipe ends the request body
+        pipe_writer
+            .shutdown()
+            .map_err(|e| format!("Failed to close upload stream: {}", e))
+    });
+
+    let uploaded_bytes = Arc::new(AtomicU64::new(0));
+    let uploaded_bytes_clone = uploaded_bytes.clone();
+    let progress_channel_clone = progress_channel.clone();
+    let mut uploading_speed_rate = RateMeter::new(Duration::from_secs_f64(
+        speed_update_interval.unwrap_or(1.0),
+    ));
+    let (pipe_reader, digest_slot) = HashingReader::new(pipe_reader, UPLOAD_HASH_ALGORITHM);
+    let tracker = TrackingTokioStream::new(pipe_reader, move |read_len| {
+        let uploaded_bytes = uploaded_bytes_clone.fetch_add(read_len, Ordering::Relaxed) + read_len;
+        uploading_speed_rate.add_value(read_len);
+
+        let res = progress_channel_clone
+            .send(PublishProgressData {
+                current_bytes: packed_bytes.load(Ordering::Relaxed).min(total_bytes),
+                total_bytes,
+                uploaded_bytes,
+                delta_per_second: uploading_speed_rate.get_rate() as u64,
+            })
+            .map_err(|e| format!("Failed to send publish progress to channel: {}", e));
+        if let Err(e) = res {
+            eprintln!("{}", e);
+        }
+    });
+
+    // The archive size is unknown up front, so the part is sent chunked
+    let part = reqwest::multipart::Part::stream(reqwest::Body::wrap_stream(tracker))
+        .file_name(archive_name.clone())
+        .mime_str("application/gzip")
+        .map_err(|e| format!("Failed to create multipart part: {}", e))?;
+    let mut form = reqwest::multipart::Form::new().part("files", part);
+
+    // The manifest is complete once the archive part has been read to the end
+    let manifest = futures_util::stream::once(async move {
+        manifest_slot.lock().unwrap().take().ok_or_else(|| {
+            std::io::Error::other("Archive was not fully packed before its manifest was sent")
+        })
+    });
+    let manifest_part = reqwest::multipart::Part::stream(reqwest::Body::wrap_stream(manifest))
+        .file_name(format!("{}.manifest.json", folder_name))
+        .mime_str("application/json")
+        .map_err(|e| format!("Failed to create multipart part: {}", e))?;
+    form = form.part("manifest", manifest_part);
+
+    let mut request = http.client().patch(&url).multipart(form);
+
+    if let Some(token) = auth_token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+
+    // A packing failure drops the in-flight request instead of sending a truncated archive
+    let sending = async {
+        request
+            .send()
+            .await
+            .map_err(|e| format!("Failed to send request: {}", e))
+    };
+    let packing = async {
+        packing
+            .await
+            .map_err(|e| format!("Packing task failed: {}", e))?
+    };
+    let (response, ()) = tokio::try_join!(sending, packing)?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        return Err(format!(
+            "Upload failed with status {}: {}",
+            status, error_text
+        ));
+    }
+
+    if let Some(options) = integrity {
+        let digest = digest_slot
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| "Integrity check failed: archive was not fully sent".to_string())?;
+        let body = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read upload response: {}", e))?;
+        let uploaded = UploadedFile {
+            field: "files".to_string(),
+            file_name: archive_name,
+            digest,
+        };
+        verify_reported_hashes(&body, &options, &[uploaded])?;
+    }
+
+    let _ = progress_channel.send(PublishProgressData {
+        current_bytes: total_bytes,
+        total_bytes,
+        uploaded_bytes: uploaded_bytes.load(Ordering::Relaxed),
+        delta_per_second: 0,
+    });
+
     Ok(())
 }
 
@@ -424,6 +1052,12 @@ fn should_set_webkit_workaround() -> bool {
     }
 }
 
+/// Runs a game log writer instead of the launcher when the binary was started
+/// as one. Returns false when the launcher should start.
+pub fn run_game_log_writer() -> bool {
+    game_logs::run_log_writer(std::env::args_os())
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     if should_set_webkit_workaround() {
@@ -473,6 +1107,36 @@ pub fn run() {
             }
             let saved_state = load_window_state(app.handle()).ok().flatten();
 
+            // Broken network settings must not keep the launcher from starting
+            let network_settings = network_settings::load_network_settings(app.handle())
+                .unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    Default::default()
+                });
+            let http_client = HttpClient::new(HttpClientConfig::default(), &network_settings)
+                .or_else(|e| {
+                    eprintln!("{}", e);
+                    HttpClient::new(HttpClientConfig::default(), &Default::default())
+                })?;
+            app.manage(http_client);
+            app.manage(process_manager::ProcessManager::default());
+
+            let app_data_dir = app.path().app_data_dir()?;
+            app.manage(tus::TusUploads::new(tus::TusUploadStore::load(
+                app_data_dir.join("resumable_uploads.json"),
+            )));
+            app.manage(playtime::Playtime::new(playtime::PlaytimeStore::load(
+                app_data_dir.join("playtime.jsonl"),
+            )));
+            playtime::spawn_heartbeat(app.handle().clone());
+            app.manage(launch_profiles::LaunchProfiles::new(
+                launch_profiles::LaunchProfileStore::load(
+                    app_data_dir.join("launch_profiles.json"),
+                ),
+            ));
+            // Shared by every installed game and branch
+            app.manage(chunk_store::ChunkStore::new(app_data_dir.join("chunks")));
+
             let window = tauri::WebviewWindowBuilder::from_config(
                 app.handle(),
                 &app.config().app.windows[0],
@@ -604,9 +1268,47 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             archive_and_compress_folder,
+            get_folder_size,
             read_file_bytes,
             extract_archive,
-            upload_file_as_form_data
+            download_and_extract_archive,
+            download_archive,
+            upload_file_as_form_data,
+            publish_folder,
+            tus::upload_file_resumable,
+            tus::has_resumable_upload,
+            s3_multipart::upload_file_s3_multipart,
+            network_settings::get_network_settings,
+            network_settings::set_network_settings,
+            network_settings::test_connection,
+            verify::verify_install,
+            repair::repair_install,
+            delta::create_delta_patch,
+            delta::apply_delta_update,
+            chunked_build::pack_chunked_build,
+            chunked_build::upload_chunks,
+            chunked_build::install_chunked_build,
+            update_preview::preview_update,
+            process_manager::launch_app,
+            process_manager::stop_app,
+            process_manager::list_running,
+            process_manager::watch_resource_usage,
+            process_manager::get_install_lock,
+            process_manager::remove_install_dir,
+            launch_profiles::get_launch_profiles,
+            launch_profiles::set_launch_profiles,
+            launch_profiles::set_game_wrappers,
+            launch_profiles::set_game_compat_tool,
+            launch_profiles::set_game_instance_policy,
+            launch_settings::get_launch_settings,
+            launch_settings::set_launch_settings,
+            compat_tools::select_build,
+            compat_tools::get_compat_prefix,
+            compat_tools::delete_compat_prefix,
+            playtime::get_playtime,
+            game_logs::list_game_logs,
+            game_logs::read_game_log,
+            game_logs::export_game_logs
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
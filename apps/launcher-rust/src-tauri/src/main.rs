@@ -2,5 +2,9 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // The launcher binary also keeps the logs of running games
+    if zapuskalka_launcher_rust_lib::run_game_log_writer() {
+        return;
+    }
     zapuskalka_launcher_rust_lib::run()
 }
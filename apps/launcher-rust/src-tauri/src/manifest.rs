@@ -0,0 +1,334 @@
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::chunking::ChunkParams;
+use crate::hashing::{HashAlgorithm, StreamingHasher};
+
+/// Name of the manifest at the root of every packed build
+pub const MANIFEST_FILE_NAME: &str = ".zapuskalka-manifest.json";
+
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Encoding of the manifest file written next to a packed archive. The one
+/// inside the archive and the one uploaded to the backend are always JSON.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestFormat {
+    #[default]
+    Json,
+    Cbor,
+}
+
+impl ManifestFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ManifestFormat::Json => "json",
+            ManifestFormat::Cbor => "cbor",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ManifestChunk {
+    pub offset: u64,
+    pub size: u64,
+    pub hash: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ManifestEntry {
+    // Relative to the build root, always with `/` separators
+    pub path: String,
+    pub size: u64,
+    pub mode: u32,
+    // Seconds since the epoch, kept by tar on extraction
+    #[serde(default)]
+    pub mtime: Option<u64>,
+    // `blake3:<hex>`
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<ManifestChunk>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Manifest {
+    pub version: u32,
+    // Size of the chunks in `ManifestEntry::chunks`, none when they were not hashed
+    pub chunk_size: Option<u64>,
+    // Set instead of `chunk_size` when chunks were cut by content, their
+    // hashes then name the chunks in a chunk store
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunking: Option<ChunkParams>,
+    pub total_bytes: u64,
+    pub files: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn new(chunk_size: Option<u64>) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            chunk_size: chunk_size.filter(|size| *size > 0),
+            chunking: None,
+            total_bytes: 0,
+            files: vec![],
+        }
+    }
+
+    pub fn push(&mut self, entry: ManifestEntry) {
+        self.total_bytes += entry.size;
+        self.files.push(entry);
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, String> {
+        serde_json::to_vec_pretty(self).map_err(|e| format!("Failed to serialize manifest: {}", e))
+    }
+
+    pub fn to_cbor(&self) -> Result<Vec<u8>, String> {
+        let mut cbor = vec![];
+        ciborium::into_writer(self, &mut cbor)
+            .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+        Ok(cbor)
+    }
+
+    pub fn encode(&self, format: ManifestFormat) -> Result<Vec<u8>, String> {
+        match format {
+            ManifestFormat::Json => self.to_json(),
+            ManifestFormat::Cbor => self.to_cbor(),
+        }
+    }
+
+    /// Identifies the build by its content, independent of file order and
+    /// of when it was packed
+    pub fn content_hash(&self) -> String {
+        let mut files: Vec<&ManifestEntry> = self.files.iter().collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut hasher = StreamingHasher::new(HashAlgorithm::Blake3);
+        for file in files {
+            hasher.update(file.path.as_bytes());
+            hasher.update(b"\0");
+            hasher.update(file.hash.as_bytes());
+            hasher.update(b"\n");
+        }
+        hasher.finalize()
+    }
+
+    pub fn get(&self, path: &str) -> Option<&ManifestEntry> {
+        self.files.iter().find(|f| f.path == path)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read(path)
+            .map_err(|e| format!("Failed to read manifest {}: {}", path.display(), e))?;
+        Self::parse(&data)
+    }
+
+    /// Reads a JSON or CBOR manifest, a JSON one starts with `{`
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let is_json = data
+            .iter()
+            .find(|b| !b.is_ascii_whitespace())
+            .is_some_and(|b| *b == b'{');
+        let manifest: Manifest = if is_json {
+            serde_json::from_slice(data).map_err(|e| format!("Failed to parse manifest: {}", e))?
+        } else {
+            ciborium::from_reader(data).map_err(|e| format!("Failed to parse manifest: {}", e))?
+        };
+
+        if manifest.version > MANIFEST_VERSION {
+            return Err(format!(
+                "Unsupported manifest version {}, update the launcher",
+                manifest.version
+            ));
+        }
+
+        Ok(manifest)
+    }
+}
+
+/// Manifest path of a file relative to the build root
+pub fn manifest_path(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Reader that hashes the whole file and every `chunk_size` piece of it
+/// while the data passes through, so packing reads each file once
+pub struct ManifestHasher<R> {
+    inner: R,
+    file_hasher: StreamingHasher,
+    chunk_size: Option<u64>,
+    chunk_hasher: StreamingHasher,
+    chunk_offset: u64,
+    chunk_len: u64,
+    chunks: Vec<ManifestChunk>,
+}
+
+impl<R: Read> ManifestHasher<R> {
+    pub fn new(inner: R, chunk_size: Option<u64>) -> Self {
+        Self {
+            inner,
+            file_hasher: StreamingHasher::new(HashAlgorithm::Blake3),
+            chunk_size,
+            chunk_hasher: StreamingHasher::new(HashAlgorithm::Blake3),
+            chunk_offset: 0,
+            chunk_len: 0,
+            chunks: vec![],
+        }
+    }
+
+    fn finish_chunk(&mut self) {
+        let hasher = std::mem::replace(
+            &mut self.chunk_hasher,
+            StreamingHasher::new(HashAlgorithm::Blake3),
+        );
+        self.chunks.push(ManifestChunk {
+            offset: self.chunk_offset,
+            size: self.chunk_len,
+            hash: hasher.finalize(),
+        });
+        self.chunk_offset += self.chunk_len;
+        self.chunk_len = 0;
+    }
+
+    /// Returns the file hash and the chunk hashes
+    pub fn finalize(mut self) -> (String, Vec<ManifestChunk>) {
+        if self.chunk_len > 0 {
+            self.finish_chunk();
+        }
+        (self.file_hasher.finalize(), self.chunks)
+    }
+}
+
+impl<R: Read> Read for ManifestHasher<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.file_hasher.update(&buf[..read]);
+
+        if let Some(chunk_size) = self.chunk_size {
+            let mut data = &buf[..read];
+            while !data.is_empty() {
+                let take = data.len().min((chunk_size - self.chunk_len) as usize);
+                self.chunk_hasher.update(&data[..take]);
+                self.chunk_len += take as u64;
+                data = &data[take..];
+
+                if self.chunk_len == chunk_size {
+                    self.finish_chunk();
+                }
+            }
+        }
+
+        Ok(read)
+    }
+}
+
+/// Resolves a manifest path inside `root`, refusing absolute paths and `..`
+pub fn local_path(root: &Path, path: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(path);
+    let is_safe = relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)));
+
+    if path.is_empty() || !is_safe {
+        return Err(format!("Unsafe path in manifest: {}", path));
+    }
+
+    Ok(root.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_manifest() -> Manifest {
+        let mut manifest = Manifest::new(Some(4));
+        for (path, data) in [("game", &b"0123456789"[..]), ("data/level.bin", b"abc")] {
+            let mut hasher = ManifestHasher::new(data, manifest.chunk_size);
+            std::io::copy(&mut hasher, &mut std::io::sink()).unwrap();
+            let (hash, chunks) = hasher.finalize();
+            manifest.push(ManifestEntry {
+                path: path.to_string(),
+                size: data.len() as u64,
+                mode: 0o755,
+                mtime: Some(1_700_000_000),
+                hash,
+                chunks,
+            });
+        }
+        manifest
+    }
+
+    #[test]
+    fn round_trips_through_json_and_cbor() {
+        let manifest = test_manifest();
+
+        for format in [ManifestFormat::Json, ManifestFormat::Cbor] {
+            let encoded = manifest.encode(format).unwrap();
+            assert_eq!(Manifest::parse(&encoded).unwrap(), manifest, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn hashes_files_and_fixed_size_chunks() {
+        let manifest = test_manifest();
+        let game = manifest.get("game").unwrap();
+
+        assert_eq!(manifest.total_bytes, 13);
+        assert_eq!(
+            game.hash,
+            format!("blake3:{}", blake3::hash(b"0123456789").to_hex())
+        );
+        let chunks: Vec<(u64, u64)> = game.chunks.iter().map(|c| (c.offset, c.size)).collect();
+        assert_eq!(chunks, [(0, 4), (4, 4), (8, 2)]);
+        assert_eq!(
+            game.chunks[2].hash,
+            format!("blake3:{}", blake3::hash(b"89").to_hex())
+        );
+    }
+
+    #[test]
+    fn content_hash_ignores_file_order() {
+        let manifest = test_manifest();
+        let mut reversed = manifest.clone();
+        reversed.files.reverse();
+
+        assert_eq!(manifest.content_hash(), reversed.content_hash());
+    }
+
+    #[test]
+    fn rejects_newer_versions_and_garbage() {
+        let mut manifest = test_manifest();
+        manifest.version = MANIFEST_VERSION + 1;
+
+        let error = Manifest::parse(&manifest.to_json().unwrap()).err().unwrap();
+        assert!(error.contains("Unsupported manifest version"), "{}", error);
+        assert!(Manifest::parse(&manifest.to_cbor().unwrap()).is_err());
+        assert!(Manifest::parse(b"{\"version\": 1}").is_err());
+        assert!(Manifest::parse(b"\xff\x00").is_err());
+    }
+
+    #[test]
+    fn local_path_refuses_paths_outside_the_root() {
+        let root = Path::new("/games/app");
+
+        assert_eq!(
+            local_path(root, "data/level.bin").unwrap(),
+            root.join("data/level.bin")
+        );
+        for path in [
+            "",
+            "../secret",
+            "data/../../secret",
+            "/etc/passwd",
+            "./game",
+        ] {
+            assert!(local_path(root, path).is_err(), "{}", path);
+        }
+    }
+}
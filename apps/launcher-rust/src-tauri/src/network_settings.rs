@@ -0,0 +1,455 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::http_client::HttpClient;
+use crate::json_store::write_atomically;
+
+const NETWORK_SETTINGS_FILE_NAME: &str = "network_settings.json";
+const PROXY_PASSWORD_FILE_NAME: &str = "proxy_password";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyKind {
+    Http,
+    Https,
+    Socks5,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProxySettings {
+    pub kind: ProxyKind,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Hosts, domains (`.example.com`) and CIDR ranges that skip the proxy
+    #[serde(default)]
+    pub bypass: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct NetworkSettings {
+    pub proxy: Option<ProxySettings>,
+    // Paths to PEM files with CA certificates trusted in addition to the system ones
+    #[serde(default)]
+    pub extra_ca_certificates: Vec<String>,
+}
+
+impl ProxySettings {
+    fn to_proxy(&self) -> Result<reqwest::Proxy, String> {
+        let scheme = match self.kind {
+            ProxyKind::Http => "http",
+            ProxyKind::Https => "https",
+            // Resolve hostnames on the proxy side, like browsers do
+            ProxyKind::Socks5 => "socks5h",
+        };
+
+        let mut url = reqwest::Url::parse(&format!("{}://{}:{}", scheme, self.host, self.port))
+            .map_err(|e| format!("Invalid proxy address: {}", e))?;
+
+        // Credentials in the URL work for both HTTP and SOCKS5 proxies
+        if let Some(username) = self.username.as_deref().filter(|u| !u.is_empty()) {
+            url.set_username(username)
+                .map_err(|_| "Invalid proxy username".to_string())?;
+            url.set_password(self.password.as_deref())
+                .map_err(|_| "Invalid proxy password".to_string())?;
+        }
+
+        let proxy =
+            reqwest::Proxy::all(url).map_err(|e| format!("Invalid proxy settings: {}", e))?;
+
+        Ok(proxy.no_proxy(reqwest::NoProxy::from_string(&self.bypass.join(","))))
+    }
+}
+
+impl NetworkSettings {
+    /// Applies the proxy and extra root certificates to a client builder
+    pub fn apply(
+        &self,
+        mut builder: reqwest::ClientBuilder,
+    ) -> Result<reqwest::ClientBuilder, String> {
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.to_proxy()?);
+        }
+
+        for path in &self.extra_ca_certificates {
+            let pem = std::fs::read(path)
+                .map_err(|e| format!("Failed to read CA certificate {}: {}", path, e))?;
+            let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+                .map_err(|e| format!("Failed to parse CA certificate {}: {}", path, e))?;
+
+            if certificates.is_empty() {
+                return Err(format!("No certificates found in {}", path));
+            }
+
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        Ok(builder)
+    }
+}
+
+fn get_app_config_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let app_config_dir = app
+        .path()
+        .app_config_dir()
+        .map_err(|e| format!("Failed to get app config dir: {}", e))?;
+    std::fs::create_dir_all(&app_config_dir)
+        .map_err(|e| format!("Failed to create config dir: {}", e))?;
+    Ok(app_config_dir)
+}
+
+pub fn load_network_settings(app: &tauri::AppHandle) -> Result<NetworkSettings, String> {
+    read_network_settings(&get_app_config_dir(app)?)
+}
+
+fn save_network_settings(app: &tauri::AppHandle, settings: &NetworkSettings) -> Result<(), String> {
+    write_network_settings(&get_app_config_dir(app)?, settings)
+}
+
+/// Reads the settings in `dir`, with the proxy password from its own file
+fn read_network_settings(dir: &Path) -> Result<NetworkSettings, String> {
+    let settings_path = dir.join(NETWORK_SETTINGS_FILE_NAME);
+    if !settings_path.exists() {
+        return Ok(NetworkSettings::default());
+    }
+
+    let json = std::fs::read_to_string(&settings_path)
+        .map_err(|e| format!("Failed to read network settings: {}", e))?;
+    let mut settings: NetworkSettings = serde_json::from_str(&json)
+        .map_err(|e| format!("Failed to parse network settings: {}", e))?;
+
+    if let Some(proxy) = &mut settings.proxy {
+        match std::fs::read_to_string(dir.join(PROXY_PASSWORD_FILE_NAME)) {
+            Ok(password) => proxy.password = Some(password),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Failed to read proxy password: {}", e)),
+        }
+    }
+
+    Ok(settings)
+}
+
+/// Writes the settings to `dir`. The proxy password goes to a separate file
+/// only the current user can read, so the settings can be shared safely.
+fn write_network_settings(dir: &Path, settings: &NetworkSettings) -> Result<(), String> {
+    let password = settings
+        .proxy
+        .as_ref()
+        .and_then(|proxy| proxy.password.as_deref())
+        .filter(|password| !password.is_empty());
+    let password_path = dir.join(PROXY_PASSWORD_FILE_NAME);
+    match password {
+        Some(password) => write_private(&password_path, password)
+            .map_err(|e| format!("Failed to write proxy password: {}", e))?,
+        None => match std::fs::remove_file(&password_path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(format!("Failed to remove proxy password: {}", e));
+            }
+            _ => {}
+        },
+    }
+
+    let mut settings = settings.clone();
+    if let Some(proxy) = &mut settings.proxy {
+        proxy.password = None;
+    }
+    let json = serde_json::to_string_pretty(&settings)
+        .map_err(|e| format!("Failed to serialize network settings: {}", e))?;
+
+    write_atomically(&dir.join(NETWORK_SETTINGS_FILE_NAME), &json)
+        .map_err(|e| format!("Failed to write network settings: {}", e))
+}
+
+/// Writes a file readable by the current user only
+fn write_private(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut options = std::fs::File::options();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(path)?;
+    file.write_all(content.as_bytes())
+}
+
+#[tauri::command]
+pub async fn get_network_settings(app: tauri::AppHandle) -> Result<NetworkSettings, String> {
+    load_network_settings(&app)
+}
+
+#[tauri::command]
+pub async fn set_network_settings(
+    app: tauri::AppHandle,
+    http: tauri::State<'_, HttpClient>,
+    settings: NetworkSettings,
+) -> Result<(), String> {
+    // Rebuild first so broken settings are never persisted
+    http.configure(&settings)?;
+    save_network_settings(&app, &settings)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionStage {
+    Settings,
+    Proxy,
+    Dns,
+    Connect,
+    Tls,
+    Timeout,
+    Http,
+}
+
+#[derive(Serialize)]
+pub struct ConnectionReport {
+    success: bool,
+    status: Option<u16>,
+    failed_stage: Option<ConnectionStage>,
+    message: Option<String>,
+    elapsed_ms: u64,
+}
+
+impl ConnectionReport {
+    fn failed(stage: ConnectionStage, message: String, started: Instant) -> Self {
+        Self {
+            success: false,
+            status: None,
+            failed_stage: Some(stage),
+            message: Some(message),
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+/// Joins the error with all its sources, the top level reqwest message
+/// alone rarely says what went wrong
+fn error_chain(error: &reqwest::Error) -> String {
+    let mut chain = vec![error.to_string()];
+    let mut source = std::error::Error::source(error);
+    while let Some(e) = source {
+        chain.push(e.to_string());
+        source = e.source();
+    }
+    chain.join(": ")
+}
+
+/// Guesses the failed stage from the error and its sources, reqwest does
+/// not expose proxy, DNS and TLS failures as separate error kinds
+fn classify_error(error: &reqwest::Error) -> ConnectionStage {
+    if error.is_timeout() {
+        return ConnectionStage::Timeout;
+    }
+
+    let chain = error_chain(error).to_lowercase();
+
+    if chain.contains("proxy") || chain.contains("socks") {
+        ConnectionStage::Proxy
+    } else if chain.contains("dns") || chain.contains("lookup") || chain.contains("resolve") {
+        ConnectionStage::Dns
+    } else if chain.contains("certificate")
+        || chain.contains("tls")
+        || chain.contains("ssl")
+        || chain.contains("handshake")
+    {
+        ConnectionStage::Tls
+    } else if error.is_connect() {
+        ConnectionStage::Connect
+    } else {
+        ConnectionStage::Http
+    }
+}
+
+/// Requests `url` and reports which stage failed. When `settings` are passed
+/// they are tested without being applied, so the settings page can check
+/// them before saving.
+#[tauri::command]
+pub async fn test_connection(
+    http: tauri::State<'_, HttpClient>,
+    url: String,
+    settings: Option<NetworkSettings>,
+) -> Result<ConnectionReport, String> {
+    let started = Instant::now();
+
+    let client = match settings {
+        Some(settings) => match http.build_client(&settings) {
+            Ok(client) => client,
+            Err(e) => {
+                return Ok(ConnectionReport::failed(
+                    ConnectionStage::Settings,
+                    e,
+                    started,
+                ))
+            }
+        },
+        None => http.client(),
+    };
+
+    let response = match client.get(&url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            return Ok(ConnectionReport::failed(
+                classify_error(&e),
+                error_chain(&e),
+                started,
+            ))
+        }
+    };
+
+    let status = response.status();
+    Ok(ConnectionReport {
+        success: status.is_success(),
+        status: Some(status.as_u16()),
+        failed_stage: (!status.is_success()).then_some(ConnectionStage::Http),
+        message: (!status.is_success()).then(|| format!("Server responded with {}", status)),
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::test_support::{Response, TempDir, TestServer, MTIME};
+
+    fn proxy(password: Option<&str>) -> ProxySettings {
+        ProxySettings {
+            kind: ProxyKind::Http,
+            host: "proxy.local".to_string(),
+            port: 3128,
+            username: Some("user".to_string()),
+            password: password.map(str::to_string),
+            bypass: vec!["localhost".to_string()],
+        }
+    }
+
+    // Ignores proxies from the environment, the tests talk to local servers
+    fn client() -> reqwest::ClientBuilder {
+        reqwest::Client::builder().no_proxy()
+    }
+
+    async fn send_error(client: reqwest::ClientBuilder, url: &str) -> reqwest::Error {
+        client.build().unwrap().get(url).send().await.unwrap_err()
+    }
+
+    #[test]
+    fn proxy_password_is_not_saved_with_the_settings() {
+        let dir = TempDir::new("network_settings", "password");
+        let settings = NetworkSettings {
+            proxy: Some(proxy(Some("secret"))),
+            extra_ca_certificates: Vec::new(),
+        };
+
+        write_network_settings(&dir.0, &settings).unwrap();
+
+        let json = std::fs::read_to_string(dir.0.join(NETWORK_SETTINGS_FILE_NAME)).unwrap();
+        assert!(!json.contains("secret"));
+        let loaded = read_network_settings(&dir.0).unwrap();
+        assert_eq!(loaded.proxy.unwrap().password.as_deref(), Some("secret"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = std::fs::metadata(dir.0.join(PROXY_PASSWORD_FILE_NAME)).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn clearing_the_password_removes_it() {
+        let dir = TempDir::new("network_settings", "clear_password");
+        let mut settings = NetworkSettings {
+            proxy: Some(proxy(Some("secret"))),
+            extra_ca_certificates: Vec::new(),
+        };
+        write_network_settings(&dir.0, &settings).unwrap();
+
+        settings.proxy = Some(proxy(None));
+        write_network_settings(&dir.0, &settings).unwrap();
+
+        assert!(!dir.0.join(PROXY_PASSWORD_FILE_NAME).exists());
+        let loaded = read_network_settings(&dir.0).unwrap();
+        assert!(loaded.proxy.unwrap().password.is_none());
+    }
+
+    #[test]
+    fn proxy_accepts_credentials_and_rejects_bad_hosts() {
+        assert!(proxy(Some("p@ss:word")).to_proxy().is_ok());
+
+        let mut settings = proxy(None);
+        settings.host = "bad host".to_string();
+        let error = settings.to_proxy().unwrap_err();
+        assert!(error.starts_with("Invalid proxy address"), "{}", error);
+    }
+
+    #[test]
+    fn apply_rejects_unreadable_and_empty_certificates() {
+        let dir = TempDir::new("network_settings", "certificates");
+        dir.write("empty.pem", b"", MTIME);
+
+        let missing = NetworkSettings {
+            proxy: None,
+            extra_ca_certificates: vec![dir.0.join("missing.pem").display().to_string()],
+        };
+        let error = missing.apply(reqwest::Client::builder()).unwrap_err();
+        assert!(
+            error.starts_with("Failed to read CA certificate"),
+            "{}",
+            error
+        );
+
+        let empty = NetworkSettings {
+            proxy: None,
+            extra_ca_certificates: vec![dir.0.join("empty.pem").display().to_string()],
+        };
+        let error = empty.apply(reqwest::Client::builder()).unwrap_err();
+        assert!(error.starts_with("No certificates found"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn classifies_refused_connections() {
+        // Nothing listens on the port once the listener is dropped
+        let url = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+
+        let error = send_error(client(), &url).await;
+
+        assert!(matches!(classify_error(&error), ConnectionStage::Connect));
+    }
+
+    #[tokio::test]
+    async fn classifies_unknown_hosts() {
+        let error = send_error(client(), "http://launcher-test.invalid").await;
+
+        assert!(matches!(classify_error(&error), ConnectionStage::Dns));
+    }
+
+    #[tokio::test]
+    async fn classifies_timeouts() {
+        let server = TestServer::start(|_| {
+            std::thread::sleep(Duration::from_millis(500));
+            Response::new(200)
+        });
+        let error = send_error(client().timeout(Duration::from_millis(100)), &server.url).await;
+
+        assert!(matches!(classify_error(&error), ConnectionStage::Timeout));
+    }
+
+    #[tokio::test]
+    async fn classifies_tls_failures() {
+        // The test server only speaks plain HTTP
+        let server = TestServer::start(|_| Response::new(200));
+        let url = server.url.replacen("http://", "https://", 1);
+
+        let error = send_error(client(), &url).await;
+
+        assert!(matches!(classify_error(&error), ConnectionStage::Tls));
+    }
+}
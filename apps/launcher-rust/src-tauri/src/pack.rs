@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+use std::fs::{DirEntry, File};
+use std::io::Write;
+use std::path::Path;
+
+use tar::{Builder, Header};
+
+use crate::manifest::{manifest_path, Manifest, ManifestEntry, ManifestHasher, MANIFEST_FILE_NAME};
+
+/// Checks that `folder_path` points to an existing directory
+pub fn validate_source_folder(folder_path: &str) -> Result<&Path, String> {
+    let source_path = Path::new(folder_path);
+
+    if !source_path.exists() {
+        return Err(format!("Folder does not exist: {}", folder_path));
+    }
+
+    if !source_path.is_dir() {
+        return Err(format!("Path is not a directory: {}", folder_path));
+    }
+
+    Ok(source_path)
+}
+
+/// Walks the folder and returns every file in it with their total size
+pub fn collect_files(source_path: &Path) -> Result<(Vec<DirEntry>, u64), String> {
+    let mut total_bytes = 0_u64;
+    let mut to_visit = VecDeque::new();
+    let mut all_entries = vec![];
+
+    to_visit.push_front(source_path.to_path_buf());
+
+    while let Some(dir_path) = to_visit.pop_front() {
+        let entries = std::fs::read_dir(dir_path)
+            .map_err(|e| format!("Failed to read directory contents: {}", e))?;
+
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
+            let entry_path = entry.path();
+
+            if entry_path.is_dir() {
+                to_visit.push_front(entry_path);
+            } else {
+                let meta = entry
+                    .metadata()
+                    .map_err(|e| format!("Failed to get file metadata: {}", e))?;
+                total_bytes += meta.len();
+                all_entries.push(entry);
+            }
+        }
+    }
+
+    Ok((all_entries, total_bytes))
+}
+
+/// Appends the collected files to the tar builder with paths relative to
+/// `source_path`, hashing them on the way into the build manifest
+pub fn append_files<W: Write>(
+    tar_builder: &mut Builder<W>,
+    entries: &[DirEntry],
+    source_path: &Path,
+    chunk_size: Option<u64>,
+) -> Result<Manifest, String> {
+    let mut manifest = Manifest::new(chunk_size);
+
+    for entry in entries {
+        let entry_path = entry.path();
+        let relative_path = entry_path
+            .strip_prefix(source_path)
+            .map_err(|e| format!("Failed to calculate relative path: {}", e))?;
+        // The manifest of the build is added by append_manifest
+        if relative_path == Path::new(MANIFEST_FILE_NAME) {
+            continue;
+        }
+
+        if entry_path.is_dir() {
+            tar_builder
+                .append_dir_all(relative_path, &entry_path)
+                .map_err(|e| format!("Failed to add directory to archive: {}", e))?;
+            continue;
+        }
+
+        let file = File::open(&entry_path)
+            .map_err(|e| format!("Failed to open {}: {}", entry_path.display(), e))?;
+        let metadata = file
+            .metadata()
+            .map_err(|e| format!("Failed to get file metadata: {}", e))?;
+
+        let mut header = Header::new_gnu();
+        header.set_metadata(&metadata);
+
+        let mut hasher = ManifestHasher::new(file, manifest.chunk_size);
+        tar_builder
+            .append_data(&mut header, relative_path, &mut hasher)
+            .map_err(|e| format!("Failed to add file to archive: {}", e))?;
+
+        let (hash, chunks) = hasher.finalize();
+        manifest.push(ManifestEntry {
+            path: manifest_path(relative_path),
+            size: metadata.len(),
+            mode: file_mode(&header)?,
+            mtime: header.mtime().ok(),
+            hash,
+            chunks,
+        });
+    }
+
+    Ok(manifest)
+}
+
+/// Permission bits of a header filled from file metadata, the file type is implied
+pub fn file_mode(header: &Header) -> Result<u32, String> {
+    header
+        .mode()
+        .map(|mode| mode & 0o7777)
+        .map_err(|e| format!("Failed to read file mode: {}", e))
+}
+
+/// Builds the manifest of a folder without packing it
+pub fn hash_folder(source_path: &Path) -> Result<Manifest, String> {
+    let (entries, _) = collect_files(source_path)?;
+    let mut manifest = Manifest::new(None);
+
+    for entry in entries {
+        let entry_path = entry.path();
+        let relative_path = entry_path
+            .strip_prefix(source_path)
+            .map_err(|e| format!("Failed to calculate relative path: {}", e))?;
+        // An installed build can be hashed too, its own manifest is not part of it
+        if relative_path == Path::new(MANIFEST_FILE_NAME) {
+            continue;
+        }
+
+        let file = File::open(&entry_path)
+            .map_err(|e| format!("Failed to open {}: {}", entry_path.display(), e))?;
+        let metadata = file
+            .metadata()
+            .map_err(|e| format!("Failed to get file metadata: {}", e))?;
+
+        let mut header = Header::new_gnu();
+        header.set_metadata(&metadata);
+
+        let mut hasher = ManifestHasher::new(file, None);
+        std::io::copy(&mut hasher, &mut std::io::sink())
+            .map_err(|e| format!("Failed to read {}: {}", entry_path.display(), e))?;
+
+        let (hash, chunks) = hasher.finalize();
+        manifest.push(ManifestEntry {
+            path: manifest_path(relative_path),
+            size: metadata.len(),
+            mode: file_mode(&header)?,
+            mtime: header.mtime().ok(),
+            hash,
+            chunks,
+        });
+    }
+
+    Ok(manifest)
+}
+
+/// Appends the manifest as the last entry at the root of the archive
+pub fn append_manifest<W: Write>(
+    tar_builder: &mut Builder<W>,
+    manifest: &Manifest,
+) -> Result<Vec<u8>, String> {
+    let json = manifest.to_json()?;
+
+    let mut header = Header::new_gnu();
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+    );
+
+    tar_builder
+        .append_data(&mut header, MANIFEST_FILE_NAME, json.as_slice())
+        .map_err(|e| format!("Failed to add manifest to archive: {}", e))?;
+
+    Ok(json)
+}
@@ -0,0 +1,504 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::process_manager::unix_now;
+use crate::resource_usage::ResourcePeaks;
+
+// How much of a session is lost at most when the launcher is killed
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlaySession {
+    pub app_id: String,
+    // Seconds since the epoch
+    pub started_at: u64,
+    pub ended_at: u64,
+    pub duration_seconds: u64,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    // The launcher stopped before the game, the end is the last heartbeat
+    #[serde(default)]
+    pub interrupted: bool,
+    // Highest resource usage of the game, none where it is not sampled
+    #[serde(default)]
+    pub peak_usage: Option<ResourcePeaks>,
+}
+
+impl PlaySession {
+    fn new(open: &OpenSession, ended_at: u64) -> Self {
+        let ended_at = ended_at.max(open.started_at);
+        Self {
+            app_id: open.app_id.clone(),
+            started_at: open.started_at,
+            ended_at,
+            duration_seconds: ended_at - open.started_at,
+            exit_code: None,
+            signal: None,
+            interrupted: false,
+            peak_usage: None,
+        }
+    }
+}
+
+/// Line of the playtime log. Every change is appended and synced, so a
+/// killed launcher loses at most one heartbeat interval of a session.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum SessionEvent {
+    Started {
+        session: String,
+        app_id: String,
+        at: u64,
+    },
+    // Every open session was still running at `at`
+    Heartbeat {
+        at: u64,
+    },
+    Ended {
+        session: String,
+        at: u64,
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+        #[serde(default)]
+        peak_usage: Option<ResourcePeaks>,
+    },
+    // A finished session, written when the log is compacted
+    Session(PlaySession),
+}
+
+struct OpenSession {
+    app_id: String,
+    started_at: u64,
+    last_seen: u64,
+}
+
+pub struct PlaytimeStore {
+    path: PathBuf,
+    file: Option<File>,
+    sessions: Vec<PlaySession>,
+    open: HashMap<String, OpenSession>,
+}
+
+impl PlaytimeStore {
+    /// Loads the log from `path`. Sessions left open by a launcher that did not
+    /// exit cleanly end at their last heartbeat, then the log is compacted.
+    pub fn load(path: PathBuf) -> Self {
+        let mut store = Self {
+            path,
+            file: None,
+            sessions: vec![],
+            open: HashMap::new(),
+        };
+
+        if let Ok(file) = File::open(&store.path) {
+            // A line cut short by a crash is skipped
+            let events = BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| serde_json::from_str(&line).ok());
+            for event in events {
+                store.apply(event);
+            }
+        }
+
+        for open in std::mem::take(&mut store.open).into_values() {
+            store.sessions.push(PlaySession {
+                interrupted: true,
+                ..PlaySession::new(&open, open.last_seen)
+            });
+        }
+        store.sessions.sort_by_key(|s| s.started_at);
+
+        if let Err(e) = store.compact() {
+            eprintln!("{}", e);
+        }
+        store
+    }
+
+    fn apply(&mut self, event: SessionEvent) {
+        match event {
+            SessionEvent::Started {
+                session,
+                app_id,
+                at,
+            } => {
+                self.open.insert(
+                    session,
+                    OpenSession {
+                        app_id,
+                        started_at: at,
+                        last_seen: at,
+                    },
+                );
+            }
+            SessionEvent::Heartbeat { at } => {
+                for open in self.open.values_mut() {
+                    open.last_seen = open.last_seen.max(at);
+                }
+            }
+            SessionEvent::Ended {
+                session,
+                at,
+                exit_code,
+                signal,
+                peak_usage,
+            } => {
+                if let Some(open) = self.open.remove(&session) {
+                    self.sessions.push(PlaySession {
+                        exit_code,
+                        signal,
+                        peak_usage,
+                        ..PlaySession::new(&open, at)
+                    });
+                }
+            }
+            SessionEvent::Session(session) => self.sessions.push(session),
+        }
+    }
+
+    /// Rewrites the log with finished sessions only
+    fn compact(&mut self) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create playtime dir: {}", e))?;
+        }
+
+        let temp_path = self.path.with_extension("tmp");
+        let mut temp = File::create(&temp_path)
+            .map_err(|e| format!("Failed to create playtime log: {}", e))?;
+        for session in &self.sessions {
+            let line = serde_json::to_string(&SessionEvent::Session(session.clone()))
+                .map_err(|e| format!("Failed to serialize playtime: {}", e))?;
+            writeln!(temp, "{}", line).map_err(|e| format!("Failed to write playtime: {}", e))?;
+        }
+        temp.sync_all()
+            .map_err(|e| format!("Failed to write playtime: {}", e))?;
+        std::fs::rename(&temp_path, &self.path)
+            .map_err(|e| format!("Failed to replace playtime log: {}", e))?;
+
+        self.file = None;
+        Ok(())
+    }
+
+    fn append(&mut self, event: &SessionEvent) -> Result<(), String> {
+        if self.file.is_none() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .map_err(|e| format!("Failed to open playtime log: {}", e))?;
+            self.file = Some(file);
+        }
+        let file = self.file.as_mut().unwrap();
+
+        let line = serde_json::to_string(event)
+            .map_err(|e| format!("Failed to serialize playtime: {}", e))?;
+        writeln!(file, "{}", line)
+            .and_then(|_| file.sync_data())
+            .map_err(|e| format!("Failed to write playtime: {}", e))
+    }
+
+    fn record(&mut self, event: SessionEvent) -> Result<(), String> {
+        // Kept in memory even when the disk fails, the session still shows up
+        let result = self.append(&event);
+        self.apply(event);
+        result
+    }
+
+    pub fn start(&mut self, session: String, app_id: String, at: u64) -> Result<(), String> {
+        self.record(SessionEvent::Started {
+            session,
+            app_id,
+            at,
+        })
+    }
+
+    /// Marks every open session as still running, nothing is written without one
+    pub fn heartbeat(&mut self, at: u64) -> Result<(), String> {
+        if self.open.is_empty() {
+            return Ok(());
+        }
+        self.record(SessionEvent::Heartbeat { at })
+    }
+
+    pub fn end(
+        &mut self,
+        session: String,
+        at: u64,
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+        peak_usage: Option<ResourcePeaks>,
+    ) -> Result<(), String> {
+        self.record(SessionEvent::Ended {
+            session,
+            at,
+            exit_code,
+            signal,
+            peak_usage,
+        })
+    }
+
+    /// Finished sessions followed by the running ones, which end at `now`
+    fn sessions_until(&self, now: u64) -> Vec<PlaySession> {
+        let running = self.open.values().map(|open| PlaySession::new(open, now));
+        self.sessions.iter().cloned().chain(running).collect()
+    }
+}
+
+/// Managed state shared by the process manager and the playtime commands
+pub type Playtime = Mutex<PlaytimeStore>;
+
+/// Keeps sessions of running games alive in the log until the launcher exits
+pub fn spawn_heartbeat(app: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            interval.tick().await;
+            let result = app
+                .state::<Playtime>()
+                .lock()
+                .unwrap()
+                .heartbeat(unix_now());
+            if let Err(e) = result {
+                eprintln!("{}", e);
+            }
+        }
+    });
+}
+
+#[derive(Serialize)]
+pub struct DailyPlaytime {
+    // Local date as `YYYY-MM-DD`
+    date: String,
+    seconds: u64,
+}
+
+#[derive(Serialize)]
+pub struct PlaytimeSummary {
+    total_seconds: u64,
+    session_count: usize,
+    // End of the last session, now while the game runs
+    last_played: Option<u64>,
+    // Oldest first, days without playtime are left out
+    daily: Vec<DailyPlaytime>,
+    sessions: Vec<PlaySession>,
+}
+
+/// `YYYY-MM-DD` of a day counted from 1970-01-01
+fn civil_date(days: i64) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Seconds played per local day, sessions over midnight count for both days
+fn daily_playtime(sessions: &[PlaySession], utc_offset: i64) -> BTreeMap<i64, u64> {
+    let mut daily = BTreeMap::new();
+    for session in sessions {
+        let mut start = session.started_at as i64 + utc_offset;
+        let end = session.ended_at as i64 + utc_offset;
+        while start < end {
+            let day = start.div_euclid(SECONDS_PER_DAY);
+            let day_end = ((day + 1) * SECONDS_PER_DAY).min(end);
+            *daily.entry(day).or_default() += (day_end - start) as u64;
+            start = day_end;
+        }
+    }
+    daily
+}
+
+/// Summarizes the sessions of one app, or of every app without `app_id`.
+/// `utc_offset_minutes` places day boundaries at local midnight, `days`
+/// limits the histogram and sessions to that many recent days.
+#[tauri::command]
+pub fn get_playtime(
+    store: tauri::State<'_, Playtime>,
+    app_id: Option<String>,
+    utc_offset_minutes: Option<i32>,
+    days: Option<u32>,
+) -> PlaytimeSummary {
+    let now = unix_now();
+    let sessions: Vec<PlaySession> = store
+        .lock()
+        .unwrap()
+        .sessions_until(now)
+        .into_iter()
+        .filter(|s| app_id.as_ref().is_none_or(|id| *id == s.app_id))
+        .collect();
+
+    let utc_offset = i64::from(utc_offset_minutes.unwrap_or(0)) * 60;
+    let first_day = days
+        .map(|days| (now as i64 + utc_offset).div_euclid(SECONDS_PER_DAY) - i64::from(days) + 1);
+
+    let daily = daily_playtime(&sessions, utc_offset)
+        .into_iter()
+        .filter(|(day, _)| first_day.is_none_or(|first| *day >= first))
+        .map(|(day, seconds)| DailyPlaytime {
+            date: civil_date(day),
+            seconds,
+        })
+        .collect();
+
+    let recent_sessions = sessions
+        .iter()
+        .filter(|s| {
+            first_day.is_none_or(|first| {
+                (s.ended_at as i64 + utc_offset).div_euclid(SECONDS_PER_DAY) >= first
+            })
+        })
+        .cloned()
+        .collect();
+
+    PlaytimeSummary {
+        total_seconds: sessions.iter().map(|s| s.duration_seconds).sum(),
+        session_count: sessions.len(),
+        last_played: sessions.iter().map(|s| s.ended_at).max(),
+        daily,
+        sessions: recent_sessions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    // 2024-03-09 23:30:00 UTC
+    const LATE_EVENING: u64 = 1_710_027_000;
+
+    /// A playtime log holding `lines`, in a dir removed on drop
+    struct TestLog(TempDir, PathBuf);
+
+    impl TestLog {
+        fn new(name: &str, lines: &[&str]) -> Self {
+            let dir = TempDir::new("playtime", name);
+            let path = dir.0.join("playtime.jsonl");
+            std::fs::write(&path, lines.join("\n")).unwrap();
+            Self(dir, path)
+        }
+    }
+
+    #[test]
+    fn sessions_left_open_end_at_the_last_heartbeat() {
+        let log = TestLog::new(
+            "interrupted",
+            &[
+                r#"{"event":"started","session":"s1","app_id":"game","at":1000}"#,
+                r#"{"event":"heartbeat","at":1060}"#,
+                r#"{"event":"heartbeat","at":1120}"#,
+            ],
+        );
+
+        let store = PlaytimeStore::load(log.1.clone());
+
+        assert!(store.open.is_empty());
+        assert_eq!(store.sessions.len(), 1);
+        let session = &store.sessions[0];
+        assert!(session.interrupted);
+        assert_eq!((session.started_at, session.ended_at), (1000, 1120));
+        assert_eq!(session.duration_seconds, 120);
+
+        // The log was compacted, loading it again gives the same session
+        let reloaded = PlaytimeStore::load(log.1.clone());
+        assert_eq!(reloaded.sessions.len(), 1);
+        assert!(reloaded.sessions[0].interrupted);
+    }
+
+    #[test]
+    fn a_truncated_last_line_is_skipped() {
+        let log = TestLog::new(
+            "truncated",
+            &[
+                r#"{"event":"started","session":"s1","app_id":"game","at":1000}"#,
+                r#"{"event":"ended","session":"s1","at":1300,"exit_code":0,"signal":null}"#,
+                r#"{"event":"started","session":"s2","app_id":"ga"#,
+            ],
+        );
+
+        let store = PlaytimeStore::load(log.1.clone());
+
+        assert_eq!(store.sessions.len(), 1);
+        assert_eq!(store.sessions[0].duration_seconds, 300);
+        assert_eq!(store.sessions[0].exit_code, Some(0));
+        assert!(!store.sessions[0].interrupted);
+    }
+
+    #[test]
+    fn records_sessions_across_restarts() {
+        let log = TestLog::new("record", &[]);
+
+        let mut store = PlaytimeStore::load(log.1.clone());
+        store.start("s1".into(), "game".into(), 1000).unwrap();
+        store.heartbeat(1060).unwrap();
+        store.end("s1".into(), 1090, Some(1), None, None).unwrap();
+        store.start("s2".into(), "game".into(), 2000).unwrap();
+        store.heartbeat(2060).unwrap();
+        assert_eq!(store.sessions_until(2100).last().unwrap().ended_at, 2100);
+        drop(store);
+
+        let store = PlaytimeStore::load(log.1.clone());
+        let ends: Vec<(u64, bool)> = store
+            .sessions
+            .iter()
+            .map(|s| (s.ended_at, s.interrupted))
+            .collect();
+        assert_eq!(ends, [(1090, false), (2060, true)]);
+    }
+
+    #[test]
+    fn sessions_over_midnight_count_for_both_days() {
+        let session = PlaySession {
+            app_id: "game".into(),
+            started_at: LATE_EVENING,
+            ended_at: LATE_EVENING + 3600,
+            duration_seconds: 3600,
+            exit_code: None,
+            signal: None,
+            interrupted: false,
+            peak_usage: None,
+        };
+
+        let utc: Vec<(String, u64)> = daily_playtime(std::slice::from_ref(&session), 0)
+            .into_iter()
+            .map(|(day, seconds)| (civil_date(day), seconds))
+            .collect();
+        assert_eq!(
+            utc,
+            [("2024-03-09".into(), 1800), ("2024-03-10".into(), 1800)]
+        );
+
+        // Two hours behind UTC the whole session is on the evening before
+        let behind: Vec<(String, u64)> = daily_playtime(&[session], -2 * 3600)
+            .into_iter()
+            .map(|(day, seconds)| (civil_date(day), seconds))
+            .collect();
+        assert_eq!(behind, [("2024-03-09".into(), 3600)]);
+    }
+
+    #[test]
+    fn civil_date_handles_leap_years_and_the_epoch() {
+        assert_eq!(civil_date(0), "1970-01-01");
+        assert_eq!(civil_date(-1), "1969-12-31");
+        assert_eq!(civil_date(19_782), "2024-02-29");
+        assert_eq!(civil_date(19_783), "2024-03-01");
+        assert_eq!(civil_date(11_016), "2000-02-29");
+    }
+}
@@ -0,0 +1,782 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::process::{Child, Command};
+use tokio::sync::Notify;
+
+use crate::chunk_store::ChunkStore;
+use crate::compat_tools::{
+    compat_command, compat_root, needs_compat_tool, prepare_prefix, CompatTool,
+};
+use crate::crash_reports::{classify_exit, report_crash};
+use crate::game_logs::{create_session_log, spawn_log_writer};
+use crate::launch_profiles::{resolve_profile, InstancePolicy, LaunchProfiles};
+use crate::launch_settings::load_launch_settings;
+use crate::launch_wrappers::{wrap_command, Wrapper};
+use crate::manifest::local_path;
+use crate::playtime::Playtime;
+use crate::process_tree::{stop_tree, wait_tree, ProcessTree};
+use crate::resource_usage::{sample_usage, ResourceSample, SharedUsage};
+
+pub const APP_STARTED_EVENT: &str = "app_started";
+pub const APP_EXITED_EVENT: &str = "app_exited";
+
+// Time a game gets to save and quit before its processes are killed
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Clone)]
+pub struct RunningApp {
+    pub app_id: String,
+    pub pid: u32,
+    pub install_dir: String,
+    pub executable: String,
+    // Seconds since the epoch
+    pub started_at: u64,
+    // Where stdout and stderr of the game go, none when it could not be created
+    pub log_file: Option<String>,
+    pub build_id: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct AppExited {
+    pub app_id: String,
+    pub pid: u32,
+    pub exit_code: Option<i32>,
+    // Unix signal that ended the process
+    pub signal: Option<i32>,
+    pub started_at: u64,
+    pub ended_at: u64,
+}
+
+/// Returned instead of starting a second copy of a game or changing the
+/// files of a running one
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename = "game_running")]
+pub struct GameRunning {
+    app_id: String,
+    install_dir: String,
+    message: String,
+}
+
+impl GameRunning {
+    fn new(app_id: &str, install_dir: &Path) -> Self {
+        let install_dir = install_dir.to_string_lossy().to_string();
+        Self {
+            message: format!("{} is running from {}, close it first", app_id, install_dir),
+            app_id: app_id.to_string(),
+            install_dir,
+        }
+    }
+}
+
+impl std::fmt::Display for GameRunning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Error of commands that refuse to run while a game runs, other failures
+/// stay plain messages
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum CommandError {
+    GameRunning(GameRunning),
+    Message(String),
+}
+
+impl From<GameRunning> for CommandError {
+    fn from(error: GameRunning) -> Self {
+        Self::GameRunning(error)
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        Self::Message(message)
+    }
+}
+
+struct TrackedProcess {
+    info: RunningApp,
+    stop: Arc<Notify>,
+    usage: SharedUsage,
+}
+
+/// Game between the instance policy check and being tracked
+struct PendingLaunch {
+    app_id: String,
+    install_dir: PathBuf,
+}
+
+/// Authoritative list of game processes started by the launcher. It lives in
+/// managed state, so webview reloads ask it again instead of losing track.
+#[derive(Default)]
+pub struct ProcessManager {
+    // Keyed by PID, an app may run more than once
+    processes: Mutex<HashMap<u32, TrackedProcess>>,
+    // Locked after `processes`, keyed by `next_launch`
+    launching: Mutex<HashMap<u64, PendingLaunch>>,
+    // Install dirs whose files are being changed, locked after `launching`
+    // and keyed by `next_launch` too
+    installing: Mutex<HashMap<u64, PathBuf>>,
+    next_launch: AtomicU64,
+}
+
+/// Holds the place of a game in the process manager until it is tracked
+pub struct LaunchReservation<'a> {
+    manager: &'a ProcessManager,
+    id: u64,
+}
+
+impl Drop for LaunchReservation<'_> {
+    fn drop(&mut self) {
+        self.manager.launching.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Keeps games from being launched from an install dir while an install,
+/// update or repair changes its files
+pub struct InstallReservation<'a> {
+    manager: &'a ProcessManager,
+    id: u64,
+}
+
+impl Drop for InstallReservation<'_> {
+    fn drop(&mut self) {
+        self.manager.installing.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// App id and install dir of every game running or starting
+fn running_games<'a>(
+    processes: &'a HashMap<u32, TrackedProcess>,
+    launching: &'a HashMap<u64, PendingLaunch>,
+) -> impl Iterator<Item = (&'a str, &'a Path)> {
+    processes
+        .values()
+        .map(|p| (p.info.app_id.as_str(), Path::new(&p.info.install_dir)))
+        .chain(
+            launching
+                .values()
+                .map(|l| (l.app_id.as_str(), l.install_dir.as_path())),
+        )
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Whether changing files in one dir may touch the other
+fn overlaps(a: &Path, b: &Path) -> bool {
+    let (a, b) = (canonical(a), canonical(b));
+    a.starts_with(&b) || b.starts_with(&a)
+}
+
+impl ProcessManager {
+    pub fn list(&self) -> Vec<RunningApp> {
+        let mut running: Vec<RunningApp> = self
+            .processes
+            .lock()
+            .unwrap()
+            .values()
+            .map(|p| p.info.clone())
+            .collect();
+        running.sort_by_key(|app| app.started_at);
+        running
+    }
+
+    /// Whether a running game still writes to the log
+    pub fn is_logging_to(&self, log_file: &Path) -> bool {
+        self.processes
+            .lock()
+            .unwrap()
+            .values()
+            .any(|p| p.info.log_file.as_deref().map(Path::new) == Some(log_file))
+    }
+
+    /// Checks the instance policy and holds the place of the game until it is
+    /// tracked, so two quick launches can not both pass
+    fn reserve(
+        &self,
+        app_id: &str,
+        install_dir: &Path,
+        policy: InstancePolicy,
+    ) -> Result<LaunchReservation<'_>, CommandError> {
+        let processes = self.processes.lock().unwrap();
+        let mut launching = self.launching.lock().unwrap();
+        let installing = self.installing.lock().unwrap();
+
+        if installing.values().any(|dir| overlaps(dir, install_dir)) {
+            return Err(format!(
+                "{} is being installed or updated, wait until it finishes",
+                install_dir.display()
+            )
+            .into());
+        }
+
+        if policy == InstancePolicy::Single {
+            let running = running_games(&processes, &launching).find(|(id, _)| *id == app_id);
+            if let Some((app_id, install_dir)) = running {
+                return Err(GameRunning::new(app_id, install_dir).into());
+            }
+        }
+
+        let id = self.next_launch.fetch_add(1, Ordering::Relaxed);
+        launching.insert(
+            id,
+            PendingLaunch {
+                app_id: app_id.to_string(),
+                install_dir: install_dir.to_path_buf(),
+            },
+        );
+        Ok(LaunchReservation { manager: self, id })
+    }
+
+    /// Game running or starting from `dir`, or from a dir containing it or inside it
+    pub fn running_in(&self, dir: &Path) -> Option<GameRunning> {
+        let processes = self.processes.lock().unwrap();
+        let launching = self.launching.lock().unwrap();
+
+        let running = running_games(&processes, &launching)
+            .find(|(_, install_dir)| overlaps(install_dir, dir))
+            .map(|(app_id, install_dir)| GameRunning::new(app_id, install_dir));
+        running
+    }
+
+    /// Fails with `GameRunning` when changing files in `dir` would touch a
+    /// running game, otherwise no game is launched from it until the
+    /// reservation is dropped
+    pub fn reserve_install(&self, dir: &Path) -> Result<InstallReservation<'_>, GameRunning> {
+        let processes = self.processes.lock().unwrap();
+        let launching = self.launching.lock().unwrap();
+        let mut installing = self.installing.lock().unwrap();
+
+        if let Some((app_id, install_dir)) = running_games(&processes, &launching)
+            .find(|(_, install_dir)| overlaps(install_dir, dir))
+        {
+            return Err(GameRunning::new(app_id, install_dir));
+        }
+
+        // Several archives of a build may be installed into the same dir at once
+        let id = self.next_launch.fetch_add(1, Ordering::Relaxed);
+        installing.insert(id, dir.to_path_buf());
+        Ok(InstallReservation { manager: self, id })
+    }
+
+    fn track(&self, info: RunningApp, usage: SharedUsage) -> Arc<Notify> {
+        let stop = Arc::new(Notify::new());
+        self.processes.lock().unwrap().insert(
+            info.pid,
+            TrackedProcess {
+                info,
+                stop: stop.clone(),
+                usage,
+            },
+        );
+        stop
+    }
+
+    /// Streams resource usage of every process of the app to `channel`,
+    /// returns how many there were
+    fn watch_usage(&self, app_id: &str, channel: Channel<ResourceSample>) -> usize {
+        let processes = self.processes.lock().unwrap();
+        let matching: Vec<&TrackedProcess> = processes
+            .values()
+            .filter(|p| p.info.app_id == app_id)
+            .collect();
+        for process in &matching {
+            process.usage.lock().unwrap().subscribe(channel.clone());
+        }
+        matching.len()
+    }
+
+    fn untrack(&self, pid: u32) {
+        self.processes.lock().unwrap().remove(&pid);
+    }
+
+    /// Asks every process of the app to stop, returns how many there were
+    fn stop(&self, app_id: &str) -> usize {
+        let processes = self.processes.lock().unwrap();
+        let matching: Vec<&TrackedProcess> = processes
+            .values()
+            .filter(|p| p.info.app_id == app_id)
+            .collect();
+        for process in &matching {
+            process.stop.notify_one();
+        }
+        matching.len()
+    }
+}
+
+/// App ids name folders of the launcher, only plain names are accepted
+pub fn validate_app_id(app_id: &str) -> Result<(), String> {
+    let is_safe = !app_id.is_empty()
+        && app_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !is_safe {
+        return Err(format!("Invalid app id: {}", app_id));
+    }
+    Ok(())
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Executable named by the `CFBundleExecutable` key of the bundle, falling
+/// back to the bundle name
+#[cfg(target_os = "macos")]
+fn bundle_executable(bundle: &Path) -> PathBuf {
+    let info = std::fs::read_to_string(bundle.join("Contents/Info.plist")).unwrap_or_default();
+    let name = info
+        .split_once("<key>CFBundleExecutable</key>")
+        .and_then(|(_, rest)| rest.split_once("<string>"))
+        .and_then(|(_, rest)| rest.split_once("</string>"))
+        .map(|(name, _)| name.trim().to_string())
+        .or_else(|| {
+            bundle
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+        })
+        .unwrap_or_default();
+
+    bundle.join("Contents/MacOS").join(name)
+}
+
+/// Resolves the build entrypoint inside the install dir to the file to run
+pub fn resolve_executable(install_dir: &Path, entrypoint: &str) -> Result<PathBuf, String> {
+    let path = local_path(install_dir, entrypoint)?;
+
+    #[cfg(target_os = "macos")]
+    let path = if path.is_dir() && path.extension().is_some_and(|ext| ext == "app") {
+        bundle_executable(&path)
+    } else {
+        path
+    };
+
+    if !path.is_file() {
+        return Err(format!("Entrypoint not found: {}", path.display()));
+    }
+
+    Ok(path)
+}
+
+/// Playtime session of a launch, PIDs alone are reused
+fn session_id(info: &RunningApp) -> String {
+    format!("{}-{}", info.started_at, info.pid)
+}
+
+fn exit_details(status: &std::io::Result<ExitStatus>) -> (Option<i32>, Option<i32>) {
+    let Ok(status) = status else {
+        return (None, None);
+    };
+
+    #[cfg(unix)]
+    let signal = std::os::unix::process::ExitStatusExt::signal(status);
+    #[cfg(not(unix))]
+    let signal = None;
+
+    (status.code(), signal)
+}
+
+fn spawn_stop(app_id: &str, tree: &Arc<ProcessTree>) {
+    let (app_id, tree) = (app_id.to_string(), tree.clone());
+    tokio::spawn(async move {
+        match stop_tree(&tree, STOP_TIMEOUT).await {
+            Ok(true) => eprintln!("{} did not exit in time and was killed", app_id),
+            Ok(false) => {}
+            Err(e) => eprintln!("Failed to stop {}: {}", app_id, e),
+        }
+    });
+}
+
+/// Waits until the game and every process it started exit, stopping the
+/// whole tree on request, then reports the exit of the spawned process.
+/// Crashes are reported to `crash_report_dsn` when the game has one.
+async fn watch_process(
+    app: AppHandle,
+    mut child: Child,
+    tree: ProcessTree,
+    info: RunningApp,
+    stop: Arc<Notify>,
+    usage: SharedUsage,
+    crash_report_dsn: Option<String>,
+) {
+    let tree = Arc::new(tree);
+    let mut stopped = false;
+    let sampler = tokio::spawn(sample_usage(
+        tree.clone(),
+        info.app_id.clone(),
+        info.pid,
+        usage.clone(),
+    ));
+
+    // The child is reaped while the tree is stopped, zombies would count as alive
+    let status = tokio::select! {
+        status = child.wait() => status,
+        _ = stop.notified() => {
+            stopped = true;
+            spawn_stop(&info.app_id, &tree);
+            child.wait().await
+        }
+    };
+
+    // Launchers and crash handlers outlive the spawned process, the game runs
+    // until the last of them exits
+    loop {
+        tokio::select! {
+            _ = wait_tree(&tree) => break,
+            _ = stop.notified() => {
+                stopped = true;
+                spawn_stop(&info.app_id, &tree);
+            }
+        }
+    }
+
+    sampler.abort();
+
+    if let Err(e) = &status {
+        eprintln!("Failed to wait for {}: {}", info.app_id, e);
+    }
+    let (exit_code, signal) = exit_details(&status);
+    let ended_at = unix_now();
+
+    app.state::<ProcessManager>().untrack(info.pid);
+    let recorded = app.state::<Playtime>().lock().unwrap().end(
+        session_id(&info),
+        ended_at,
+        exit_code,
+        signal,
+        usage.lock().unwrap().peaks(),
+    );
+    if let Err(e) = recorded {
+        eprintln!("Failed to record playtime of {}: {}", info.app_id, e);
+    }
+
+    let exited = AppExited {
+        app_id: info.app_id.clone(),
+        pid: info.pid,
+        exit_code,
+        signal,
+        started_at: info.started_at,
+        ended_at,
+    };
+    if let Err(e) = app.emit(APP_EXITED_EVENT, exited.clone()) {
+        eprintln!("Failed to emit app exit event: {}", e);
+    }
+
+    if let Some(reason) = classify_exit(&exited, stopped) {
+        report_crash(app, info, exited, reason, crash_report_dsn).await;
+    }
+}
+
+/// Launch settings of one game merged with the global ones
+struct GameLaunchSettings {
+    wrappers: Vec<Wrapper>,
+    compat_tool: Option<CompatTool>,
+    instance_policy: InstancePolicy,
+}
+
+/// Global wrappers go around the ones of the game unless the game opts out,
+/// the compatibility tool of the game replaces the global one
+fn game_launch_settings(
+    app: &AppHandle,
+    profiles: &LaunchProfiles,
+    install_dir: &str,
+) -> Result<GameLaunchSettings, String> {
+    let install = profiles.lock().unwrap().get(install_dir);
+    let global = load_launch_settings(app)?;
+
+    let mut wrappers = if install.skip_global_wrappers {
+        vec![]
+    } else {
+        global.wrappers
+    };
+    wrappers.extend(install.wrappers);
+
+    Ok(GameLaunchSettings {
+        wrappers,
+        compat_tool: install.compat_tool.or(global.compat_tool),
+        instance_policy: install.instance_policy,
+    })
+}
+
+/// Starts the entrypoint of an installed build and tracks it until it exits.
+/// The launch profile (`profile`, else the selected one) may replace the
+/// entrypoint and adds its args and environment, `args` go after its own.
+/// Windows builds run through the compatibility tool with a prefix of their
+/// own, launch wrappers start the game when configured.
+/// `working_dir` is relative to the install dir, the entrypoint's folder by default.
+/// `build_id` and `crash_report_dsn` go into crash reports of the game.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub async fn launch_app(
+    app: AppHandle,
+    manager: tauri::State<'_, ProcessManager>,
+    profiles: tauri::State<'_, LaunchProfiles>,
+    playtime: tauri::State<'_, Playtime>,
+    app_id: String,
+    install_dir: String,
+    entrypoint: String,
+    profile: Option<String>,
+    args: Option<Vec<String>>,
+    working_dir: Option<String>,
+    build_id: Option<String>,
+    crash_report_dsn: Option<String>,
+) -> Result<RunningApp, CommandError> {
+    let profile = resolve_profile(&profiles, &install_dir, profile.as_deref())?;
+    let settings = game_launch_settings(&app, &profiles, &install_dir)?;
+    let install_dir = PathBuf::from(install_dir);
+    let reservation = manager.reserve(&app_id, &install_dir, settings.instance_policy)?;
+
+    let entrypoint = profile
+        .as_ref()
+        .and_then(|p| p.entrypoint.clone())
+        .filter(|e| !e.is_empty())
+        .unwrap_or(entrypoint);
+    let executable = resolve_executable(&install_dir, &entrypoint)?;
+
+    let working_dir = working_dir
+        .filter(|dir| !dir.is_empty())
+        .or_else(|| profile.as_ref().and_then(|p| p.working_dir.clone()))
+        .filter(|dir| !dir.is_empty());
+    let working_dir = match working_dir {
+        Some(dir) => local_path(&install_dir, &dir)?,
+        None => executable
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| install_dir.clone()),
+    };
+
+    let mut command_line = vec![executable.clone().into_os_string()];
+    if let Some(profile) = &profile {
+        command_line.extend(profile.args.iter().map(OsString::from));
+    }
+    command_line.extend(args.unwrap_or_default().into_iter().map(OsString::from));
+
+    // Tools and wrappers are looked up in the PATH the game gets
+    let path = match profile.as_ref().and_then(|p| p.env.get("PATH")) {
+        Some(path) => path.as_ref().map(OsString::from),
+        None => std::env::var_os("PATH"),
+    };
+
+    let mut compat_env = vec![];
+    let command_line = if needs_compat_tool(&executable) {
+        let tool = settings.compat_tool.as_ref().ok_or_else(|| {
+            "Windows builds need Wine or Proton, choose one in the launch settings".to_string()
+        })?;
+        let root = compat_root(&app)?;
+        let prefix = prepare_prefix(&root, &app_id)?;
+        let launch = compat_command(tool, &root, &prefix, command_line, path.as_deref())?;
+        compat_env = launch.env;
+        launch.command
+    } else {
+        command_line
+    };
+    let command_line = wrap_command(&settings.wrappers, command_line, path.as_deref())?;
+
+    let mut command = Command::new(&command_line[0]);
+    // Profiles may still override what the tool is given
+    command.envs(compat_env);
+    if let Some(profile) = &profile {
+        for (key, value) in &profile.env {
+            match value {
+                Some(value) => command.env(key, value),
+                None => command.env_remove(key),
+            };
+        }
+    }
+    command
+        .args(&command_line[1..])
+        .current_dir(&working_dir)
+        .stdin(Stdio::null());
+
+    // The game still starts when its output can not be kept. A separate
+    // writer process keeps the log, so the output outlives the launcher.
+    let log_output = create_session_log(&app, &app_id, &command_line).and_then(|path| {
+        spawn_log_writer(&path)
+            .map(|output| (path, output))
+            .map_err(|e| format!("Failed to start the game log writer: {}", e))
+    });
+    let log_file = match log_output {
+        Ok((path, (stdout, stderr))) => {
+            command.stdout(stdout).stderr(stderr);
+            Some(path.to_string_lossy().to_string())
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            command.stdout(Stdio::null()).stderr(Stdio::null());
+            None
+        }
+    };
+    ProcessTree::prepare(&mut command);
+
+    let child = command
+        .spawn()
+        .map_err(|e| format!("Failed to launch {}: {}", executable.display(), e))?;
+    // Closes the launcher's copy of the log pipe, the writer exits with the game
+    drop(command);
+    let pid = child
+        .id()
+        .ok_or_else(|| "Game exited before it could be tracked".to_string())?;
+    let tree = ProcessTree::attach(&child)
+        .map_err(|e| format!("Failed to track the processes of the game: {}", e))?;
+
+    let info = RunningApp {
+        app_id,
+        pid,
+        install_dir: install_dir.to_string_lossy().to_string(),
+        executable: executable.to_string_lossy().to_string(),
+        started_at: unix_now(),
+        log_file,
+        build_id,
+    };
+    let usage = SharedUsage::default();
+    let stop = manager.track(info.clone(), usage.clone());
+    drop(reservation);
+    let recorded =
+        playtime
+            .lock()
+            .unwrap()
+            .start(session_id(&info), info.app_id.clone(), info.started_at);
+    if let Err(e) = recorded {
+        eprintln!("Failed to record playtime of {}: {}", info.app_id, e);
+    }
+
+    if let Err(e) = app.emit(APP_STARTED_EVENT, info.clone()) {
+        eprintln!("Failed to emit app start event: {}", e);
+    }
+    tokio::spawn(watch_process(
+        app,
+        child,
+        tree,
+        info.clone(),
+        stop,
+        usage,
+        crash_report_dsn,
+    ));
+
+    Ok(info)
+}
+
+#[tauri::command]
+pub fn stop_app(manager: tauri::State<'_, ProcessManager>, app_id: String) -> Result<(), String> {
+    if manager.stop(&app_id) == 0 {
+        return Err(format!("{} is not running", app_id));
+    }
+    Ok(())
+}
+
+/// Streams CPU, memory and disk usage of the running game every few seconds
+/// until it exits. Only sampled on Linux.
+#[tauri::command]
+pub fn watch_resource_usage(
+    manager: tauri::State<'_, ProcessManager>,
+    app_id: String,
+    channel: Channel<ResourceSample>,
+) -> Result<(), String> {
+    if !cfg!(target_os = "linux") {
+        return Err("Resource usage is only sampled on Linux".to_string());
+    }
+    if manager.watch_usage(&app_id, channel) == 0 {
+        return Err(format!("{} is not running", app_id));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_running(manager: tauri::State<'_, ProcessManager>) -> Vec<RunningApp> {
+    manager.list()
+}
+
+/// Game holding the files of the install dir, none when they may be changed
+#[tauri::command]
+pub fn get_install_lock(
+    manager: tauri::State<'_, ProcessManager>,
+    install_dir: String,
+) -> Option<String> {
+    manager
+        .running_in(Path::new(&install_dir))
+        .map(|running| running.app_id)
+}
+
+/// Deletes an install dir unless a game runs from it, with the chunks only
+/// it used
+#[tauri::command]
+pub async fn remove_install_dir(
+    manager: tauri::State<'_, ProcessManager>,
+    store: tauri::State<'_, ChunkStore>,
+    install_dir: String,
+) -> Result<(), CommandError> {
+    let install_dir = PathBuf::from(install_dir);
+    let _reservation = manager.reserve_install(&install_dir)?;
+
+    match std::fs::remove_dir_all(&install_dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(format!("Failed to remove install dir: {}", e).into());
+        }
+        _ => {}
+    }
+
+    let store = store.inner().clone();
+    tokio::task::spawn_blocking(move || {
+        store.remove_references(&install_dir)?;
+        store.collect_garbage().map(|_| ())
+    })
+    .await
+    .map_err(|e| format!("Chunk cleanup task failed: {}", e))??;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn install_and_launch_exclude_each_other() {
+        let manager = ProcessManager::default();
+        let root = TempDir::new("process_manager", "install_reservation");
+        let (dir, game_dir) = (root.0.clone(), root.0.join("game"));
+
+        let install = manager.reserve_install(&dir).unwrap();
+        // Archives of one build install side by side
+        let second = manager.reserve_install(&game_dir).unwrap();
+        assert!(matches!(
+            manager.reserve("game", &game_dir, InstancePolicy::Multiple),
+            Err(CommandError::Message(_))
+        ));
+        drop((install, second));
+
+        let launch = manager
+            .reserve("game", &game_dir, InstancePolicy::Multiple)
+            .unwrap();
+        let Err(running) = manager.reserve_install(&dir) else {
+            panic!("installed over a starting game");
+        };
+        assert_eq!(
+            serde_json::to_value(&running).unwrap(),
+            serde_json::json!({
+                "kind": "game_running",
+                "app_id": "game",
+                "install_dir": game_dir.to_string_lossy(),
+                "message": running.to_string(),
+            })
+        );
+        drop(launch);
+
+        assert!(manager.reserve_install(&dir).is_ok());
+    }
+}
@@ -0,0 +1,382 @@
+use std::time::Duration;
+
+use tokio::process::{Child, Command};
+use tokio::time::Instant;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Every process a game started, not only the one the launcher spawned.
+///
+/// On Linux and macOS the game runs in a session of its own, so its process
+/// group holds the whole tree. On Windows the game is put into a job object.
+pub struct ProcessTree {
+    #[cfg(unix)]
+    pgid: nix::unistd::Pid,
+    #[cfg(windows)]
+    job: windows::Job,
+}
+
+impl ProcessTree {
+    /// Must be called on the command before it is spawned
+    pub fn prepare(command: &mut Command) {
+        #[cfg(unix)]
+        // SAFETY: setsid is async-signal-safe and touches no memory of the parent
+        unsafe {
+            command.pre_exec(|| {
+                nix::unistd::setsid()
+                    .map(|_| ())
+                    .map_err(std::io::Error::from)
+            });
+        }
+
+        #[cfg(not(unix))]
+        let _ = command;
+    }
+
+    /// Takes over a child spawned from a prepared command
+    pub fn attach(child: &Child) -> std::io::Result<Self> {
+        #[cfg(unix)]
+        {
+            let pid = child
+                .id()
+                .ok_or_else(|| std::io::Error::other("Process already exited"))?;
+            Ok(Self {
+                pgid: nix::unistd::Pid::from_raw(pid as i32),
+            })
+        }
+
+        #[cfg(windows)]
+        {
+            let process = child
+                .raw_handle()
+                .ok_or_else(|| std::io::Error::other("Process already exited"))?;
+            Ok(Self {
+                job: windows::Job::assign(process)?,
+            })
+        }
+    }
+
+    /// Processes of the tree that have not exited yet
+    #[cfg(target_os = "linux")]
+    pub fn members(&self) -> Vec<u32> {
+        let Ok(entries) = std::fs::read_dir("/proc") else {
+            return vec![];
+        };
+
+        entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+            .filter(|pid| {
+                // `pid (comm) state ppid pgrp ...`, comm may contain anything
+                let Ok(stat) = std::fs::read_to_string(format!("/proc/{}/stat", pid)) else {
+                    return false;
+                };
+                let mut fields = stat
+                    .rsplit_once(')')
+                    .map(|(_, rest)| rest.split_whitespace())
+                    .into_iter()
+                    .flatten();
+                let state = fields.next();
+                let pgrp = fields.nth(1).and_then(|pgrp| pgrp.parse::<i32>().ok());
+                // Zombies are gone already, they only wait to be reaped
+                state != Some("Z") && pgrp == Some(self.pgid.as_raw())
+            })
+            .collect()
+    }
+
+    pub fn is_alive(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        return !self.members().is_empty();
+
+        #[cfg(all(unix, not(target_os = "linux")))]
+        return !matches!(
+            nix::sys::signal::killpg(self.pgid, None),
+            Err(nix::errno::Errno::ESRCH)
+        );
+
+        #[cfg(windows)]
+        return self.job.active_processes() > 0;
+    }
+
+    /// Asks every process to exit: SIGTERM on unix, WM_CLOSE to the windows of
+    /// the job on Windows
+    pub fn terminate(&self) -> std::io::Result<()> {
+        #[cfg(unix)]
+        return signal_group(self.pgid, nix::sys::signal::Signal::SIGTERM);
+
+        #[cfg(windows)]
+        return self.job.close_windows();
+    }
+
+    pub fn kill(&self) -> std::io::Result<()> {
+        #[cfg(unix)]
+        return signal_group(self.pgid, nix::sys::signal::Signal::SIGKILL);
+
+        #[cfg(windows)]
+        return self.job.terminate();
+    }
+}
+
+#[cfg(unix)]
+fn signal_group(pgid: nix::unistd::Pid, signal: nix::sys::signal::Signal) -> std::io::Result<()> {
+    match nix::sys::signal::killpg(pgid, signal) {
+        // Everything exited already
+        Ok(()) | Err(nix::errno::Errno::ESRCH) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Asks the tree to exit and kills whatever is left after `timeout`, resolving
+/// once every process is gone. Returns whether it had to be killed.
+pub async fn stop_tree(tree: &ProcessTree, timeout: Duration) -> std::io::Result<bool> {
+    tree.terminate()?;
+
+    let deadline = Instant::now() + timeout;
+    while tree.is_alive() {
+        if Instant::now() >= deadline {
+            tree.kill()?;
+            wait_tree(tree).await;
+            return Ok(true);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    Ok(false)
+}
+
+/// Resolves once every process of the tree has exited
+pub async fn wait_tree(tree: &ProcessTree) {
+    while tree.is_alive() {
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::os::windows::io::RawHandle;
+
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE, HWND, LPARAM, TRUE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectBasicAccountingInformation,
+        JobObjectBasicProcessIdList, QueryInformationJobObject, TerminateJobObject,
+        JOBOBJECT_BASIC_ACCOUNTING_INFORMATION, JOBOBJECT_BASIC_PROCESS_ID_LIST,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        EnumWindows, GetWindowThreadProcessId, PostMessageW, WM_CLOSE,
+    };
+
+    // Room for this many PIDs when listing the job
+    const MAX_LISTED_PROCESSES: usize = 1024;
+
+    pub struct Job(HANDLE);
+
+    // SAFETY: job handles may be used from any thread
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    impl Job {
+        /// Creates a job for the process. Processes it started before this
+        /// call are not part of the job, games rarely spawn that early.
+        /// The job does not kill on close, games outlive the launcher.
+        pub fn assign(process: RawHandle) -> std::io::Result<Self> {
+            // SAFETY: plain Win32 calls, the handle is closed on drop
+            unsafe {
+                let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+                if job.is_null() {
+                    return Err(std::io::Error::last_os_error());
+                }
+                let job = Job(job);
+
+                if AssignProcessToJobObject(job.0, process as HANDLE) == 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(job)
+            }
+        }
+
+        pub fn active_processes(&self) -> u32 {
+            // SAFETY: the struct is plain data and the size matches
+            unsafe {
+                let mut info: JOBOBJECT_BASIC_ACCOUNTING_INFORMATION = std::mem::zeroed();
+                let ok = QueryInformationJobObject(
+                    self.0,
+                    JobObjectBasicAccountingInformation,
+                    &mut info as *mut _ as *mut _,
+                    std::mem::size_of_val(&info) as u32,
+                    std::ptr::null_mut(),
+                );
+                if ok == 0 {
+                    return 0;
+                }
+                info.ActiveProcesses
+            }
+        }
+
+        fn process_ids(&self) -> std::io::Result<Vec<u32>> {
+            // The list is a header followed by a variable number of PIDs
+            let size = std::mem::size_of::<JOBOBJECT_BASIC_PROCESS_ID_LIST>()
+                + MAX_LISTED_PROCESSES * std::mem::size_of::<usize>();
+            let mut buf = vec![0_usize; size.div_ceil(std::mem::size_of::<usize>())];
+
+            // SAFETY: the buffer is aligned for the struct and as large as told
+            unsafe {
+                let list = buf.as_mut_ptr() as *mut JOBOBJECT_BASIC_PROCESS_ID_LIST;
+                let ok = QueryInformationJobObject(
+                    self.0,
+                    JobObjectBasicProcessIdList,
+                    list as *mut _,
+                    size as u32,
+                    std::ptr::null_mut(),
+                );
+                if ok == 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+
+                let count = (*list).NumberOfProcessIdsInList as usize;
+                let ids = std::ptr::addr_of!((*list).ProcessIdList) as *const usize;
+                Ok((0..count).map(|i| *ids.add(i) as u32).collect())
+            }
+        }
+
+        pub fn close_windows(&self) -> std::io::Result<()> {
+            let pids = self.process_ids()?;
+
+            unsafe extern "system" fn close_window(hwnd: HWND, lparam: LPARAM) -> i32 {
+                let pids = &*(lparam as *const Vec<u32>);
+                let mut pid = 0;
+                GetWindowThreadProcessId(hwnd, &mut pid);
+                if pids.contains(&pid) {
+                    PostMessageW(hwnd, WM_CLOSE, 0, 0);
+                }
+                TRUE
+            }
+
+            // SAFETY: `pids` outlives the enumeration, which is synchronous
+            unsafe {
+                EnumWindows(Some(close_window), &pids as *const Vec<u32> as LPARAM);
+            }
+            Ok(())
+        }
+
+        pub fn terminate(&self) -> std::io::Result<()> {
+            // SAFETY: plain Win32 call on a handle owned by self
+            if unsafe { TerminateJobObject(self.0, 1) } == 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            // SAFETY: the handle is owned by self and closed once
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    use tokio::process::Command;
+
+    use crate::test_support::TempDir;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/process_tree.sh");
+
+    struct Fixture {
+        tree: ProcessTree,
+        child: tokio::process::Child,
+        pids: Vec<u32>,
+    }
+
+    fn is_running(pid: u32) -> bool {
+        std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .ok()
+            .and_then(|stat| {
+                let (_, rest) = stat.rsplit_once(')')?;
+                Some(rest.split_whitespace().next()? != "Z")
+            })
+            .unwrap_or(false)
+    }
+
+    async fn read_pids(path: &Path) -> Vec<u32> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            if let Ok(content) = std::fs::read_to_string(path) {
+                return content.lines().map(|l| l.trim().parse().unwrap()).collect();
+            }
+            assert!(Instant::now() < deadline, "Fixture did not start");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    async fn start(mode: &str) -> Fixture {
+        let dir = TempDir::new("process_tree", mode);
+        let pid_file = dir.0.join("pids");
+
+        let mut command = Command::new("sh");
+        command.arg(FIXTURE).arg(mode).arg(&pid_file);
+        ProcessTree::prepare(&mut command);
+        let child = command.spawn().unwrap();
+        let tree = ProcessTree::attach(&child).unwrap();
+
+        let pids = read_pids(&pid_file).await;
+        // The first PID is the script, which may be gone already in orphans mode
+        assert_eq!(pids.len(), 4);
+        assert!(pids[1..].iter().all(|pid| is_running(*pid)));
+
+        Fixture { tree, child, pids }
+    }
+
+    #[tokio::test]
+    async fn stop_ends_every_process_gracefully() {
+        let mut fixture = start("polite").await;
+
+        let forced = stop_tree(&fixture.tree, Duration::from_secs(10))
+            .await
+            .unwrap();
+        fixture.child.wait().await.unwrap();
+
+        assert!(!forced);
+        assert!(!fixture.tree.is_alive());
+        assert!(fixture.pids.iter().all(|pid| !is_running(*pid)));
+    }
+
+    #[tokio::test]
+    async fn stop_kills_processes_ignoring_terminate() {
+        let mut fixture = start("stubborn").await;
+
+        let started = Instant::now();
+        let forced = stop_tree(&fixture.tree, Duration::from_millis(500))
+            .await
+            .unwrap();
+        fixture.child.wait().await.unwrap();
+
+        assert!(forced);
+        assert!(started.elapsed() >= Duration::from_millis(500));
+        assert!(fixture.pids.iter().all(|pid| !is_running(*pid)));
+    }
+
+    #[tokio::test]
+    async fn tree_outlives_the_spawned_process() {
+        let mut fixture = start("orphans").await;
+
+        let status = fixture.child.wait().await.unwrap();
+        assert!(status.success());
+        assert!(fixture.tree.is_alive());
+        assert_eq!(fixture.tree.members().len(), 3);
+
+        let forced = stop_tree(&fixture.tree, Duration::from_secs(10))
+            .await
+            .unwrap();
+
+        assert!(!forced);
+        assert!(fixture.pids.iter().all(|pid| !is_running(*pid)));
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use tokio_util::io::{StreamReader, SyncIoBridge};
+
+use crate::http_client::HttpClient;
+use crate::http_range_reader::HttpRangeReader;
+use crate::manifest::{local_path, Manifest, ManifestEntry, ManifestHasher, MANIFEST_FILE_NAME};
+use crate::process_manager::{CommandError, ProcessManager};
+use crate::rate_meter::RateMeter;
+use crate::unpack::{decompress_tar, detect_format, ArchiveFormat};
+use crate::verify::verify_files;
+use crate::ProgressCallbackData;
+
+const COPY_BUFFER_SIZE: usize = 256 * 1024;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairArchive {
+    url: String,
+    file_name: String,
+}
+
+/// Where damaged files are fetched from
+#[derive(Deserialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "snake_case",
+    rename_all_fields = "camelCase"
+)]
+pub enum RepairSource {
+    // Every file is published on its own under `<base_url>/<manifest path>`
+    Files { base_url: String },
+    // The build archives, only the entries that need repair are extracted.
+    // Zip archives are read with range requests when the server allows it.
+    Archives { archives: Vec<RepairArchive> },
+}
+
+#[derive(Serialize)]
+pub struct RepairFailure {
+    path: String,
+    error: String,
+}
+
+#[derive(Serialize, Default)]
+pub struct RepairReport {
+    repaired: Vec<String>,
+    failed: Vec<RepairFailure>,
+}
+
+pub type ProgressFn = Arc<dyn Fn(u64) + Send + Sync>;
+
+/// Hidden file next to `target` used while it is replaced
+pub fn sibling_path(target: &Path, extension: &str) -> PathBuf {
+    let file_name = target
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    target.with_file_name(format!(".{}.{}", file_name, extension))
+}
+
+/// Writes the file read from `reader`, raw or through a decoder, next to its
+/// target and checks it against the manifest. Returns the temporary path to
+/// rename over the target.
+pub fn stage_verified(
+    install_dir: &Path,
+    entry: &ManifestEntry,
+    reader: impl Read,
+    on_write: &ProgressFn,
+) -> Result<PathBuf, String> {
+    let target = local_path(install_dir, &entry.path)?;
+    let parent = target
+        .parent()
+        .ok_or_else(|| format!("Invalid path: {}", entry.path))?;
+    std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
+
+    let temp_path = sibling_path(&target, "repair");
+
+    let result = (|| {
+        let mut file = File::create(&temp_path)
+            .map_err(|e| format!("Failed to create temporary file: {}", e))?;
+        let mut hasher = ManifestHasher::new(reader, None);
+        let mut buf = vec![0; COPY_BUFFER_SIZE];
+
+        loop {
+            let read = hasher
+                .read(&mut buf)
+                .map_err(|e| format!("Failed to read {}: {}", entry.path, e))?;
+            if read == 0 {
+                break;
+            }
+            file.write_all(&buf[..read])
+                .map_err(|e| format!("Failed to write {}: {}", entry.path, e))?;
+            on_write(read as u64);
+        }
+
+        let (hash, _) = hasher.finalize();
+        if !hash.eq_ignore_ascii_case(&entry.hash) {
+            return Err(format!(
+                "Integrity check failed for {}: expected {}, got {}",
+                entry.path, entry.hash, hash
+            ));
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(entry.mode))
+                .map_err(|e| format!("Failed to set permissions: {}", e))?;
+        }
+
+        // Keeps quick verification happy after the repair
+        if let Some(mtime) = entry.mtime {
+            file.set_modified(UNIX_EPOCH + Duration::from_secs(mtime))
+                .map_err(|e| format!("Failed to set modification time: {}", e))?;
+        }
+
+        file.sync_all()
+            .map_err(|e| format!("Failed to write {}: {}", entry.path, e))
+    })();
+
+    match result {
+        Ok(()) => Ok(temp_path),
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+/// Stages the file and renames it over the target, so a failed repair leaves
+/// the old file alone
+pub fn write_verified(
+    install_dir: &Path,
+    entry: &ManifestEntry,
+    reader: impl Read,
+    on_write: &ProgressFn,
+) -> Result<(), String> {
+    let temp_path = stage_verified(install_dir, entry, reader, on_write)?;
+    let target = local_path(install_dir, &entry.path)?;
+
+    std::fs::rename(&temp_path, &target).map_err(|e| {
+        let _ = std::fs::remove_file(&temp_path);
+        format!("Failed to replace {}: {}", entry.path, e)
+    })
+}
+
+/// Archive entry path in manifest form, `./` prefixes dropped
+fn entry_manifest_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+async fn repair_from_file_url(
+    http: &HttpClient,
+    base_url: &str,
+    install_dir: &Path,
+    entry: &ManifestEntry,
+    on_write: &ProgressFn,
+) -> Result<(), String> {
+    let mut url =
+        reqwest::Url::parse(base_url).map_err(|e| format!("Invalid repair URL: {}", e))?;
+    url.path_segments_mut()
+        .map_err(|_| format!("Invalid repair URL: {}", base_url))?
+        .pop_if_empty()
+        .extend(entry.path.split('/'));
+
+    let response = http
+        .send(http.client().get(url))
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Download of {} failed with status {}",
+            entry.path,
+            response.status()
+        ));
+    }
+
+    let body = response.bytes_stream().map_err(std::io::Error::other);
+    let reader = SyncIoBridge::new(StreamReader::new(Box::pin(body)));
+
+    let install_dir = install_dir.to_path_buf();
+    let entry = entry.clone();
+    let on_write = on_write.clone();
+    tokio::task::spawn_blocking(move || write_verified(&install_dir, &entry, reader, &on_write))
+        .await
+        .map_err(|e| format!("Repair task failed: {}", e))?
+}
+
+/// Extracts the wanted entries from one archive. Returns the result for every
+/// entry found in it, the rest are left for the next archive.
+async fn repair_from_archive(
+    http: &HttpClient,
+    archive: &RepairArchive,
+    install_dir: &Path,
+    wanted: HashMap<String, ManifestEntry>,
+    on_write: &ProgressFn,
+) -> Result<Vec<(String, Result<(), String>)>, String> {
+    let install_dir = install_dir.to_path_buf();
+    let on_write = on_write.clone();
+
+    if ArchiveFormat::from_name(&archive.file_name) == Some(ArchiveFormat::Zip) {
+        if let Some(reader) = HttpRangeReader::open(http.client(), &archive.url).await? {
+            return tokio::task::spawn_blocking(move || {
+                let mut zip = zip::ZipArchive::new(reader)
+                    .map_err(|e| format!("Failed to read zip index: {}", e))?;
+                // Names of the central directory in manifest form, reading the
+                // entries themselves would cost a request each
+                let indices: HashMap<String, usize> = (0..zip.len())
+                    .filter_map(|i| {
+                        Some((entry_manifest_path(Path::new(zip.name_for_index(i)?)), i))
+                    })
+                    .collect();
+
+                Ok(wanted
+                    .into_values()
+                    .filter_map(|entry| {
+                        let index = *indices.get(&entry.path)?;
+                        let result = zip
+                            .by_index(index)
+                            .map_err(|e| format!("Failed to read {}: {}", entry.path, e))
+                            .and_then(|file| match file.enclosed_name() {
+                                Some(path) if entry_manifest_path(&path) == entry.path => {
+                                    write_verified(&install_dir, &entry, file, &on_write)
+                                }
+                                _ => Err(format!("Unsafe path in archive: {}", file.name())),
+                            });
+                        Some((entry.path, result))
+                    })
+                    .collect())
+            })
+            .await
+            .map_err(|e| format!("Repair task failed: {}", e))?;
+        }
+    }
+
+    // Without an index the archive is streamed until every wanted entry is seen
+    let response = http
+        .send(http.client().get(&archive.url))
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Download of {} failed with status {}",
+            archive.file_name,
+            response.status()
+        ));
+    }
+
+    let body = response.bytes_stream().map_err(std::io::Error::other);
+    let reader = SyncIoBridge::new(StreamReader::new(Box::pin(body)));
+    let file_name = archive.file_name.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let mut wanted = wanted;
+        let mut results = vec![];
+        let mut reader = BufReader::new(reader);
+        let format = detect_format(&file_name, &mut reader)
+            .map_err(|e| format!("Failed to detect archive format: {}", e))?;
+
+        if format == ArchiveFormat::Zip {
+            while !wanted.is_empty() {
+                let Some(file) = zip::read::read_zipfile_from_stream(&mut reader)
+                    .map_err(|e| format!("Failed to read archive: {}", e))?
+                else {
+                    break;
+                };
+                let Some(path) = file.enclosed_name().map(|p| entry_manifest_path(&p)) else {
+                    continue;
+                };
+                if let Some(entry) = wanted.remove(&path) {
+                    let result = write_verified(&install_dir, &entry, file, &on_write);
+                    results.push((path, result));
+                }
+            }
+            return Ok(results);
+        }
+
+        let decoder =
+            decompress_tar(reader, format).map_err(|e| format!("Failed to read archive: {}", e))?;
+        let mut tar = tar::Archive::new(decoder);
+        let entries = tar
+            .entries()
+            .map_err(|e| format!("Failed to read archive: {}", e))?;
+
+        for file in entries {
+            if wanted.is_empty() {
+                break;
+            }
+            let file = file.map_err(|e| format!("Failed to read archive: {}", e))?;
+            let path = match file.path() {
+                Ok(path) => entry_manifest_path(&path),
+                Err(_) => continue,
+            };
+            if let Some(entry) = wanted.remove(&path) {
+                let result = write_verified(&install_dir, &entry, file, &on_write);
+                results.push((path, result));
+            }
+        }
+
+        Ok(results)
+    })
+    .await
+    .map_err(|e| format!("Repair task failed: {}", e))?
+}
+
+/// Refetches damaged files of an installation. When `paths` are not given
+/// the installation is verified first and every missing or modified file is
+/// repaired. Extra files are left alone.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub async fn repair_install(
+    http: tauri::State<'_, HttpClient>,
+    manager: tauri::State<'_, ProcessManager>,
+    install_dir: String,
+    manifest_path: Option<String>,
+    paths: Option<Vec<String>>,
+    source: RepairSource,
+    progress_channel: tauri::ipc::Channel<ProgressCallbackData>,
+    speed_update_interval: Option<f64>,
+) -> Result<RepairReport, CommandError> {
+    let install_dir = PathBuf::from(install_dir);
+    let _reservation = manager.reserve_install(&install_dir)?;
+    let manifest_path = manifest_path
+        .map(PathBuf::from)
+        .unwrap_or_else(|| install_dir.join(MANIFEST_FILE_NAME));
+    let manifest = Arc::new(Manifest::load(&manifest_path)?);
+    let speed_update_interval = Duration::from_secs_f64(speed_update_interval.unwrap_or(1.0));
+
+    let paths = match paths {
+        Some(paths) => paths,
+        None => {
+            let install_dir = install_dir.clone();
+            let manifest = manifest.clone();
+            let report = tokio::task::spawn_blocking(move || {
+                verify_files(
+                    &install_dir,
+                    &manifest,
+                    false,
+                    speed_update_interval,
+                    |_| {},
+                )
+            })
+            .await
+            .map_err(|e| format!("Verification task failed: {}", e))??;
+            report.missing.into_iter().chain(report.modified).collect()
+        }
+    };
+
+    let mut report = RepairReport::default();
+    let mut pending = HashMap::new();
+    for path in paths {
+        match manifest.get(&path) {
+            Some(entry) => {
+                pending.insert(path, entry.clone());
+            }
+            None => report.failed.push(RepairFailure {
+                path,
+                error: "File is not part of the build".to_string(),
+            }),
+        }
+    }
+
+    let total_bytes: u64 = pending.values().map(|e| e.size).sum();
+    let progress = Mutex::new((RateMeter::new(speed_update_interval), 0_u64));
+    let on_write: ProgressFn = Arc::new(move |delta| {
+        let mut progress = progress.lock().unwrap();
+        progress.0.add_value(delta);
+        progress.1 += delta;
+
+        let res = progress_channel
+            .send(ProgressCallbackData {
+                current_bytes: progress.1,
+                total_bytes: total_bytes.max(progress.1),
+                delta_per_second: progress.0.get_rate() as u64,
+            })
+            .map_err(|e| format!("Failed to send repair progress to channel: {}", e));
+        if let Err(e) = res {
+            eprintln!("{}", e);
+        }
+    });
+
+    let mut results = vec![];
+    let mut archive_errors = HashMap::new();
+    match source {
+        RepairSource::Files { base_url } => {
+            for (path, entry) in pending.drain() {
+                let result =
+                    repair_from_file_url(&http, &base_url, &install_dir, &entry, &on_write).await;
+                results.push((path, result));
+            }
+        }
+        RepairSource::Archives { archives } => {
+            for archive in &archives {
+                if pending.is_empty() {
+                    break;
+                }
+                let found =
+                    repair_from_archive(&http, archive, &install_dir, pending.clone(), &on_write)
+                        .await;
+                match found {
+                    Ok(found) => {
+                        for (path, result) in found {
+                            pending.remove(&path);
+                            results.push((path, result));
+                        }
+                    }
+                    // The next archives may still have the files
+                    Err(error) => {
+                        for path in pending.keys() {
+                            archive_errors
+                                .insert(path.clone(), format!("{}: {}", archive.file_name, error));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    for (path, result) in results {
+        match result {
+            Ok(()) => report.repaired.push(path),
+            Err(error) => report.failed.push(RepairFailure { path, error }),
+        }
+    }
+    for path in pending.into_keys() {
+        let error = archive_errors
+            .remove(&path)
+            .unwrap_or_else(|| "File was not found in any source".to_string());
+        report.failed.push(RepairFailure { path, error });
+    }
+
+    report.repaired.sort();
+    report.failed.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{entry, TempDir, MTIME};
+
+    fn no_progress() -> ProgressFn {
+        Arc::new(|_| {})
+    }
+
+    #[test]
+    fn write_verified_replaces_the_target_with_its_metadata() {
+        let install = TempDir::new("repair", "replace");
+        std::fs::write(install.0.join("game"), "damaged").unwrap();
+        let entry = ManifestEntry {
+            mode: 0o755,
+            ..entry("game", b"binary")
+        };
+
+        write_verified(&install.0, &entry, &b"binary"[..], &no_progress()).unwrap();
+
+        let target = install.0.join("game");
+        assert_eq!(std::fs::read(&target).unwrap(), b"binary");
+        let metadata = std::fs::metadata(&target).unwrap();
+        assert_eq!(
+            metadata.modified().unwrap(),
+            UNIX_EPOCH + Duration::from_secs(MTIME)
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        }
+        assert!(!install.0.join(".game.repair").exists());
+    }
+
+    #[test]
+    fn write_verified_creates_missing_folders() {
+        let install = TempDir::new("repair", "folders");
+        let entry = entry("data/levels/1.bin", b"level");
+
+        write_verified(&install.0, &entry, &b"level"[..], &no_progress()).unwrap();
+
+        assert_eq!(
+            std::fs::read(install.0.join("data/levels/1.bin")).unwrap(),
+            b"level"
+        );
+    }
+
+    #[test]
+    fn write_verified_keeps_the_old_file_on_mismatch() {
+        let install = TempDir::new("repair", "mismatch");
+        std::fs::write(install.0.join("game"), "old").unwrap();
+        let entry = entry("game", b"binary");
+
+        let error = write_verified(&install.0, &entry, &b"corrupt"[..], &no_progress())
+            .err()
+            .unwrap();
+
+        assert!(error.contains("Integrity check failed"), "{}", error);
+        assert_eq!(std::fs::read(install.0.join("game")).unwrap(), b"old");
+        assert!(!install.0.join(".game.repair").exists());
+    }
+}
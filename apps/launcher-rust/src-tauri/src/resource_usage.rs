@@ -0,0 +1,280 @@
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::ipc::Channel;
+
+use crate::process_tree::ProcessTree;
+
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Clone)]
+pub struct ResourceSample {
+    app_id: String,
+    // Spawned process of the game, the sample covers its whole tree
+    pid: u32,
+    // Seconds since the epoch
+    at: u64,
+    process_count: usize,
+    // Share of one core, above 100 when the game keeps several cores busy
+    cpu_percent: f64,
+    // Resident memory, pages shared between the processes count for each
+    memory_bytes: u64,
+    read_bytes_per_second: u64,
+    write_bytes_per_second: u64,
+    // Since the game started, processes that exited included
+    read_bytes: u64,
+    write_bytes: u64,
+}
+
+/// Highest values over a session, stored with its playtime
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ResourcePeaks {
+    pub cpu_percent: f64,
+    pub memory_bytes: u64,
+    pub read_bytes_per_second: u64,
+    pub write_bytes_per_second: u64,
+}
+
+impl ResourcePeaks {
+    fn add(&mut self, sample: &ResourceSample) {
+        self.cpu_percent = self.cpu_percent.max(sample.cpu_percent);
+        self.memory_bytes = self.memory_bytes.max(sample.memory_bytes);
+        self.read_bytes_per_second = self.read_bytes_per_second.max(sample.read_bytes_per_second);
+        self.write_bytes_per_second = self
+            .write_bytes_per_second
+            .max(sample.write_bytes_per_second);
+    }
+}
+
+/// Latest sample of one running game and the channels it is streamed to
+#[derive(Default)]
+pub struct UsageMonitor {
+    latest: Option<ResourceSample>,
+    // None until the first sample
+    peaks: Option<ResourcePeaks>,
+    subscribers: Vec<Channel<ResourceSample>>,
+}
+
+pub type SharedUsage = Arc<Mutex<UsageMonitor>>;
+
+impl UsageMonitor {
+    /// Streams samples to `channel` until it closes or the game exits, the
+    /// latest one is sent right away
+    pub fn subscribe(&mut self, channel: Channel<ResourceSample>) {
+        if let Some(latest) = &self.latest {
+            if channel.send(latest.clone()).is_err() {
+                return;
+            }
+        }
+        self.subscribers.push(channel);
+    }
+
+    fn publish(&mut self, sample: ResourceSample) {
+        self.peaks.get_or_insert_default().add(&sample);
+        self.subscribers
+            .retain(|channel| channel.send(sample.clone()).is_ok());
+        self.latest = Some(sample);
+    }
+
+    pub fn peaks(&self) -> Option<ResourcePeaks> {
+        self.peaks.clone()
+    }
+}
+
+/// Counters of one process, cumulative since it started
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy)]
+struct ProcessCounters {
+    // Clock ticks after boot, another process when a PID is reused
+    start_time: u64,
+    cpu_ticks: u64,
+    read_bytes: u64,
+    write_bytes: u64,
+    memory_bytes: u64,
+}
+
+#[cfg(target_os = "linux")]
+impl ProcessCounters {
+    /// Reads `/proc/<pid>`, none once the process is gone
+    fn read(pid: u32) -> Option<Self> {
+        // `pid (comm) state ppid ...`, utime and stime are the 14th and 15th
+        // fields, starttime the 22nd
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        let mut fields = stat.rsplit_once(')')?.1.split_whitespace().skip(11);
+        let utime: u64 = fields.next()?.parse().ok()?;
+        let stime: u64 = fields.next()?.parse().ok()?;
+        let start_time: u64 = fields.nth(6)?.parse().ok()?;
+
+        let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+        // Kernel threads and zombies have no memory
+        let memory_kb: u64 = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))
+            .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+            .unwrap_or(0);
+
+        // Not readable for processes that changed their credentials
+        let io = std::fs::read_to_string(format!("/proc/{}/io", pid)).unwrap_or_default();
+        let io_field = |name: &str| {
+            io.lines()
+                .find_map(|line| line.strip_prefix(name))
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(0)
+        };
+
+        Some(Self {
+            start_time,
+            cpu_ticks: utime + stime,
+            read_bytes: io_field("read_bytes:"),
+            write_bytes: io_field("write_bytes:"),
+            memory_bytes: memory_kb * 1024,
+        })
+    }
+}
+
+/// CPU ticks, read and written bytes of the tree since the previous sample.
+/// Processes seen for the first time only set their baseline, counting them
+/// from zero would add all they did before the sample as a spike. The ones
+/// that exited are missing their last moments.
+#[cfg(target_os = "linux")]
+fn counter_deltas(
+    previous: &HashMap<u32, ProcessCounters>,
+    current: &HashMap<u32, ProcessCounters>,
+) -> (u64, u64, u64) {
+    let (mut cpu_ticks, mut read_bytes, mut write_bytes) = (0, 0, 0);
+    for (pid, counters) in current {
+        let Some(before) = previous
+            .get(pid)
+            .filter(|before| before.start_time == counters.start_time)
+        else {
+            continue;
+        };
+        cpu_ticks += counters.cpu_ticks.saturating_sub(before.cpu_ticks);
+        read_bytes += counters.read_bytes.saturating_sub(before.read_bytes);
+        write_bytes += counters.write_bytes.saturating_sub(before.write_bytes);
+    }
+    (cpu_ticks, read_bytes, write_bytes)
+}
+
+#[cfg(target_os = "linux")]
+fn clock_ticks_per_second() -> u64 {
+    use nix::unistd::{sysconf, SysconfVar};
+
+    sysconf(SysconfVar::CLK_TCK)
+        .ok()
+        .flatten()
+        .and_then(|ticks| u64::try_from(ticks).ok())
+        .filter(|ticks| *ticks > 0)
+        // USER_HZ of every Linux architecture
+        .unwrap_or(100)
+}
+
+/// Samples the process tree every `SAMPLE_INTERVAL` until the task is aborted.
+/// Only Linux exposes the counters of other processes cheaply, elsewhere
+/// nothing is sampled.
+pub async fn sample_usage(tree: Arc<ProcessTree>, app_id: String, pid: u32, usage: SharedUsage) {
+    #[cfg(target_os = "linux")]
+    {
+        use std::time::Instant;
+
+        let ticks_per_second = clock_ticks_per_second() as f64;
+        let read_tree = || -> HashMap<u32, ProcessCounters> {
+            tree.members()
+                .into_iter()
+                .filter_map(|pid| Some((pid, ProcessCounters::read(pid)?)))
+                .collect()
+        };
+
+        let mut previous = read_tree();
+        let mut previous_at = Instant::now();
+        let (mut read_bytes, mut write_bytes) = (0, 0);
+        let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // The first tick is immediate
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            let current = read_tree();
+            let now = Instant::now();
+            let elapsed = now
+                .duration_since(previous_at)
+                .as_secs_f64()
+                .max(f64::EPSILON);
+
+            let (cpu_ticks, read_delta, write_delta) = counter_deltas(&previous, &current);
+            read_bytes += read_delta;
+            write_bytes += write_delta;
+
+            if !current.is_empty() {
+                usage.lock().unwrap().publish(ResourceSample {
+                    app_id: app_id.clone(),
+                    pid,
+                    at: crate::process_manager::unix_now(),
+                    process_count: current.len(),
+                    cpu_percent: cpu_ticks as f64 / ticks_per_second / elapsed * 100.0,
+                    memory_bytes: current.values().map(|c| c.memory_bytes).sum(),
+                    read_bytes_per_second: (read_delta as f64 / elapsed) as u64,
+                    write_bytes_per_second: (write_delta as f64 / elapsed) as u64,
+                    read_bytes,
+                    write_bytes,
+                });
+            }
+
+            previous = current;
+            previous_at = now;
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    let _ = (tree, app_id, pid, usage);
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    fn counters(start_time: u64, cpu_ticks: u64, read_bytes: u64) -> ProcessCounters {
+        ProcessCounters {
+            start_time,
+            cpu_ticks,
+            read_bytes,
+            write_bytes: read_bytes * 2,
+            memory_bytes: 0,
+        }
+    }
+
+    #[test]
+    fn new_and_reused_pids_only_set_their_baseline() {
+        let previous = HashMap::from([(10, counters(5, 100, 1000)), (11, counters(6, 50, 500))]);
+        let current = HashMap::from([
+            // Same process, only what it did since counts
+            (10, counters(5, 130, 1600)),
+            // PID reused by a process started later
+            (11, counters(90, 4000, 900_000)),
+            // Child that started between the samples
+            (12, counters(95, 7000, 5_000_000)),
+        ]);
+        assert_eq!(counter_deltas(&previous, &current), (30, 600, 1200));
+
+        // Both count normally once they have a baseline
+        let next = HashMap::from([
+            (11, counters(90, 4010, 900_100)),
+            (12, counters(95, 7005, 5_000_000)),
+        ]);
+        assert_eq!(counter_deltas(&current, &next), (15, 100, 200));
+    }
+
+    #[test]
+    fn reads_own_start_time() {
+        let pid = std::process::id();
+        let first = ProcessCounters::read(pid).unwrap();
+        let second = ProcessCounters::read(pid).unwrap();
+        assert_eq!(first.start_time, second.start_time);
+        assert!(first.start_time > 0);
+        assert!(second.cpu_ticks >= first.cpu_ticks);
+    }
+}
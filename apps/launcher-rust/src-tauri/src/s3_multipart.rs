@@ -0,0 +1,500 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::{StreamExt, TryStreamExt};
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::http_client::HttpClient;
+use crate::rate_meter::RateMeter;
+use crate::tracking_tokio_stream::TrackingTokioStream;
+use crate::ProgressCallbackData;
+
+const DEFAULT_CONCURRENCY: usize = 4;
+const MAX_PART_RETRIES: u32 = 5;
+const PART_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_PART_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Multipart upload that the backend created in the bucket.
+/// `part_urls` are presigned `UploadPart` URLs in part number order,
+/// `complete_url` and `abort_url` are presigned `CompleteMultipartUpload`
+/// and `AbortMultipartUpload` URLs for the same upload id.
+#[derive(Deserialize)]
+pub struct PresignedMultipartUpload {
+    pub part_size: u64,
+    pub part_urls: Vec<String>,
+    pub complete_url: String,
+    pub abort_url: Option<String>,
+}
+
+struct CompletedPart {
+    part_number: usize,
+    etag: String,
+}
+
+enum PartError {
+    Retryable(String),
+    Fatal(String),
+}
+
+struct PartUploader {
+    client: reqwest::Client,
+    file_path: PathBuf,
+    // Bytes confirmed plus bytes in flight across all parts
+    uploaded_bytes: AtomicU64,
+    on_progress: Mutex<Box<dyn FnMut(u64) + Send>>,
+}
+
+impl PartUploader {
+    fn report(&self, delta: u64) {
+        let current_bytes = self.uploaded_bytes.fetch_add(delta, Ordering::Relaxed) + delta;
+        (self.on_progress.lock().unwrap())(current_bytes);
+    }
+
+    async fn put_part(
+        self: &Arc<Self>,
+        url: &str,
+        offset: u64,
+        length: u64,
+    ) -> Result<String, PartError> {
+        let mut file = tokio::fs::File::open(&self.file_path)
+            .await
+            .map_err(|e| PartError::Fatal(format!("Failed to open file: {}", e)))?;
+        file.seek(std::io::SeekFrom::Start(offset))
+            .await
+            .map_err(|e| PartError::Fatal(format!("Failed to seek file: {}", e)))?;
+
+        let sent = Arc::new(AtomicU64::new(0));
+        let sent_clone = sent.clone();
+        let uploader = self.clone();
+        let tracker = TrackingTokioStream::new(file.take(length), move |read_len| {
+            sent_clone.fetch_add(read_len, Ordering::Relaxed);
+            uploader.report(read_len);
+        });
+
+        let result = self
+            .client
+            .put(url)
+            .header(reqwest::header::CONTENT_LENGTH, length)
+            .body(reqwest::Body::wrap_stream(tracker))
+            .send()
+            .await;
+
+        let response = match result {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                self.rollback(&sent);
+                let status = response.status();
+                let error_text = response.text().await.unwrap_or_default();
+                let message = format!("Part upload failed with status {}: {}", status, error_text);
+                return Err(if status.is_server_error() || status.as_u16() == 429 {
+                    PartError::Retryable(message)
+                } else {
+                    PartError::Fatal(message)
+                });
+            }
+            Err(e) => {
+                self.rollback(&sent);
+                return Err(PartError::Retryable(format!("Failed to send part: {}", e)));
+            }
+        };
+
+        response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+            .ok_or_else(|| PartError::Retryable("Part response has no ETag header".to_string()))
+    }
+
+    fn rollback(&self, sent: &AtomicU64) {
+        self.uploaded_bytes
+            .fetch_sub(sent.swap(0, Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    async fn upload_part(
+        self: Arc<Self>,
+        part_number: usize,
+        url: String,
+        offset: u64,
+        length: u64,
+    ) -> Result<CompletedPart, String> {
+        let mut attempt = 0;
+        loop {
+            match self.put_part(&url, offset, length).await {
+                Ok(etag) => return Ok(CompletedPart { part_number, etag }),
+                Err(PartError::Fatal(message)) => {
+                    return Err(format!("Part {}: {}", part_number, message))
+                }
+                Err(PartError::Retryable(message)) => {
+                    attempt += 1;
+                    if attempt > MAX_PART_RETRIES {
+                        return Err(format!(
+                            "Part {} failed after {} retries: {}",
+                            part_number, MAX_PART_RETRIES, message
+                        ));
+                    }
+
+                    let delay = PART_RETRY_DELAY
+                        .saturating_mul(2_u32.saturating_pow(attempt - 1))
+                        .min(MAX_PART_RETRY_DELAY);
+                    eprintln!("Part {}: {}, retrying in {:?}", part_number, message, delay);
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+}
+
+fn complete_request_body(parts: &[CompletedPart]) -> String {
+    let parts = parts
+        .iter()
+        .map(|part| {
+            format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                part.part_number,
+                part.etag.replace('&', "&amp;").replace('"', "&quot;")
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+        parts
+    )
+}
+
+/// Uploads the file parts in parallel and completes the multipart upload
+pub async fn upload_multipart<F: FnMut(u64) + Send + 'static>(
+    client: reqwest::Client,
+    file_path: &Path,
+    upload: &PresignedMultipartUpload,
+    concurrency: usize,
+    on_progress: F,
+) -> Result<(), String> {
+    let file_size = std::fs::metadata(file_path)
+        .map_err(|e| format!("Failed to get file metadata: {}", e))?
+        .len();
+
+    if upload.part_size == 0 {
+        return Err("Part size must be greater than zero".to_string());
+    }
+
+    let parts_count = file_size.div_ceil(upload.part_size).max(1) as usize;
+    if upload.part_urls.len() != parts_count {
+        return Err(format!(
+            "Expected {} part URLs for {} bytes, got {}",
+            parts_count,
+            file_size,
+            upload.part_urls.len()
+        ));
+    }
+
+    let uploader = Arc::new(PartUploader {
+        client: client.clone(),
+        file_path: file_path.to_path_buf(),
+        uploaded_bytes: AtomicU64::new(0),
+        on_progress: Mutex::new(Box::new(on_progress)),
+    });
+
+    let parts = upload.part_urls.iter().enumerate().map(|(index, url)| {
+        let offset = index as u64 * upload.part_size;
+        let length = upload.part_size.min(file_size - offset);
+        uploader
+            .clone()
+            .upload_part(index + 1, url.clone(), offset, length)
+    });
+
+    let result = futures_util::stream::iter(parts)
+        .buffer_unordered(concurrency.max(1))
+        .try_collect::<Vec<_>>()
+        .await;
+
+    let mut parts = match result {
+        Ok(parts) => parts,
+        Err(e) => {
+            // Best effort, otherwise the bucket keeps the orphaned parts until its lifecycle rule
+            if let Some(abort_url) = &upload.abort_url {
+                if let Err(abort_error) = client.delete(abort_url).send().await {
+                    eprintln!("Failed to abort multipart upload: {}", abort_error);
+                }
+            }
+            return Err(e);
+        }
+    };
+    parts.sort_by_key(|part| part.part_number);
+
+    let response = client
+        .post(&upload.complete_url)
+        .header(reqwest::header::CONTENT_TYPE, "application/xml")
+        .body(complete_request_body(&parts))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to complete multipart upload: {}", e))?;
+
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .unwrap_or_else(|_| "Unknown error".to_string());
+
+    // S3 may answer 200 and still report an error in the body
+    if !status.is_success() || body.contains("<Error>") {
+        return Err(format!(
+            "Completing multipart upload failed with status {}: {}",
+            status, body
+        ));
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn upload_file_s3_multipart(
+    http: tauri::State<'_, HttpClient>,
+    file_path: String,
+    upload: PresignedMultipartUpload,
+    concurrency: Option<usize>,
+    progress_channel: tauri::ipc::Channel<ProgressCallbackData>,
+    speed_update_interval: Option<f64>,
+) -> Result<(), String> {
+    let file_path = Path::new(&file_path);
+    if !file_path.exists() {
+        return Err(format!("File does not exist: {}", file_path.display()));
+    }
+
+    let total_bytes = std::fs::metadata(file_path)
+        .map_err(|e| format!("Failed to get file metadata: {}", e))?
+        .len();
+
+    let progress_channel_clone = progress_channel.clone();
+    let mut uploading_speed_rate = RateMeter::new(Duration::from_secs_f64(
+        speed_update_interval.unwrap_or(1.0),
+    ));
+    let mut last_bytes = 0_u64;
+    let on_progress = move |current_bytes: u64| {
+        uploading_speed_rate.add_value(current_bytes.saturating_sub(last_bytes));
+        last_bytes = current_bytes;
+
+        let res = progress_channel_clone
+            .send(ProgressCallbackData {
+                current_bytes,
+                total_bytes,
+                delta_per_second: uploading_speed_rate.get_rate() as u64,
+            })
+            .map_err(|e| format!("Failed to send upload progress to channel: {}", e));
+        if let Err(e) = res {
+            eprintln!("{}", e);
+        }
+    };
+
+    upload_multipart(
+        http.client(),
+        file_path,
+        &upload,
+        concurrency.unwrap_or(DEFAULT_CONCURRENCY),
+        on_progress,
+    )
+    .await?;
+
+    let _ = progress_channel.send(ProgressCallbackData {
+        current_bytes: total_bytes,
+        total_bytes,
+        delta_per_second: 0,
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    use crate::test_support::{test_data, Request, Response, TempDir, TestServer};
+
+    const PART_SIZE: u64 = 4096;
+
+    #[derive(Default)]
+    struct BucketState {
+        parts: BTreeMap<usize, Vec<u8>>,
+        part_requests: usize,
+        completed: Option<String>,
+        aborted: bool,
+        // Statuses answered to the next requests for a part, before storing it
+        part_failures: BTreeMap<usize, Vec<u16>>,
+        complete_body: Option<String>,
+    }
+
+    /// Stands in for the presigned URLs of one multipart upload
+    fn bucket(state: Arc<Mutex<BucketState>>) -> TestServer {
+        TestServer::start(move |request: Request| {
+            let mut state = state.lock().unwrap();
+            let (_, query) = request.path.split_once('?').unwrap_or((&request.path, ""));
+            let part_number = query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("partNumber="))
+                .and_then(|n| n.parse::<usize>().ok());
+
+            match (request.method.as_str(), part_number) {
+                ("PUT", Some(part_number)) => {
+                    state.part_requests += 1;
+                    if let Some(status) = state
+                        .part_failures
+                        .get_mut(&part_number)
+                        .and_then(|failures| failures.pop())
+                    {
+                        return Response::new(status);
+                    }
+                    state.parts.insert(part_number, request.body);
+                    Response::new(200).header("ETag", format!("\"etag-{}\"", part_number))
+                }
+                ("POST", None) => {
+                    state.completed = Some(String::from_utf8(request.body).unwrap());
+                    let body = state.complete_body.clone().unwrap_or_else(|| {
+                        "<CompleteMultipartUploadResult></CompleteMultipartUploadResult>"
+                            .to_string()
+                    });
+                    Response::new(200).body(body)
+                }
+                ("DELETE", None) => {
+                    state.aborted = true;
+                    Response::new(204)
+                }
+                _ => Response::new(400),
+            }
+        })
+    }
+
+    struct Fixture {
+        _dir: TempDir,
+        file: std::path::PathBuf,
+        data: Vec<u8>,
+        state: Arc<Mutex<BucketState>>,
+        upload: PresignedMultipartUpload,
+    }
+
+    impl Fixture {
+        fn new(name: &str, len: usize) -> Self {
+            let dir = TempDir::new("s3_multipart", name);
+            let data = test_data(len);
+            let file = dir.0.join("build.tar.gz");
+            std::fs::write(&file, &data).unwrap();
+
+            let state = Arc::new(Mutex::new(BucketState::default()));
+            let server = bucket(state.clone());
+            let object = format!("{}/bucket/build.tar.gz", server.url);
+            let parts = (len as u64).div_ceil(PART_SIZE).max(1) as usize;
+
+            Self {
+                upload: PresignedMultipartUpload {
+                    part_size: PART_SIZE,
+                    part_urls: (1..=parts)
+                        .map(|n| format!("{}?partNumber={}&uploadId=u1", object, n))
+                        .collect(),
+                    complete_url: format!("{}?uploadId=u1", object),
+                    abort_url: Some(format!("{}?uploadId=u1", object)),
+                },
+                _dir: dir,
+                file,
+                data,
+                state,
+            }
+        }
+
+        async fn upload(&self) -> Result<(), String> {
+            upload_multipart(reqwest::Client::new(), &self.file, &self.upload, 2, |_| {}).await
+        }
+
+        fn assembled(&self) -> Vec<u8> {
+            self.state
+                .lock()
+                .unwrap()
+                .parts
+                .values()
+                .flatten()
+                .copied()
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn uploads_parts_and_completes_in_order() {
+        let fixture = Fixture::new("complete", 10_000);
+
+        fixture.upload().await.unwrap();
+
+        assert_eq!(fixture.assembled(), fixture.data);
+        let state = fixture.state.lock().unwrap();
+        assert_eq!(
+            state.completed.as_deref(),
+            Some(
+                "<CompleteMultipartUpload>\
+                 <Part><PartNumber>1</PartNumber><ETag>&quot;etag-1&quot;</ETag></Part>\
+                 <Part><PartNumber>2</PartNumber><ETag>&quot;etag-2&quot;</ETag></Part>\
+                 <Part><PartNumber>3</PartNumber><ETag>&quot;etag-3&quot;</ETag></Part>\
+                 </CompleteMultipartUpload>"
+            )
+        );
+        assert!(!state.aborted);
+    }
+
+    #[tokio::test]
+    async fn retries_part_after_server_error() {
+        let fixture = Fixture::new("retry", 10_000);
+        fixture
+            .state
+            .lock()
+            .unwrap()
+            .part_failures
+            .insert(2, vec![503]);
+
+        fixture.upload().await.unwrap();
+
+        assert_eq!(fixture.assembled(), fixture.data);
+        assert_eq!(fixture.state.lock().unwrap().part_requests, 4);
+    }
+
+    #[tokio::test]
+    async fn aborts_upload_when_part_is_rejected() {
+        let fixture = Fixture::new("abort", 10_000);
+        fixture
+            .state
+            .lock()
+            .unwrap()
+            .part_failures
+            .insert(2, vec![403]);
+
+        let error = fixture.upload().await.unwrap_err();
+
+        assert!(error.contains("Part 2"), "{}", error);
+        let state = fixture.state.lock().unwrap();
+        assert!(state.aborted);
+        assert!(state.completed.is_none());
+    }
+
+    #[tokio::test]
+    async fn fails_when_completion_reports_error() {
+        let fixture = Fixture::new("complete_error", 10_000);
+        fixture.state.lock().unwrap().complete_body =
+            Some("<Error><Code>InvalidPart</Code></Error>".to_string());
+
+        let error = fixture.upload().await.unwrap_err();
+
+        assert!(error.contains("InvalidPart"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn rejects_part_urls_that_do_not_cover_file() {
+        let mut fixture = Fixture::new("part_count", 10_000);
+        fixture.upload.part_urls.pop();
+
+        let error = fixture.upload().await.unwrap_err();
+
+        assert!(error.contains("Expected 3 part URLs"), "{}", error);
+        assert_eq!(fixture.state.lock().unwrap().part_requests, 0);
+    }
+}
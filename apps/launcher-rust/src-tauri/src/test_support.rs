@@ -0,0 +1,219 @@
+//! Fixtures shared by the unit tests
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::manifest::{Manifest, ManifestEntry};
+
+/// Modification time of the files described by `entry`
+pub const MTIME: u64 = 1_700_000_000;
+
+/// Folder under the system temp dir, removed on drop, also when a test panics
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    /// `prefix` and `name` keep the dirs of parallel tests apart
+    pub fn new(prefix: &str, name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("{}_{}_{}", prefix, std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    /// Writes a file at `path` under the dir, modified at `mtime`
+    pub fn write(&self, path: &str, data: &[u8], mtime: u64) {
+        let path = self.0.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut file = std::fs::File::create(&path).unwrap();
+        file.write_all(data).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(mtime))
+            .unwrap();
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+pub fn hash(data: &[u8]) -> String {
+    format!("blake3:{}", blake3::hash(data).to_hex())
+}
+
+/// Manifest entry of a file holding `data`, written at `MTIME`
+pub fn entry(path: &str, data: &[u8]) -> ManifestEntry {
+    ManifestEntry {
+        path: path.to_string(),
+        size: data.len() as u64,
+        mode: 0o644,
+        mtime: Some(MTIME),
+        hash: hash(data),
+        chunks: vec![],
+    }
+}
+
+pub fn manifest(files: &[(&str, &[u8])]) -> Manifest {
+    let mut manifest = Manifest::new(None);
+    for (path, data) in files {
+        manifest.push(entry(path, data));
+    }
+    manifest
+}
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: vec![],
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: impl ToString) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+/// HTTP/1.1 server on a loopback port standing in for the backend, every
+/// request is answered by `handler` on the connection's thread
+pub struct TestServer {
+    pub url: String,
+}
+
+impl TestServer {
+    pub fn start<H>(handler: H) -> Self
+    where
+        H: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handler = Arc::new(handler);
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let handler = handler.clone();
+                std::thread::spawn(move || serve_connection(stream, &*handler));
+            }
+        });
+
+        Self { url }
+    }
+}
+
+fn serve_connection(stream: TcpStream, handler: &dyn Fn(Request) -> Response) {
+    let mut writer = stream.try_clone().unwrap();
+    let mut reader = BufReader::new(stream);
+
+    // Keep-alive, the client reuses pooled connections
+    while let Some(request) = read_request(&mut reader) {
+        let head_only = request.method == "HEAD";
+        let response = handler(request);
+
+        let mut head = format!("HTTP/1.1 {} Test\r\n", response.status);
+        for (name, value) in &response.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if !head_only {
+            head.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+        }
+        head.push_str("\r\n");
+
+        let mut bytes = head.into_bytes();
+        if !head_only {
+            bytes.extend_from_slice(&response.body);
+        }
+        if writer.write_all(&bytes).is_err() {
+            return;
+        }
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> Option<String> {
+    let mut line = String::new();
+    match reader.read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim_end().to_string()),
+    }
+}
+
+fn read_request(reader: &mut impl BufRead) -> Option<Request> {
+    let request_line = read_line(reader)?;
+    let mut parts = request_line.split(' ');
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut headers = vec![];
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':')?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: vec![],
+    };
+
+    if request
+        .header("Transfer-Encoding")
+        .is_some_and(|value| value.eq_ignore_ascii_case("chunked"))
+    {
+        loop {
+            let size = usize::from_str_radix(&read_line(reader)?, 16).ok()?;
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).ok()?;
+            if size == 0 {
+                break;
+            }
+            request.body.extend_from_slice(&chunk[..size]);
+        }
+    } else if let Some(length) = request.header("Content-Length") {
+        let mut body = vec![0; length.parse().ok()?];
+        reader.read_exact(&mut body).ok()?;
+        request.body = body;
+    }
+
+    Some(request)
+}
+
+/// Bytes that differ at every position, so misplaced chunks are noticed
+pub fn test_data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 + i / 251) as u8).collect()
+}
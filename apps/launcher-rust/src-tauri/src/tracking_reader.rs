@@ -1,4 +1,4 @@
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
 
 pub struct TrackingReader<T: Read, F: FnMut(&mut [u8])> {
     pub source: T,
@@ -19,3 +19,9 @@ impl<T: Read, F: FnMut(&mut [u8])> Read for TrackingReader<T, F> {
         Ok(n)
     }
 }
+
+impl<T: Read + Seek, F: FnMut(&mut [u8])> Seek for TrackingReader<T, F> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.source.seek(pos)
+    }
+}
@@ -0,0 +1,733 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::Mutex;
+
+use crate::http_client::HttpClient;
+use crate::json_store::{load_or_backup, write_atomically};
+use crate::rate_meter::RateMeter;
+use crate::tracking_tokio_stream::TrackingTokioStream;
+use crate::ProgressCallbackData;
+
+const TUS_VERSION: &str = "1.0.0";
+const DEFAULT_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+const DEFAULT_MAX_RETRIES: u32 = 5;
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Upload that was started but not finished, persisted across launcher restarts
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TusUploadState {
+    pub upload_url: String,
+    pub offset: u64,
+    pub file_size: u64,
+    pub modified: u64,
+}
+
+pub struct TusUploadStore {
+    path: PathBuf,
+    uploads: HashMap<String, TusUploadState>,
+}
+
+impl TusUploadStore {
+    /// Loads the store from `path`, starting empty if the file is missing.
+    /// A broken file is kept as `.bak` next to it.
+    pub fn load(path: PathBuf) -> Self {
+        let uploads = load_or_backup(&path);
+
+        Self { path, uploads }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&TusUploadState> {
+        self.uploads.get(key)
+    }
+
+    pub fn set(&mut self, key: String, state: TusUploadState) -> Result<(), String> {
+        self.uploads.insert(key, state);
+        self.save()
+    }
+
+    pub fn remove(&mut self, key: &str) -> Result<(), String> {
+        if self.uploads.remove(key).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create uploads state dir: {}", e))?;
+        }
+
+        let json = serde_json::to_string_pretty(&self.uploads)
+            .map_err(|e| format!("Failed to serialize uploads state: {}", e))?;
+
+        write_atomically(&self.path, &json)
+            .map_err(|e| format!("Failed to write uploads state: {}", e))
+    }
+
+    /// The saved upload of a file, unless the file changed since it was started
+    fn resumable(&self, key: &str, (file_size, modified): (u64, u64)) -> Option<&TusUploadState> {
+        self.get(key)
+            .filter(|s| s.file_size == file_size && s.modified == modified)
+    }
+}
+
+/// Key of the saved upload of a file to an endpoint
+fn upload_key(endpoint: &str, file_path: &Path) -> String {
+    format!("{}|{}", endpoint, file_path.display())
+}
+
+/// Size and modification time of a file, a saved upload of it is resumed
+/// only while they stay the same
+fn file_version(file_path: &Path) -> Result<(u64, u64), String> {
+    let metadata =
+        std::fs::metadata(file_path).map_err(|e| format!("Failed to get file metadata: {}", e))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Ok((metadata.len(), modified))
+}
+
+/// Managed state shared by all resumable upload commands
+pub type TusUploads = Mutex<TusUploadStore>;
+
+enum ChunkError {
+    /// The server no longer knows the upload, it has to be created again
+    Gone,
+    /// The chunk can be retried after re-reading the offset
+    Retryable(String),
+    Fatal(String),
+}
+
+/// Client for the tus 1.0 resumable upload protocol (core + creation extensions)
+pub struct TusClient {
+    client: reqwest::Client,
+    endpoint: String,
+    headers: Vec<(String, String)>,
+    chunk_size: u64,
+    max_retries: u32,
+    retry_delay: Duration,
+}
+
+impl TusClient {
+    pub fn new(client: reqwest::Client, endpoint: String) -> Self {
+        Self {
+            client,
+            endpoint,
+            headers: vec![],
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_delay: DEFAULT_RETRY_DELAY,
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: String) -> Self {
+        self.headers.push((name.to_string(), value));
+        self
+    }
+
+    pub fn chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    pub fn retries(mut self, max_retries: u32, retry_delay: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        let mut request = self
+            .client
+            .request(method, url)
+            .header("Tus-Resumable", TUS_VERSION);
+
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        request
+    }
+
+    /// Creates a new upload and returns its absolute URL
+    pub async fn create(
+        &self,
+        file_size: u64,
+        metadata: &[(&str, &str)],
+    ) -> Result<String, String> {
+        let metadata = metadata
+            .iter()
+            .map(|(key, value)| {
+                format!(
+                    "{} {}",
+                    key,
+                    base64::engine::general_purpose::STANDARD.encode(value)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let response = self
+            .request(reqwest::Method::POST, &self.endpoint)
+            .header("Upload-Length", file_size)
+            .header("Upload-Metadata", metadata)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to create upload: {}", e))?;
+
+        if response.status() != reqwest::StatusCode::CREATED {
+            let status = response.status();
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(format!(
+                "Upload creation failed with status {}: {}",
+                status, error_text
+            ));
+        }
+
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| "Upload creation response has no Location header".to_string())?;
+
+        // Location may be relative to the creation endpoint
+        response
+            .url()
+            .join(location)
+            .map(|url| url.to_string())
+            .map_err(|e| format!("Invalid upload location {}: {}", location, e))
+    }
+
+    /// Asks the server how many bytes it already has, `None` if the upload is gone
+    pub async fn offset(&self, upload_url: &str) -> Result<Option<u64>, String> {
+        let response = self
+            .request(reqwest::Method::HEAD, upload_url)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to get upload offset: {}", e))?;
+
+        let status = response.status();
+        if matches!(
+            status,
+            reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::GONE
+        ) {
+            return Ok(None);
+        }
+
+        if !status.is_success() {
+            return Err(format!("Offset request failed with status {}", status));
+        }
+
+        read_offset_header(&response).map(Some)
+    }
+
+    async fn upload_chunk<F: FnMut(u64) + Send + Unpin + 'static>(
+        &self,
+        upload_url: &str,
+        file_path: &Path,
+        offset: u64,
+        length: u64,
+        on_progress: F,
+    ) -> Result<u64, ChunkError> {
+        let mut file = tokio::fs::File::open(file_path)
+            .await
+            .map_err(|e| ChunkError::Fatal(format!("Failed to open file: {}", e)))?;
+        file.seek(std::io::SeekFrom::Start(offset))
+            .await
+            .map_err(|e| ChunkError::Fatal(format!("Failed to seek file: {}", e)))?;
+
+        let tracker = TrackingTokioStream::new(file.take(length), on_progress);
+
+        let response = self
+            .request(reqwest::Method::PATCH, upload_url)
+            .header("Upload-Offset", offset)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                "application/offset+octet-stream",
+            )
+            .header(reqwest::header::CONTENT_LENGTH, length)
+            .body(reqwest::Body::wrap_stream(tracker))
+            .send()
+            .await
+            .map_err(|e| ChunkError::Retryable(format!("Failed to send chunk: {}", e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return read_offset_header(&response).map_err(ChunkError::Retryable);
+        }
+
+        match status {
+            reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::GONE => Err(ChunkError::Gone),
+            // Offset mismatch, the next attempt re-reads it from the server
+            reqwest::StatusCode::CONFLICT => Err(ChunkError::Retryable(format!(
+                "Offset {} was rejected by the server",
+                offset
+            ))),
+            status if status.is_server_error() || status.as_u16() == 429 => Err(
+                ChunkError::Retryable(format!("Chunk upload failed with status {}", status)),
+            ),
+            status => Err(ChunkError::Fatal(format!(
+                "Chunk upload failed with status {}",
+                status
+            ))),
+        }
+    }
+
+    /// Creates the upload again after the server lost it. Counted separately
+    /// from chunk retries, a server that keeps losing uploads fails the upload.
+    async fn recreate(
+        &self,
+        recreated: &mut u32,
+        file_size: u64,
+        filename: &str,
+    ) -> Result<String, String> {
+        *recreated += 1;
+        if *recreated > self.max_retries {
+            return Err(format!(
+                "Upload failed, the server lost it {} times",
+                self.max_retries
+            ));
+        }
+        self.create(file_size, &[("filename", filename)]).await
+    }
+
+    /// Uploads the whole file, resuming a previous upload stored under the same key.
+    /// `on_progress` receives the number of bytes the server has confirmed so far
+    /// plus the bytes of the chunk that is in flight.
+    pub async fn upload_file<F: FnMut(u64) + Clone + Send + Unpin + 'static>(
+        &self,
+        file_path: &Path,
+        store: &TusUploads,
+        on_progress: F,
+    ) -> Result<String, String> {
+        let (file_size, modified) = file_version(file_path)?;
+
+        let filename = file_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| "Invalid filename".to_string())?;
+
+        let key = upload_key(&self.endpoint, file_path);
+
+        // Resume only if the file did not change since the upload was started
+        let saved = store
+            .lock()
+            .await
+            .resumable(&key, (file_size, modified))
+            .cloned();
+
+        let mut upload_url = match saved {
+            Some(state) => state.upload_url,
+            None => self.create(file_size, &[("filename", filename)]).await?,
+        };
+        let mut recreated = 0;
+        let mut offset = match self.offset(&upload_url).await? {
+            Some(offset) => offset,
+            None => {
+                upload_url = self.recreate(&mut recreated, file_size, filename).await?;
+                0
+            }
+        };
+
+        let mut attempt = 0;
+        while offset < file_size {
+            store.lock().await.set(
+                key.clone(),
+                TusUploadState {
+                    upload_url: upload_url.clone(),
+                    offset,
+                    file_size,
+                    modified,
+                },
+            )?;
+
+            let length = self.chunk_size.min(file_size - offset);
+            let mut chunk_progress = on_progress.clone();
+            let mut sent = offset;
+            let result = self
+                .upload_chunk(&upload_url, file_path, offset, length, move |read_len| {
+                    sent += read_len;
+                    chunk_progress(sent);
+                })
+                .await;
+
+            match result {
+                Ok(new_offset) => {
+                    offset = new_offset;
+                    attempt = 0;
+                }
+                Err(ChunkError::Fatal(message)) => return Err(message),
+                Err(ChunkError::Gone) => {
+                    upload_url = self.recreate(&mut recreated, file_size, filename).await?;
+                    offset = 0;
+                }
+                Err(ChunkError::Retryable(message)) => {
+                    attempt += 1;
+                    if attempt > self.max_retries {
+                        return Err(format!(
+                            "Upload failed after {} retries: {}",
+                            self.max_retries, message
+                        ));
+                    }
+
+                    let delay = self
+                        .retry_delay
+                        .saturating_mul(2_u32.saturating_pow(attempt - 1))
+                        .min(MAX_RETRY_DELAY);
+                    eprintln!("{}, retrying in {:?}", message, delay);
+                    tokio::time::sleep(delay).await;
+
+                    // The server is the source of truth for how much of the chunk arrived
+                    match self.offset(&upload_url).await {
+                        Ok(Some(server_offset)) => offset = server_offset,
+                        Ok(None) => {
+                            upload_url = self.recreate(&mut recreated, file_size, filename).await?;
+                            offset = 0;
+                        }
+                        Err(e) => eprintln!("{}", e),
+                    }
+                }
+            }
+        }
+
+        store.lock().await.remove(&key)?;
+
+        Ok(upload_url)
+    }
+}
+
+fn read_offset_header(response: &reqwest::Response) -> Result<u64, String> {
+    response
+        .headers()
+        .get("Upload-Offset")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| "Response has no valid Upload-Offset header".to_string())
+}
+
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub async fn upload_file_resumable(
+    http: tauri::State<'_, HttpClient>,
+    store: tauri::State<'_, TusUploads>,
+    endpoint: String,
+    file_path: String,
+    auth_token: Option<String>,
+    chunk_size: Option<u64>,
+    progress_channel: tauri::ipc::Channel<ProgressCallbackData>,
+    speed_update_interval: Option<f64>,
+) -> Result<String, String> {
+    let file_path = Path::new(&file_path);
+    if !file_path.exists() {
+        return Err(format!("File does not exist: {}", file_path.display()));
+    }
+
+    let total_bytes = std::fs::metadata(file_path)
+        .map_err(|e| format!("Failed to get file metadata: {}", e))?
+        .len();
+
+    let mut client = TusClient::new(http.client(), endpoint);
+    if let Some(token) = auth_token {
+        client = client.header("Authorization", format!("Bearer {}", token));
+    }
+    if let Some(chunk_size) = chunk_size {
+        client = client.chunk_size(chunk_size);
+    }
+
+    // The callback is cloned for every chunk, so the meter and the last
+    // reported position are shared between the clones
+    let uploading_speed_rate = std::sync::Arc::new(std::sync::Mutex::new((
+        RateMeter::new(Duration::from_secs_f64(
+            speed_update_interval.unwrap_or(1.0),
+        )),
+        0_u64,
+    )));
+    let progress_channel_clone = progress_channel.clone();
+    let on_progress = move |current_bytes: u64| {
+        let mut guard = uploading_speed_rate.lock().unwrap();
+        let (rate, last_bytes) = &mut *guard;
+        rate.add_value(current_bytes.saturating_sub(*last_bytes));
+        *last_bytes = current_bytes;
+
+        let res = progress_channel_clone
+            .send(ProgressCallbackData {
+                current_bytes,
+                total_bytes,
+                delta_per_second: rate.get_rate() as u64,
+            })
+            .map_err(|e| format!("Failed to send upload progress to channel: {}", e));
+        if let Err(e) = res {
+            eprintln!("{}", e);
+        }
+    };
+
+    let upload_url = client.upload_file(file_path, &store, on_progress).await?;
+
+    let _ = progress_channel.send(ProgressCallbackData {
+        current_bytes: total_bytes,
+        total_bytes,
+        delta_per_second: 0,
+    });
+
+    Ok(upload_url)
+}
+
+/// Whether `upload_file_resumable` would continue a saved upload of the
+/// file, so the caller can keep the file instead of producing it again
+#[tauri::command]
+pub async fn has_resumable_upload(
+    store: tauri::State<'_, TusUploads>,
+    endpoint: String,
+    file_path: String,
+) -> Result<bool, String> {
+    let file_path = Path::new(&file_path);
+    if !file_path.exists() {
+        return Ok(false);
+    }
+
+    let version = file_version(file_path)?;
+    Ok(store
+        .lock()
+        .await
+        .resumable(&upload_key(&endpoint, file_path), version)
+        .is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::test_support::{test_data, Request, Response, TempDir, TestServer};
+
+    #[derive(Default)]
+    struct TusServerState {
+        uploads: Vec<(u64, Vec<u8>)>,
+        creates: usize,
+        patches: usize,
+        // Status answered to the n-th PATCH (from 1) after storing half of its body
+        failing_patches: HashMap<usize, u16>,
+        // Status answered to every PATCH without storing anything
+        rejecting_patches: Option<u16>,
+    }
+
+    /// tus core + creation, uploads live at `/files/<index>`
+    fn tus_server(state: Arc<Mutex<TusServerState>>) -> TestServer {
+        TestServer::start(move |request: Request| {
+            if request.header("Tus-Resumable") != Some("1.0.0") {
+                return Response::new(412);
+            }
+
+            let mut state = state.lock().unwrap();
+            if request.method == "POST" && request.path == "/files" {
+                let Some(length) = request.header("Upload-Length").and_then(|v| v.parse().ok())
+                else {
+                    return Response::new(400);
+                };
+                state.creates += 1;
+                state.uploads.push((length, vec![]));
+                return Response::new(201)
+                    .header("Location", format!("/files/{}", state.uploads.len() - 1));
+            }
+
+            let Some(index) = request
+                .path
+                .strip_prefix("/files/")
+                .and_then(|id| id.parse::<usize>().ok())
+                .filter(|index| *index < state.uploads.len())
+            else {
+                return Response::new(404);
+            };
+
+            match request.method.as_str() {
+                "HEAD" => {
+                    let (length, data) = &state.uploads[index];
+                    Response::new(200)
+                        .header("Upload-Offset", data.len())
+                        .header("Upload-Length", length)
+                }
+                "PATCH" => {
+                    state.patches += 1;
+                    if let Some(status) = state.rejecting_patches {
+                        return Response::new(status);
+                    }
+
+                    let offset: usize = request.header("Upload-Offset").unwrap().parse().unwrap();
+                    if offset != state.uploads[index].1.len() {
+                        return Response::new(409);
+                    }
+
+                    let patch = state.patches;
+                    if let Some(status) = state.failing_patches.get(&patch).copied() {
+                        let half = request.body.len() / 2;
+                        state.uploads[index]
+                            .1
+                            .extend_from_slice(&request.body[..half]);
+                        return Response::new(status);
+                    }
+
+                    let data = &mut state.uploads[index].1;
+                    data.extend_from_slice(&request.body);
+                    Response::new(204).header("Upload-Offset", data.len())
+                }
+                _ => Response::new(405),
+            }
+        })
+    }
+
+    struct Fixture {
+        dir: TempDir,
+        file: PathBuf,
+        data: Vec<u8>,
+        state: Arc<Mutex<TusServerState>>,
+        endpoint: String,
+    }
+
+    impl Fixture {
+        fn new(name: &str, len: usize) -> Self {
+            let dir = TempDir::new("tus", name);
+            let data = test_data(len);
+            let file = dir.0.join("build.tar.gz");
+            std::fs::write(&file, &data).unwrap();
+
+            let state = Arc::new(Mutex::new(TusServerState::default()));
+            let server = tus_server(state.clone());
+
+            Self {
+                endpoint: format!("{}/files", server.url),
+                dir,
+                file,
+                data,
+                state,
+            }
+        }
+
+        fn client(&self) -> TusClient {
+            TusClient::new(reqwest::Client::new(), self.endpoint.clone())
+                .chunk_size(4096)
+                .retries(2, Duration::from_millis(1))
+        }
+
+        fn store(&self) -> TusUploads {
+            TusUploads::new(TusUploadStore::load(self.dir.0.join("uploads.json")))
+        }
+
+        fn key(&self) -> String {
+            format!("{}|{}", self.endpoint, self.file.display())
+        }
+
+        fn received(&self, index: usize) -> Vec<u8> {
+            self.state.lock().unwrap().uploads[index].1.clone()
+        }
+    }
+
+    async fn upload(client: &TusClient, file: &Path, store: &TusUploads) -> Result<String, String> {
+        client.upload_file(file, store, |_| {}).await
+    }
+
+    #[tokio::test]
+    async fn uploads_file_in_chunks() {
+        let fixture = Fixture::new("chunks", 10_000);
+        let store = fixture.store();
+
+        let progress = Arc::new(Mutex::new(vec![]));
+        let progress_clone = progress.clone();
+        let upload_url = fixture
+            .client()
+            .upload_file(&fixture.file, &store, move |bytes| {
+                progress_clone.lock().unwrap().push(bytes)
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(upload_url, format!("{}/0", fixture.endpoint));
+        assert_eq!(fixture.received(0), fixture.data);
+        assert_eq!(fixture.state.lock().unwrap().patches, 3);
+        assert_eq!(progress.lock().unwrap().last(), Some(&10_000));
+        // Finished uploads are not resumed
+        assert!(store.lock().await.get(&fixture.key()).is_none());
+    }
+
+    #[tokio::test]
+    async fn resumes_from_server_offset_after_failed_chunk() {
+        let fixture = Fixture::new("resume_offset", 10_000);
+        fixture.state.lock().unwrap().failing_patches.insert(2, 503);
+
+        upload(&fixture.client(), &fixture.file, &fixture.store())
+            .await
+            .unwrap();
+
+        let state = fixture.state.lock().unwrap();
+        assert_eq!(state.creates, 1);
+        // The half of the failed chunk that arrived is not sent again
+        assert_eq!(state.patches, 3);
+        drop(state);
+        assert_eq!(fixture.received(0), fixture.data);
+    }
+
+    #[tokio::test]
+    async fn resumes_saved_upload_after_restart() {
+        let fixture = Fixture::new("resume_saved", 10_000);
+        fixture.state.lock().unwrap().failing_patches.insert(2, 400);
+
+        let error = upload(&fixture.client(), &fixture.file, &fixture.store())
+            .await
+            .unwrap_err();
+        assert!(error.contains("400"), "{}", error);
+
+        // A new store reads the state the failed upload left on disk
+        let store = fixture.store();
+        assert_eq!(store.lock().await.get(&fixture.key()).unwrap().offset, 4096);
+        upload(&fixture.client(), &fixture.file, &store)
+            .await
+            .unwrap();
+
+        assert_eq!(fixture.state.lock().unwrap().creates, 1);
+        assert_eq!(fixture.received(0), fixture.data);
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_server_keeps_losing_upload() {
+        let fixture = Fixture::new("lost", 10_000);
+        fixture.state.lock().unwrap().rejecting_patches = Some(404);
+
+        let error = upload(&fixture.client(), &fixture.file, &fixture.store())
+            .await
+            .unwrap_err();
+
+        assert!(error.contains("lost"), "{}", error);
+        // The first upload and one per allowed retry
+        assert_eq!(fixture.state.lock().unwrap().creates, 3);
+    }
+
+    #[tokio::test]
+    async fn forbidden_chunk_fails_without_recreating() {
+        let fixture = Fixture::new("forbidden", 10_000);
+        fixture.state.lock().unwrap().rejecting_patches = Some(403);
+
+        let error = upload(&fixture.client(), &fixture.file, &fixture.store())
+            .await
+            .unwrap_err();
+
+        assert!(error.contains("403"), "{}", error);
+        let state = fixture.state.lock().unwrap();
+        assert_eq!(state.creates, 1);
+        assert_eq!(state.patches, 1);
+    }
+}
@@ -0,0 +1,143 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use tar::Archive;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    TarGz,
+    TarZst,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Guesses the archive format from a file name or URL path
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name
+            .split(['?', '#'])
+            .next()
+            .unwrap_or(name)
+            .to_ascii_lowercase();
+
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(Self::TarZst)
+        } else if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// Guesses the archive format from the first bytes of the archive
+    pub fn from_magic(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::TarGz)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Self::TarZst)
+        } else if bytes.starts_with(b"PK\x03\x04") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Picks the archive format by name first and falls back to sniffing the
+/// first bytes of the reader without consuming them.
+pub fn detect_format<R: BufRead>(name: &str, reader: &mut R) -> std::io::Result<ArchiveFormat> {
+    if let Some(format) = ArchiveFormat::from_name(name) {
+        return Ok(format);
+    }
+
+    let head = reader.fill_buf()?;
+    ArchiveFormat::from_magic(head).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Unknown archive format: {}", name),
+        )
+    })
+}
+
+/// Unpacks an archive from a non-seekable reader into `destination`.
+///
+/// Tar entries go through `tar::Archive::unpack`, which refuses absolute
+/// paths and `..` components. Zip entries are checked with `enclosed_name`.
+pub fn unpack_stream<R: Read>(
+    reader: R,
+    format: ArchiveFormat,
+    destination: &Path,
+) -> std::io::Result<()> {
+    match format {
+        ArchiveFormat::Zip => unpack_zip_stream(reader, destination),
+        _ => Archive::new(decompress_tar(reader, format)?).unpack(destination),
+    }
+}
+
+/// Wraps the reader in the decoder of a compressed tar format
+pub fn decompress_tar<'a, R: Read + 'a>(
+    reader: R,
+    format: ArchiveFormat,
+) -> std::io::Result<Box<dyn Read + 'a>> {
+    match format {
+        ArchiveFormat::TarGz => Ok(Box::new(GzDecoder::new(reader))),
+        ArchiveFormat::TarZst => Ok(Box::new(zstd::stream::read::Decoder::new(reader)?)),
+        ArchiveFormat::Zip => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Zip is not a tar format",
+        )),
+    }
+}
+
+/// Unpacks an archive file that is already on disk into `destination`.
+pub fn unpack_file<R: Read + std::io::Seek>(
+    reader: R,
+    format: ArchiveFormat,
+    destination: &Path,
+) -> std::io::Result<()> {
+    match format {
+        ArchiveFormat::Zip => zip::ZipArchive::new(reader)
+            .and_then(|mut archive| archive.extract(destination))
+            .map_err(std::io::Error::other),
+        _ => unpack_stream(reader, format, destination),
+    }
+}
+
+fn unpack_zip_stream<R: Read>(reader: R, destination: &Path) -> std::io::Result<()> {
+    let mut reader = BufReader::new(reader);
+
+    while let Some(mut entry) =
+        zip::read::read_zipfile_from_stream(&mut reader).map_err(std::io::Error::other)?
+    {
+        let relative_path = entry.enclosed_name().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Unsafe path in archive: {}", entry.name()),
+            )
+        })?;
+        let entry_path = destination.join(relative_path);
+
+        if entry.is_dir() {
+            std::fs::create_dir_all(&entry_path)?;
+            continue;
+        }
+
+        if let Some(parent) = entry_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut file = File::create(&entry_path)?;
+        std::io::copy(&mut entry, &mut file)?;
+
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&entry_path, std::fs::Permissions::from_mode(mode))?;
+        }
+    }
+
+    Ok(())
+}
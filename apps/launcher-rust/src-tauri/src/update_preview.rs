@@ -0,0 +1,232 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use reqwest::header::CONTENT_LENGTH;
+use serde::Serialize;
+
+use crate::chunk_store::ChunkStore;
+use crate::chunked_build::{installed_chunks, InstalledChunk};
+use crate::http_client::HttpClient;
+use crate::manifest::{Manifest, ManifestEntry, MANIFEST_FILE_NAME};
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateMethod {
+    Chunks,
+    Patch,
+    Full,
+}
+
+#[derive(Serialize)]
+pub struct UpdatePreview {
+    method: UpdateMethod,
+    // Unknown when the server does not report the size of a file
+    download_bytes: Option<u64>,
+    // Unknown when the installed build has no manifest
+    disk_delta_bytes: Option<i64>,
+    changed_files: usize,
+    added_files: usize,
+    removed_files: usize,
+}
+
+/// Size of a remote file from the `Content-Length` of a HEAD request
+async fn remote_size(http: &HttpClient, url: &str) -> Result<Option<u64>, String> {
+    let response = http
+        .send(http.client().head(url))
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Size request failed with status {}",
+            response.status()
+        ));
+    }
+
+    // `content_length()` is the size of the empty HEAD body, not the header
+    Ok(response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok()))
+}
+
+/// Counts changed, added and removed files between two builds
+fn compare_files(installed: &Manifest, target: &Manifest) -> (usize, usize, usize) {
+    let installed_files: HashMap<&str, &ManifestEntry> = installed
+        .files
+        .iter()
+        .map(|f| (f.path.as_str(), f))
+        .collect();
+    let target_paths: HashSet<&str> = target.files.iter().map(|f| f.path.as_str()).collect();
+
+    let (mut changed, mut added) = (0, 0);
+    for file in &target.files {
+        match installed_files.get(file.path.as_str()) {
+            Some(old) if old.hash == file.hash => {}
+            Some(_) => changed += 1,
+            None => added += 1,
+        }
+    }
+    let removed = installed_files
+        .keys()
+        .filter(|path| !target_paths.contains(*path))
+        .count();
+
+    (changed, added, removed)
+}
+
+/// File counts and disk change of an update, the download is filled in by
+/// the caller once the method is known
+fn preview_files(installed: Option<&Manifest>, target: &Manifest) -> UpdatePreview {
+    // Installs from before manifests were packed count as fully changed
+    let (changed_files, added_files, removed_files) = match installed {
+        Some(installed) => compare_files(installed, target),
+        None => (target.files.len(), 0, 0),
+    };
+
+    UpdatePreview {
+        method: UpdateMethod::Full,
+        download_bytes: None,
+        disk_delta_bytes: installed
+            .map(|installed| target.total_bytes as i64 - installed.total_bytes as i64),
+        changed_files,
+        added_files,
+        removed_files,
+    }
+}
+
+/// Size of the chunks of `target` that are neither in the installed files nor
+/// in the store, chunks shared by several files are fetched once
+fn missing_chunk_bytes(
+    target: &Manifest,
+    installed: &HashMap<String, InstalledChunk>,
+    store: &ChunkStore,
+) -> u64 {
+    let missing: HashMap<&str, u64> = target
+        .files
+        .iter()
+        .flat_map(|f| &f.chunks)
+        .filter(|chunk| !installed.contains_key(&chunk.hash) && !store.contains(&chunk.hash))
+        .map(|chunk| (chunk.hash.as_str(), chunk.size))
+        .collect();
+    missing.values().sum()
+}
+
+/// Tells how much an update downloads and how it changes the install before
+/// anything is fetched besides the target manifest. The frontend passes the
+/// patch only when it was made against the installed build, and the archives
+/// for a full install.
+#[tauri::command]
+pub async fn preview_update(
+    http: tauri::State<'_, HttpClient>,
+    store: tauri::State<'_, ChunkStore>,
+    install_dir: String,
+    manifest_url: String,
+    patch_url: Option<String>,
+    archive_urls: Option<Vec<String>>,
+) -> Result<UpdatePreview, String> {
+    let response = http
+        .send(http.client().get(&manifest_url))
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Manifest download failed with status {}",
+            response.status()
+        ));
+    }
+    let json = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to download manifest: {}", e))?;
+    let target = Manifest::parse(&json)?;
+
+    let install_dir = PathBuf::from(install_dir);
+    let installed = Manifest::load(&install_dir.join(MANIFEST_FILE_NAME)).ok();
+    let mut preview = preview_files(installed.as_ref(), &target);
+
+    (preview.method, preview.download_bytes) = if target.chunking.is_some() {
+        // Chunks of the installed build are read from its files
+        let installed_chunks = installed
+            .as_ref()
+            .map(|installed| installed_chunks(&install_dir, installed))
+            .unwrap_or_default();
+        (
+            UpdateMethod::Chunks,
+            Some(missing_chunk_bytes(&target, &installed_chunks, &store)),
+        )
+    } else if let Some(patch_url) = patch_url.filter(|_| installed.is_some()) {
+        (UpdateMethod::Patch, remote_size(&http, &patch_url).await?)
+    } else {
+        let mut total = Some(0);
+        for url in archive_urls.unwrap_or_default() {
+            let size = remote_size(&http, &url).await?;
+            total = total.zip(size).map(|(total, size)| total + size);
+        }
+        (UpdateMethod::Full, total)
+    };
+
+    Ok(preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::ManifestChunk;
+    use crate::test_support::{hash, manifest, TempDir, MTIME};
+
+    #[test]
+    fn counts_changed_added_and_removed_files() {
+        let installed = manifest(&[("game", b"v1"), ("data/a", b"same"), ("data/old", b"old")]);
+        let target = manifest(&[
+            ("game", b"v2"),
+            ("data/a", b"same"),
+            ("data/new", b"new data"),
+        ]);
+
+        let preview = preview_files(Some(&installed), &target);
+
+        assert_eq!(
+            (
+                preview.changed_files,
+                preview.added_files,
+                preview.removed_files
+            ),
+            (1, 1, 1)
+        );
+        assert_eq!(preview.disk_delta_bytes, Some(5));
+    }
+
+    #[test]
+    fn installs_without_a_manifest_count_as_fully_changed() {
+        let target = manifest(&[("game", b"v2"), ("data/a", b"same")]);
+
+        let preview = preview_files(None, &target);
+
+        assert_eq!(preview.changed_files, 2);
+        assert_eq!(preview.disk_delta_bytes, None);
+    }
+
+    #[test]
+    fn only_missing_chunks_are_downloaded_once() {
+        let root = TempDir::new("update_preview", "chunks");
+        let store = ChunkStore::new(root.0.join("store"));
+        store.insert(&hash(b"stored"), b"stored").unwrap();
+
+        let chunk = |data: &[u8]| ManifestChunk {
+            offset: 0,
+            size: data.len() as u64,
+            hash: hash(data),
+        };
+        root.write("install/old", b"installed", MTIME);
+        let mut installed = manifest(&[("old", b"installed")]);
+        installed.files[0].chunks = vec![chunk(b"installed")];
+        let installed = installed_chunks(&root.0.join("install"), &installed);
+
+        let mut target = manifest(&[("a", b"stored+shared"), ("b", b"shared+new+installed")]);
+        target.files[0].chunks = vec![chunk(b"stored"), chunk(b"shared")];
+        target.files[1].chunks = vec![chunk(b"shared"), chunk(b"new"), chunk(b"installed")];
+
+        assert_eq!(missing_chunk_bytes(&target, &installed, &store), 9);
+    }
+}
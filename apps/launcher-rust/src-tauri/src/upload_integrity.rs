@@ -0,0 +1,188 @@
+use serde::Deserialize;
+
+use crate::hashing::HashAlgorithm;
+
+// The backend stores SHA-256 of every uploaded file
+pub const UPLOAD_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha256;
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadIntegrityOptions {
+    // Response field the server reports the digests in, the client does not send them
+    pub field: Option<String>,
+    // Fail when the server does not report the digest of a file
+    #[serde(default = "default_required")]
+    pub required: bool,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+impl UploadIntegrityOptions {
+    pub fn field(&self) -> &str {
+        self.field.as_deref().unwrap_or("hashes")
+    }
+}
+
+/// File sent in a multipart upload and its local digest
+pub struct UploadedFile {
+    // Form field the file was sent in
+    pub field: String,
+    pub file_name: String,
+    pub digest: String,
+}
+
+/// Compares local digests with the ones the server reports in `field` of the
+/// response record, an object keyed by the stored file name. The server
+/// renames files, so the stored names are taken from the record field each
+/// file was sent in: new files are its last entries, in upload order.
+pub fn verify_reported_hashes(
+    response_body: &str,
+    options: &UploadIntegrityOptions,
+    uploaded: &[UploadedFile],
+) -> Result<(), String> {
+    let record: serde_json::Value = serde_json::from_str(response_body).unwrap_or_default();
+    let reported = record.get(options.field()).and_then(|v| v.as_object());
+
+    let mut fields: Vec<&str> = uploaded.iter().map(|file| file.field.as_str()).collect();
+    fields.sort_unstable();
+    fields.dedup();
+
+    for field in fields {
+        let files: Vec<&UploadedFile> = uploaded.iter().filter(|f| f.field == field).collect();
+        let stored: Vec<&str> = match record.get(field) {
+            Some(serde_json::Value::String(name)) => vec![name.as_str()],
+            Some(serde_json::Value::Array(names)) => {
+                names.iter().filter_map(|name| name.as_str()).collect()
+            }
+            _ => vec![],
+        };
+
+        let new_names = stored
+            .len()
+            .checked_sub(files.len())
+            .map(|kept| &stored[kept..]);
+        let Some(new_names) = new_names else {
+            if options.required {
+                return Err(format!(
+                    "Integrity check failed: server stored {} files in \"{}\", uploaded {}",
+                    stored.len(),
+                    field,
+                    files.len()
+                ));
+            }
+            continue;
+        };
+
+        for (file, stored_name) in files.iter().zip(new_names) {
+            let digest = reported
+                .and_then(|hashes| hashes.get(*stored_name))
+                .and_then(|digest| digest.as_str());
+
+            match digest {
+                Some(digest) if digest.eq_ignore_ascii_case(&file.digest) => {}
+                Some(digest) => {
+                    return Err(format!(
+                        "Integrity check failed for {}: uploaded {}, server stored {}",
+                        file.file_name, file.digest, digest
+                    ))
+                }
+                None if options.required => {
+                    return Err(format!(
+                        "Integrity check failed: server did not report a hash for {} in \"{}\"",
+                        file.file_name,
+                        options.field()
+                    ))
+                }
+                None => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARCHIVE: &str = "sha256:aaaa";
+    const MANIFEST: &str = "sha256:bbbb";
+
+    fn options(required: bool) -> UploadIntegrityOptions {
+        UploadIntegrityOptions {
+            field: None,
+            required,
+        }
+    }
+
+    fn uploaded(field: &str, file_name: &str, digest: &str) -> UploadedFile {
+        UploadedFile {
+            field: field.to_string(),
+            file_name: file_name.to_string(),
+            digest: digest.to_string(),
+        }
+    }
+
+    #[test]
+    fn matches_each_file_by_stored_name() {
+        let body = r#"{
+            "files": ["build_tar_abc.gz"],
+            "manifest": "manifest_def.json",
+            "hashes": {"build_tar_abc.gz": "SHA256:AAAA", "manifest_def.json": "sha256:bbbb"}
+        }"#;
+        let files = [
+            uploaded("files", "build.tar.gz", ARCHIVE),
+            uploaded("manifest", "manifest.json", MANIFEST),
+        ];
+
+        assert!(verify_reported_hashes(body, &options(true), &files).is_ok());
+    }
+
+    #[test]
+    fn rejects_digests_reported_for_another_file() {
+        let body = r#"{
+            "files": ["build_tar_abc.gz"],
+            "manifest": "manifest_def.json",
+            "hashes": {"build_tar_abc.gz": "sha256:bbbb", "manifest_def.json": "sha256:aaaa"}
+        }"#;
+        let files = [
+            uploaded("files", "build.tar.gz", ARCHIVE),
+            uploaded("manifest", "manifest.json", MANIFEST),
+        ];
+
+        let error = verify_reported_hashes(body, &options(false), &files).unwrap_err();
+        assert!(error.contains("build.tar.gz"), "{}", error);
+    }
+
+    #[test]
+    fn new_files_are_the_last_stored_entries() {
+        let body = r#"{
+            "files": ["old_abc.gz", "first_def.gz", "second_ghi.gz"],
+            "hashes": {"old_abc.gz": "sha256:cccc", "first_def.gz": "sha256:aaaa", "second_ghi.gz": "sha256:bbbb"}
+        }"#;
+        let files = [
+            uploaded("files", "first.gz", ARCHIVE),
+            uploaded("files", "second.gz", MANIFEST),
+        ];
+
+        assert!(verify_reported_hashes(body, &options(true), &files).is_ok());
+    }
+
+    #[test]
+    fn missing_hash_fails_only_when_required() {
+        let body = r#"{"files": ["build_tar_abc.gz"], "hashes": {}}"#;
+        let files = [uploaded("files", "build.tar.gz", ARCHIVE)];
+
+        assert!(verify_reported_hashes(body, &options(false), &files).is_ok());
+        let error = verify_reported_hashes(body, &options(true), &files).unwrap_err();
+        assert!(error.contains("did not report"), "{}", error);
+    }
+
+    #[test]
+    fn required_by_default() {
+        let options: UploadIntegrityOptions = serde_json::from_str("{}").unwrap();
+        assert!(options.required);
+    }
+}
@@ -0,0 +1,270 @@
+use std::path::{Path, PathBuf};
+
+use futures_util::StreamExt;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+use crate::delta::Rollback;
+use crate::hashing::{HashAlgorithm, StreamingHasher};
+use crate::http_client::{is_retryable_status, HttpClient};
+use crate::StreamingInstallError;
+
+// A mismatch is retried this many times before giving up
+pub const INTEGRITY_RETRIES: u32 = 1;
+
+#[derive(Serialize, Clone)]
+pub struct IntegrityError {
+    pub file_name: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl IntegrityError {
+    pub fn message(&self) -> String {
+        format!(
+            "Integrity check failed for {}: expected {}, downloaded {}",
+            self.file_name, self.expected, self.actual
+        )
+    }
+}
+
+/// Where an archive is extracted until its hash is checked, next to the
+/// install dir so unverified files never show up among the game files and
+/// can be moved in with a rename. The file name comes from the server, the
+/// dir is named after its hash so it can't point anywhere else.
+pub fn staging_dir(destination_path: &Path, file_name: &str) -> PathBuf {
+    let dir_name = destination_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let parent = destination_path.parent().unwrap_or(destination_path);
+    let file_hash = blake3::hash(file_name.as_bytes()).to_hex();
+
+    parent.join(format!(".{}-{}.extracting", dir_name, &file_hash[..16]))
+}
+
+/// Moves everything in `from` into `to`, merging folders that exist in both.
+/// Files already in `to` are replaced. On failure the replaced files are put
+/// back and the moved ones removed, so `to` is left as it was.
+pub fn move_into(from: &Path, to: &Path) -> std::io::Result<()> {
+    let mut rollback = Rollback::default();
+    match move_entries(from, to, &mut rollback) {
+        Ok(()) => {
+            rollback.finish();
+            Ok(())
+        }
+        Err(e) => {
+            rollback.restore();
+            Err(e)
+        }
+    }
+}
+
+fn move_entries(from: &Path, to: &Path, rollback: &mut Rollback) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let is_dir = entry.file_type()?.is_dir();
+
+        if is_dir && target.is_dir() {
+            move_entries(&entry.path(), &target, rollback)?;
+            continue;
+        }
+        rollback.replace(Some(&entry.path()), &target)?;
+    }
+
+    Ok(())
+}
+
+/// Downloads `url` into `path`, hashing the bytes as they are written.
+/// Returns the digest tagged with the algorithm name.
+async fn download_to_file(
+    http: &HttpClient,
+    url: &str,
+    path: &Path,
+    algorithm: HashAlgorithm,
+    on_progress: &mut impl FnMut(u64, u64),
+) -> Result<String, StreamingInstallError> {
+    let response = http
+        .send(http.client().get(url))
+        .await
+        .map_err(|e| StreamingInstallError::retryable(format!("Failed to send request: {}", e)))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let message = format!("Download failed with status {}", status);
+        return Err(if is_retryable_status(status) {
+            StreamingInstallError::retryable(message)
+        } else {
+            StreamingInstallError::fatal(message)
+        });
+    }
+
+    let total_bytes = response.content_length().unwrap_or(0);
+    let mut file = tokio::fs::File::create(path).await.map_err(|e| {
+        StreamingInstallError::fatal(format!("Failed to create download file: {}", e))
+    })?;
+    let mut hasher = StreamingHasher::new(algorithm);
+    let mut current_bytes = 0_u64;
+
+    let mut body = response.bytes_stream();
+    while let Some(chunk) = body.next().await {
+        let bytes = chunk.map_err(|e| {
+            StreamingInstallError::retryable(format!("Failed to download archive: {}", e))
+        })?;
+
+        hasher.update(&bytes);
+        file.write_all(&bytes).await.map_err(|e| {
+            StreamingInstallError::fatal(format!("Failed to write download file: {}", e))
+        })?;
+
+        current_bytes += bytes.len() as u64;
+        on_progress(current_bytes, total_bytes.max(current_bytes));
+    }
+
+    file.flush().await.map_err(|e| {
+        StreamingInstallError::fatal(format!("Failed to write download file: {}", e))
+    })?;
+
+    Ok(hasher.finalize())
+}
+
+/// Downloads the archive into `path` for builds published without a hash
+pub async fn download_unverified(
+    http: &HttpClient,
+    url: &str,
+    path: &Path,
+    mut on_progress: impl FnMut(u64, u64),
+) -> Result<(), StreamingInstallError> {
+    let result =
+        download_to_file(http, url, path, HashAlgorithm::default(), &mut on_progress).await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(path).await;
+    }
+    result.map(|_| ())
+}
+
+/// Downloads the archive into `path` and checks it against `expected_hash`.
+/// On a mismatch the file is deleted and downloaded again, a second mismatch
+/// fails with an [`IntegrityError`]. The file is only left behind on success.
+pub async fn download_verified(
+    http: &HttpClient,
+    url: &str,
+    file_name: &str,
+    path: &Path,
+    expected_hash: &str,
+    mut on_progress: impl FnMut(u64, u64),
+) -> Result<(), StreamingInstallError> {
+    let (algorithm, _) = HashAlgorithm::parse_tagged(expected_hash).ok_or_else(|| {
+        StreamingInstallError::fatal(format!("Unsupported hash: {}", expected_hash))
+    })?;
+
+    let mut attempt = 0;
+    loop {
+        let result = download_to_file(http, url, path, algorithm, &mut on_progress).await;
+        let digest = match result {
+            Ok(digest) => digest,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(path).await;
+                return Err(e);
+            }
+        };
+
+        if digest.eq_ignore_ascii_case(expected_hash) {
+            return Ok(());
+        }
+
+        let _ = tokio::fs::remove_file(path).await;
+
+        let error = IntegrityError {
+            file_name: file_name.to_string(),
+            expected: expected_hash.to_string(),
+            actual: digest,
+        };
+
+        attempt += 1;
+        if attempt > INTEGRITY_RETRIES {
+            return Err(StreamingInstallError::integrity(error));
+        }
+
+        eprintln!("{}, downloading again", error.message());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn move_into_merges_folders_and_replaces_files() {
+        let root = TempDir::new("verified_download", "move_into");
+        let (from, to) = (root.0.join("from"), root.0.join("to"));
+        std::fs::create_dir_all(from.join("data/levels")).unwrap();
+        std::fs::create_dir_all(to.join("data")).unwrap();
+        std::fs::write(from.join("game"), "new").unwrap();
+        std::fs::write(from.join("data/levels/1.bin"), "level").unwrap();
+        std::fs::write(to.join("game"), "old").unwrap();
+        std::fs::write(to.join("data/saves.bin"), "kept").unwrap();
+
+        move_into(&from, &to).unwrap();
+
+        assert_eq!(std::fs::read_to_string(to.join("game")).unwrap(), "new");
+        assert_eq!(
+            std::fs::read_to_string(to.join("data/levels/1.bin")).unwrap(),
+            "level"
+        );
+        assert_eq!(
+            std::fs::read_to_string(to.join("data/saves.bin")).unwrap(),
+            "kept"
+        );
+        assert!(!from.join("game").exists());
+        assert!(!to.join(".game.orig").exists());
+    }
+
+    #[test]
+    fn move_into_restores_the_destination_on_failure() {
+        let root = TempDir::new("verified_download", "move_into_rollback");
+        let (from, to) = (root.0.join("from"), root.0.join("to"));
+        std::fs::create_dir_all(from.join("data")).unwrap();
+        std::fs::create_dir_all(to.join("data")).unwrap();
+        std::fs::write(from.join("new"), "new").unwrap();
+        std::fs::write(from.join("game"), "new").unwrap();
+        std::fs::write(to.join("game"), "old").unwrap();
+        // The old file can't be moved aside, its backup name is too long
+        let long_name = "x".repeat(250);
+        std::fs::write(from.join("data").join(&long_name), "new").unwrap();
+        std::fs::write(to.join("data").join(&long_name), "old").unwrap();
+
+        assert!(move_into(&from, &to).is_err());
+
+        assert!(!to.join("new").exists());
+        assert_eq!(std::fs::read_to_string(to.join("game")).unwrap(), "old");
+        assert_eq!(
+            std::fs::read_to_string(to.join("data").join(&long_name)).unwrap(),
+            "old"
+        );
+        assert!(!to.join(".game.orig").exists());
+    }
+
+    #[test]
+    fn staging_dir_stays_next_to_the_install_dir() {
+        let destination = Path::new("/games/app");
+
+        for file_name in ["build.zip", "../../home", "a/../../b", "/etc"] {
+            let staging = staging_dir(destination, file_name);
+            assert_eq!(staging.parent(), Some(Path::new("/games")));
+            assert!(staging
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with(".app-"));
+        }
+        assert_ne!(
+            staging_dir(destination, "a.zip"),
+            staging_dir(destination, "b.zip")
+        );
+    }
+}
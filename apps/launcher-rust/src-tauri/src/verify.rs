@@ -0,0 +1,342 @@
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::hashing::{HashAlgorithm, StreamingHasher};
+use crate::manifest::{local_path, manifest_path, Manifest, ManifestEntry, MANIFEST_FILE_NAME};
+use crate::pack::collect_files;
+use crate::rate_meter::RateMeter;
+
+const READ_BUFFER_SIZE: usize = 256 * 1024;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileIssueKind {
+    Missing,
+    Modified,
+    Extra,
+}
+
+#[derive(Serialize, Clone)]
+pub struct FileIssue {
+    pub path: String,
+    pub kind: FileIssueKind,
+}
+
+#[derive(Serialize, Clone)]
+pub struct VerifyProgressData {
+    checked_files: usize,
+    total_files: usize,
+    current_bytes: u64,
+    total_bytes: u64,
+    delta_per_second: u64,
+    // Unknown until the rate settles
+    eta_seconds: Option<u64>,
+    // Set on the event sent right after a damaged file is found
+    issue: Option<FileIssue>,
+}
+
+#[derive(Serialize, Default)]
+pub struct VerifyReport {
+    pub intact: bool,
+    pub missing: Vec<String>,
+    pub modified: Vec<String>,
+    pub extra: Vec<String>,
+}
+
+struct ProgressState {
+    checked_files: usize,
+    current_bytes: u64,
+    rate: RateMeter,
+}
+
+/// Checks installed files against the manifest. Files are hashed on all
+/// cores, in quick mode only sizes and modification times are compared.
+pub fn verify_files(
+    install_dir: &Path,
+    manifest: &Manifest,
+    quick: bool,
+    speed_update_interval: Duration,
+    on_progress: impl Fn(VerifyProgressData) + Sync,
+) -> Result<VerifyReport, String> {
+    let total_files = manifest.files.len();
+    let total_bytes = manifest.total_bytes;
+
+    let state = Mutex::new(ProgressState {
+        checked_files: 0,
+        current_bytes: 0,
+        rate: RateMeter::new(speed_update_interval),
+    });
+    let issues = Mutex::new(vec![]);
+
+    let report_progress = |delta: u64, file_done: bool, issue: Option<FileIssue>| {
+        let mut state = state.lock().unwrap();
+        state.current_bytes += delta;
+        state.rate.add_value(delta);
+        if file_done {
+            state.checked_files += 1;
+        }
+
+        let rate = state.rate.get_rate();
+        let remaining = total_bytes.saturating_sub(state.current_bytes);
+        on_progress(VerifyProgressData {
+            checked_files: state.checked_files,
+            total_files,
+            current_bytes: state.current_bytes,
+            total_bytes,
+            delta_per_second: rate as u64,
+            eta_seconds: (rate > 0.0).then(|| (remaining as f64 / rate).ceil() as u64),
+            issue,
+        });
+    };
+
+    // Workers take the next file from a shared index until the list runs out
+    let next = AtomicUsize::new(0);
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+        .min(total_files)
+        .max(1);
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Some(entry) = manifest.files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let mut hashed_bytes = 0;
+                    let kind = check_file(install_dir, entry, quick, &mut |delta| {
+                        hashed_bytes += delta;
+                        report_progress(delta, false, None);
+                    });
+
+                    let issue = kind.map(|kind| FileIssue {
+                        path: entry.path.clone(),
+                        kind,
+                    });
+                    if let Some(issue) = &issue {
+                        issues.lock().unwrap().push(issue.clone());
+                    }
+
+                    // Missing, skipped and quick checked files still count towards the total
+                    report_progress(entry.size.saturating_sub(hashed_bytes), true, issue);
+                }
+            });
+        }
+    });
+
+    let expected: HashSet<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+    for path in find_extra_files(install_dir, &expected)? {
+        let issue = FileIssue {
+            path,
+            kind: FileIssueKind::Extra,
+        };
+        issues.lock().unwrap().push(issue.clone());
+        report_progress(0, false, Some(issue));
+    }
+
+    let mut report = VerifyReport::default();
+    for issue in issues.into_inner().unwrap() {
+        match issue.kind {
+            FileIssueKind::Missing => report.missing.push(issue.path),
+            FileIssueKind::Modified => report.modified.push(issue.path),
+            FileIssueKind::Extra => report.extra.push(issue.path),
+        }
+    }
+    report.missing.sort();
+    report.modified.sort();
+    report.extra.sort();
+    report.intact = report.missing.is_empty() && report.modified.is_empty();
+
+    Ok(report)
+}
+
+fn check_file(
+    install_dir: &Path,
+    entry: &ManifestEntry,
+    quick: bool,
+    on_read: &mut impl FnMut(u64),
+) -> Option<FileIssueKind> {
+    let path = match local_path(install_dir, &entry.path) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Some(FileIssueKind::Modified);
+        }
+    };
+
+    let metadata = match std::fs::metadata(&path) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        Ok(_) => return Some(FileIssueKind::Modified),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Some(FileIssueKind::Missing),
+        Err(e) => {
+            eprintln!("Failed to read metadata of {}: {}", path.display(), e);
+            return Some(FileIssueKind::Modified);
+        }
+    };
+
+    if metadata.len() != entry.size {
+        return Some(FileIssueKind::Modified);
+    }
+
+    if quick {
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+        let mtime_changed = entry.mtime.is_some() && mtime != entry.mtime;
+        return mtime_changed.then_some(FileIssueKind::Modified);
+    }
+
+    match hash_file(&path, &entry.hash, on_read) {
+        Ok(true) => None,
+        Ok(false) => Some(FileIssueKind::Modified),
+        Err(e) => {
+            eprintln!("Failed to hash {}: {}", path.display(), e);
+            Some(FileIssueKind::Modified)
+        }
+    }
+}
+
+/// Hashes the file with the algorithm of `expected` and compares the digests
+fn hash_file(path: &Path, expected: &str, on_read: &mut impl FnMut(u64)) -> Result<bool, String> {
+    let (algorithm, _) = HashAlgorithm::parse_tagged(expected)
+        .ok_or_else(|| format!("Unsupported hash: {}", expected))?;
+
+    let mut file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut hasher = StreamingHasher::new(algorithm);
+    let mut buf = vec![0; READ_BUFFER_SIZE];
+
+    loop {
+        let read = file.read(&mut buf).map_err(|e| e.to_string())?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+        on_read(read as u64);
+    }
+
+    Ok(hasher.finalize().eq_ignore_ascii_case(expected))
+}
+
+/// Files in the install dir that are not part of the build
+fn find_extra_files(install_dir: &Path, expected: &HashSet<&str>) -> Result<Vec<String>, String> {
+    let (entries, _) = collect_files(install_dir)?;
+
+    Ok(entries
+        .iter()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let relative = path.strip_prefix(install_dir).ok()?;
+            Some(manifest_path(relative))
+        })
+        .filter(|path| path != MANIFEST_FILE_NAME && !expected.contains(path.as_str()))
+        .collect())
+}
+
+#[tauri::command]
+pub async fn verify_install(
+    install_dir: String,
+    manifest_path: Option<String>,
+    quick: Option<bool>,
+    progress_channel: tauri::ipc::Channel<VerifyProgressData>,
+    speed_update_interval: Option<f64>,
+) -> Result<VerifyReport, String> {
+    let install_dir = PathBuf::from(install_dir);
+
+    // Every packed build carries its manifest at the root
+    let manifest_path = manifest_path
+        .map(PathBuf::from)
+        .unwrap_or_else(|| install_dir.join(MANIFEST_FILE_NAME));
+    let manifest = Manifest::load(&manifest_path)?;
+
+    tokio::task::spawn_blocking(move || {
+        verify_files(
+            &install_dir,
+            &manifest,
+            quick.unwrap_or(false),
+            Duration::from_secs_f64(speed_update_interval.unwrap_or(1.0)),
+            |progress| {
+                let res = progress_channel
+                    .send(progress)
+                    .map_err(|e| format!("Failed to send verify progress to channel: {}", e));
+                if let Err(e) = res {
+                    eprintln!("{}", e);
+                }
+            },
+        )
+    })
+    .await
+    .map_err(|e| format!("Verification task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{entry, TempDir, MTIME};
+
+    fn verify(install: &TempDir, files: Vec<ManifestEntry>, quick: bool) -> VerifyReport {
+        let mut manifest = Manifest::new(None);
+        files.into_iter().for_each(|file| manifest.push(file));
+        verify_files(&install.0, &manifest, quick, Duration::from_secs(1), |_| {}).unwrap()
+    }
+
+    #[test]
+    fn reports_missing_modified_and_extra_files() {
+        let install = TempDir::new("verify", "full");
+        install.write("game", b"binary", MTIME);
+        install.write("data/level.bin", b"changed", MTIME);
+        install.write("data/save.bin", b"save", MTIME);
+
+        let report = verify(
+            &install,
+            vec![
+                entry("game", b"binary"),
+                entry("data/level.bin", b"level!!"),
+                entry("data/gone.bin", b"gone"),
+            ],
+            false,
+        );
+
+        assert!(!report.intact);
+        assert_eq!(report.missing, ["data/gone.bin"]);
+        assert_eq!(report.modified, ["data/level.bin"]);
+        assert_eq!(report.extra, ["data/save.bin"]);
+    }
+
+    #[test]
+    fn quick_mode_compares_size_and_mtime_only() {
+        let install = TempDir::new("verify", "quick");
+        // Same size and time as the manifest, only hashing notices the change
+        install.write("same_stat", b"changed", MTIME);
+        install.write("touched", b"level", MTIME + 60);
+        install.write("resized", b"level+1", MTIME);
+
+        let files = vec![
+            entry("same_stat", b"content"),
+            entry("touched", b"level"),
+            entry("resized", b"level"),
+        ];
+
+        let quick = verify(&install, files.clone(), true);
+        assert_eq!(quick.modified, ["resized", "touched"]);
+
+        let full = verify(&install, files, false);
+        assert_eq!(full.modified, ["resized", "same_stat"]);
+    }
+
+    #[test]
+    fn quick_mode_skips_the_mtime_when_the_manifest_has_none() {
+        let install = TempDir::new("verify", "no_mtime");
+        install.write("game", b"binary", MTIME + 60);
+
+        let mut game = entry("game", b"binary");
+        game.mtime = None;
+
+        assert!(verify(&install, vec![game], true).intact);
+    }
+}
@@ -0,0 +1,5 @@
+export interface CompatTool {
+  kind: 'wine' | 'proton'
+  // Wine binary or the folder of a Proton release
+  path: string
+}
@@ -0,0 +1,18 @@
+export interface Wrapper {
+  program: string
+  args: string[]
+}
+
+// One wrapper per line, e.g. `mangohud --dlsym %command%`. Arguments are split
+// on whitespace, wrappers needing quoted arguments belong in a script.
+export function parseWrappers(text: string): Wrapper[] {
+  return text
+    .split('\n')
+    .map((line) => line.trim().split(/\s+/))
+    .filter(([program]) => program != '')
+    .map(([program, ...args]) => ({ program, args }))
+}
+
+export function formatWrappers(wrappers: Wrapper[]): string {
+  return wrappers.map((wrapper) => [wrapper.program, ...wrapper.args].join(' ')).join('\n')
+}
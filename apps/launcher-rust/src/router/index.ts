@@ -7,6 +7,8 @@ import LibraryGamePage from '@/pages/LibraryGamePage.vue'
 import SettingsPage from '@/pages/SettingsPage.vue'
 import SettingsAccountPage from '@/pages/SettingsAccountPage.vue'
 import SettingsStoragePage from '@/pages/SettingsStoragePage.vue'
+import SettingsNetworkPage from '@/pages/SettingsNetworkPage.vue'
+import SettingsLaunchPage from '@/pages/SettingsLaunchPage.vue'
 
 import { getCurrentWindow } from '@tauri-apps/api/window'
 
@@ -22,6 +24,8 @@ export const router = createRouter({
         { path: '', redirect: '/settings/account', name: 'Settings' },
         { path: 'account', component: SettingsAccountPage, name: 'Settings - Account' },
         { path: 'storage', component: SettingsStoragePage, name: 'Settings - Storage' },
+        { path: 'network', component: SettingsNetworkPage, name: 'Settings - Network' },
+        { path: 'launch', component: SettingsLaunchPage, name: 'Settings - Launch' },
       ],
     },
     {
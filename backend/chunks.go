@@ -0,0 +1,317 @@
+package main
+
+import (
+	"bufio"
+	"crypto/sha256"
+	"encoding/hex"
+	"encoding/json"
+	"io"
+	"maps"
+	"net/http"
+	"path"
+	"regexp"
+	"strings"
+	"sync"
+	"time"
+
+	"github.com/pocketbase/pocketbase/apis"
+	"github.com/pocketbase/pocketbase/core"
+	"github.com/pocketbase/pocketbase/tools/filesystem"
+)
+
+// Must not be below the launcher's maximum chunk size
+const maxChunkSize = 4 << 20
+
+// Hashes per request to /api/builds/{id}/chunks/missing
+const maxMissingBatch = 1000
+
+// Chunks uploaded this recently are kept by the cleanup job, the manifest of
+// their build is attached after them
+const chunkGracePeriod = 24 * time.Hour
+
+// Chunk sets of manifests kept for downloads, the cache is cleared when full
+const maxCachedManifests = 32
+
+var chunkHashPattern = regexp.MustCompile(`^[0-9a-f]{64}$`)
+
+var manifestChunksCache = struct {
+	sync.Mutex
+	entries map[string]map[string]struct{}
+}{entries: map[string]map[string]struct{}{}}
+
+// The part of a build manifest naming its chunks
+type chunkManifest struct {
+	Files []struct {
+		Chunks []struct {
+			Hash string `json:"hash"`
+		} `json:"chunks"`
+	} `json:"files"`
+}
+
+// registerChunkRoutes serves the content addressed chunk store of chunked
+// builds. Chunks are named by the SHA-256 of their content and shared by every
+// app, so an upload is only stored when its content matches its name. Chunks
+// are read through a build the user can view and that references them, and
+// written through a build the user can update.
+func registerChunkRoutes(se *core.ServeEvent) {
+	chunks := se.Router.Group("/api/builds/{id}/chunks")
+	chunks.Bind(apis.RequireAuth())
+	chunks.GET("/{hash}", downloadChunk)
+	chunks.POST("/missing", findMissingChunks)
+	chunks.PUT("/{hash}", uploadChunk)
+
+	se.App.Cron().MustAdd("unreferencedChunksCleanup", "30 3 * * *", func() {
+		removeUnreferencedChunks(se.App)
+	})
+}
+
+func chunkKey(hash string) string {
+	return "chunks/" + hash[:2] + "/" + hash
+}
+
+// findViewableBuild loads a build the current user is allowed to view
+func findViewableBuild(e *core.RequestEvent) (*core.Record, error) {
+	build, err := e.App.FindRecordById("app_builds", e.Request.PathValue("id"))
+	if err != nil {
+		return nil, e.NotFoundError("Build not found", err)
+	}
+
+	info, err := e.RequestInfo()
+	if err != nil {
+		return nil, e.BadRequestError("Failed to read request", err)
+	}
+	canView, err := e.App.CanAccessRecord(build, info, build.Collection().ViewRule)
+	if !canView {
+		return nil, e.NotFoundError("Build not found", err)
+	}
+
+	return build, nil
+}
+
+// readManifestChunks returns the digests of the chunks the manifest of a
+// build references. Chunked builds have JSON manifests, CBOR ones have none.
+func readManifestChunks(fsys *filesystem.System, build *core.Record) (map[string]struct{}, error) {
+	chunks := map[string]struct{}{}
+	name := build.GetString("manifest")
+	if name == "" {
+		return chunks, nil
+	}
+
+	reader, err := fsys.GetReader(build.BaseFilesPath() + "/" + name)
+	if err != nil {
+		return nil, err
+	}
+	defer reader.Close()
+
+	buffered := bufio.NewReader(reader)
+	first, err := buffered.Peek(1)
+	if err != nil {
+		return nil, err
+	}
+	if first[0] != '{' {
+		return chunks, nil
+	}
+
+	manifest := chunkManifest{}
+	if err := json.NewDecoder(buffered).Decode(&manifest); err != nil {
+		return nil, err
+	}
+	for _, file := range manifest.Files {
+		for _, chunk := range file.Chunks {
+			if digest, ok := strings.CutPrefix(chunk.Hash, "sha256:"); ok {
+				chunks[strings.ToLower(digest)] = struct{}{}
+			}
+		}
+	}
+
+	return chunks, nil
+}
+
+// cachedManifestChunks is readManifestChunks for downloads, which ask for
+// the chunks of the same few builds over and over
+func cachedManifestChunks(fsys *filesystem.System, build *core.Record) (map[string]struct{}, error) {
+	key := build.Id + "/" + build.GetString("manifest")
+
+	manifestChunksCache.Lock()
+	chunks, ok := manifestChunksCache.entries[key]
+	manifestChunksCache.Unlock()
+	if ok {
+		return chunks, nil
+	}
+
+	chunks, err := readManifestChunks(fsys, build)
+	if err != nil {
+		return nil, err
+	}
+
+	manifestChunksCache.Lock()
+	if len(manifestChunksCache.entries) >= maxCachedManifests {
+		clear(manifestChunksCache.entries)
+	}
+	manifestChunksCache.entries[key] = chunks
+	manifestChunksCache.Unlock()
+
+	return chunks, nil
+}
+
+func downloadChunk(e *core.RequestEvent) error {
+	hash := e.Request.PathValue("hash")
+	if !chunkHashPattern.MatchString(hash) {
+		return e.BadRequestError("Invalid chunk hash", nil)
+	}
+
+	build, err := findViewableBuild(e)
+	if err != nil {
+		return err
+	}
+
+	fsys, err := e.App.NewFilesystem()
+	if err != nil {
+		return e.InternalServerError("Failed to open storage", err)
+	}
+	defer fsys.Close()
+
+	chunks, err := cachedManifestChunks(fsys, build)
+	if err != nil {
+		return e.InternalServerError("Failed to read build manifest", err)
+	}
+	if _, ok := chunks[hash]; !ok {
+		return e.NotFoundError("Chunk not found", nil)
+	}
+
+	exists, err := fsys.Exists(chunkKey(hash))
+	if err != nil {
+		return e.InternalServerError("Failed to check chunk", err)
+	}
+	if !exists {
+		return e.NotFoundError("Chunk not found", nil)
+	}
+
+	// The content of a chunk never changes, only users who can view a build get it
+	e.Response.Header().Set("Cache-Control", "private, max-age=31536000, immutable")
+	return fsys.Serve(e.Response, e.Request, chunkKey(hash), hash)
+}
+
+func findMissingChunks(e *core.RequestEvent) error {
+	data := struct {
+		Hashes []string `json:"hashes"`
+	}{}
+
+	if err := e.BindBody(&data); err != nil {
+		return e.BadRequestError("Failed to read request data", err)
+	}
+	if len(data.Hashes) > maxMissingBatch {
+		return e.BadRequestError("Too many hashes", nil)
+	}
+
+	if _, err := findUpdatableBuild(e); err != nil {
+		return err
+	}
+
+	fsys, err := e.App.NewFilesystem()
+	if err != nil {
+		return e.InternalServerError("Failed to open storage", err)
+	}
+	defer fsys.Close()
+
+	missing := []string{}
+	for _, hash := range data.Hashes {
+		if !chunkHashPattern.MatchString(hash) {
+			return e.BadRequestError("Invalid chunk hash", nil)
+		}
+
+		exists, err := fsys.Exists(chunkKey(hash))
+		if err != nil {
+			return e.InternalServerError("Failed to check chunk", err)
+		}
+		if !exists {
+			missing = append(missing, hash)
+		}
+	}
+
+	return e.JSON(http.StatusOK, map[string]any{"missing": missing})
+}
+
+func uploadChunk(e *core.RequestEvent) error {
+	hash := e.Request.PathValue("hash")
+	if !chunkHashPattern.MatchString(hash) {
+		return e.BadRequestError("Invalid chunk hash", nil)
+	}
+
+	content, err := io.ReadAll(io.LimitReader(e.Request.Body, maxChunkSize+1))
+	if err != nil {
+		return e.BadRequestError("Failed to read chunk", err)
+	}
+	if len(content) > maxChunkSize {
+		return e.BadRequestError("Chunk is too large", nil)
+	}
+
+	digest := sha256.Sum256(content)
+	if hex.EncodeToString(digest[:]) != hash {
+		return e.BadRequestError("Chunk content does not match its hash", nil)
+	}
+
+	if _, err := findUpdatableBuild(e); err != nil {
+		return err
+	}
+
+	fsys, err := e.App.NewFilesystem()
+	if err != nil {
+		return e.InternalServerError("Failed to open storage", err)
+	}
+	defer fsys.Close()
+
+	exists, err := fsys.Exists(chunkKey(hash))
+	if err != nil {
+		return e.InternalServerError("Failed to check chunk", err)
+	}
+	if !exists {
+		if err := fsys.Upload(content, chunkKey(hash)); err != nil {
+			return e.InternalServerError("Failed to store chunk", err)
+		}
+	}
+
+	return e.NoContent(http.StatusNoContent)
+}
+
+// removeUnreferencedChunks keeps only the chunks the stored manifests of
+// builds reference. Nothing is removed when a manifest can't be read.
+func removeUnreferencedChunks(app core.App) {
+	builds, err := app.FindRecordsByFilter("app_builds", "manifest != ''", "", 0, 0)
+	if err != nil {
+		app.Logger().Warn("Failed to list builds with manifests", "error", err)
+		return
+	}
+
+	fsys, err := app.NewFilesystem()
+	if err != nil {
+		app.Logger().Warn("Failed to open storage", "error", err)
+		return
+	}
+	defer fsys.Close()
+
+	referenced := map[string]struct{}{}
+	for _, build := range builds {
+		chunks, err := readManifestChunks(fsys, build)
+		if err != nil {
+			app.Logger().Warn("Failed to read build manifest, keeping every chunk", "build", build.Id, "error", err)
+			return
+		}
+		maps.Copy(referenced, chunks)
+	}
+
+	objects, err := fsys.List("chunks/")
+	if err != nil {
+		app.Logger().Warn("Failed to list chunks", "error", err)
+		return
+	}
+
+	for _, object := range objects {
+		if _, ok := referenced[path.Base(object.Key)]; ok || time.Since(object.ModTime) < chunkGracePeriod {
+			continue
+		}
+		if err := fsys.Delete(object.Key); err != nil {
+			app.Logger().Warn("Failed to remove unreferenced chunk", "key", object.Key, "error", err)
+		}
+	}
+}
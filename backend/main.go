@@ -1,6 +1,9 @@
 package main
 
 import (
+	"crypto/sha256"
+	"encoding/hex"
+	"io"
 	"log"
 	"log/slog"
 	"os"
@@ -9,6 +12,7 @@ import (
 	"github.com/pocketbase/pocketbase/apis"
 	"github.com/pocketbase/pocketbase/core"
 	"github.com/pocketbase/pocketbase/plugins/migratecmd"
+	"github.com/pocketbase/pocketbase/tools/filesystem"
 
 	// enable once you have at least one migration
 	_ "zapuskalka-backend/migrations"
@@ -48,10 +52,17 @@ func main() {
 			return apis.RecordAuthResponse(e, user, "app", nil)
 
 		})
+		registerChunkRoutes(se)
+		registerTusRoutes(se)
+		registerS3MultipartRoutes(se)
 
 		return se.Next()
 	})
 
+	// Store digests of uploaded build files so the uploader can check them
+	app.OnRecordCreateRequest("app_builds").BindFunc(hashBuildFiles)
+	app.OnRecordUpdateRequest("app_builds").BindFunc(hashBuildFiles)
+
 	migratecmd.MustRegister(app, app.RootCmd, migratecmd.Config{
 		// enable auto creation of migration files when making collection changes in the Dashboard
 		Dir:         "./migrations",
@@ -62,3 +73,54 @@ func main() {
 		log.Fatal(err)
 	}
 }
+
+// hashBuildFiles stores the SHA-256 of every file uploaded in the request in
+// the "hashes" field, keyed by the stored file name so the launcher can look
+// it up from the file fields. Digests of files kept from before the request
+// stay. Whatever the client sent in "hashes" is only a claim and is never
+// stored.
+func hashBuildFiles(e *core.RecordRequestEvent) error {
+	hashes := map[string]string{}
+	if !e.Record.IsNew() {
+		if err := e.Record.Original().UnmarshalJSONField("hashes", &hashes); err != nil {
+			hashes = map[string]string{}
+		}
+	}
+
+	for _, field := range e.Record.Collection().Fields {
+		if field.Type() != core.FieldTypeFile {
+			continue
+		}
+
+		for _, file := range e.Record.GetUnsavedFiles(field.GetName()) {
+			digest, err := hashFile(file)
+			if err != nil {
+				return e.InternalServerError("Failed to hash uploaded file", err)
+			}
+			hashes[file.Name] = digest
+		}
+	}
+
+	if len(hashes) == 0 {
+		e.Record.Set("hashes", nil)
+	} else {
+		e.Record.Set("hashes", hashes)
+	}
+
+	return e.Next()
+}
+
+func hashFile(file *filesystem.File) (string, error) {
+	reader, err := file.Reader.Open()
+	if err != nil {
+		return "", err
+	}
+	defer reader.Close()
+
+	hasher := sha256.New()
+	if _, err := io.Copy(hasher, reader); err != nil {
+		return "", err
+	}
+
+	return "sha256:" + hex.EncodeToString(hasher.Sum(nil)), nil
+}
@@ -0,0 +1,50 @@
+package migrations
+
+import (
+	"github.com/pocketbase/pocketbase/core"
+	m "github.com/pocketbase/pocketbase/migrations"
+)
+
+func init() {
+	m.Register(func(app core.App) error {
+		collection, err := app.FindCollectionByNameOrId("pbc_1033968107")
+		if err != nil {
+			return err
+		}
+
+		// add field, CBOR manifests aren't sniffed as application/cbor
+		// and are detected as application/octet-stream
+		if err := collection.Fields.AddMarshaledJSONAt(8, []byte(`{
+			"hidden": false,
+			"id": "file2474116734",
+			"maxSelect": 1,
+			"maxSize": 104857600,
+			"mimeTypes": [
+				"application/json",
+				"application/cbor",
+				"application/octet-stream"
+			],
+			"name": "manifest",
+			"presentable": false,
+			"protected": false,
+			"required": false,
+			"system": false,
+			"thumbs": [],
+			"type": "file"
+		}`)); err != nil {
+			return err
+		}
+
+		return app.Save(collection)
+	}, func(app core.App) error {
+		collection, err := app.FindCollectionByNameOrId("pbc_1033968107")
+		if err != nil {
+			return err
+		}
+
+		// remove field
+		collection.Fields.RemoveById("file2474116734")
+
+		return app.Save(collection)
+	})
+}
@@ -0,0 +1,296 @@
+package main
+
+import (
+	"encoding/base64"
+	"encoding/json"
+	"errors"
+	"io"
+	"io/fs"
+	"net/http"
+	"os"
+	"path/filepath"
+	"regexp"
+	"strconv"
+	"strings"
+	"sync"
+	"time"
+
+	"github.com/pocketbase/pocketbase/apis"
+	"github.com/pocketbase/pocketbase/core"
+	"github.com/pocketbase/pocketbase/tools/filesystem"
+	"github.com/pocketbase/pocketbase/tools/security"
+)
+
+const tusVersion = "1.0.0"
+
+// Limit of resumable and multipart uploads, the "files" field of app_builds
+// allows the same size since attached uploads are validated against it
+const maxResumableUploadSize = 64 << 30
+
+// Unfinished uploads older than this are removed by the cleanup job
+const tusUploadTTL = 24 * time.Hour
+
+var tusUploadIdPattern = regexp.MustCompile(`^[a-zA-Z0-9]{32}$`)
+
+// Files of an upload directory, an upload can't be named like them
+var tusReservedNames = map[string]bool{"info.json": true, "data": true}
+
+// Appends to the same upload are serialized, its offset is the file size
+var tusUploadLocks sync.Map
+
+type tusUpload struct {
+	Length   int64  `json:"length"`
+	Filename string `json:"filename"`
+	Owner    string `json:"owner"`
+}
+
+// registerTusRoutes serves resumable uploads with the tus 1.0 protocol (core
+// and creation extensions) for build archives too large for one request. A
+// finished upload is moved into a build record with attach-upload.
+func registerTusRoutes(se *core.ServeEvent) {
+	uploads := se.Router.Group("/api/uploads")
+	uploads.Bind(apis.RequireAuth())
+	uploads.POST("", createTusUpload)
+	uploads.HEAD("/{id}", tusUploadOffset)
+	uploads.PATCH("/{id}", appendTusUpload)
+
+	se.Router.POST("/api/builds/{id}/attach-upload", attachTusUpload).Bind(apis.RequireAuth())
+
+	se.App.Cron().MustAdd("tusUploadsCleanup", "0 * * * *", func() {
+		removeStaleTusUploads(se.App)
+	})
+}
+
+func tusUploadsDir(app core.App) string {
+	return filepath.Join(app.DataDir(), "tus_uploads")
+}
+
+func tusUploadDir(app core.App, id string) string {
+	return filepath.Join(tusUploadsDir(app), id)
+}
+
+// parseTusFilename reads the "filename" key of Upload-Metadata, a comma
+// separated list of "key base64(value)" pairs
+func parseTusFilename(metadata string) string {
+	for _, pair := range strings.Split(metadata, ",") {
+		key, value, _ := strings.Cut(strings.TrimSpace(pair), " ")
+		if key != "filename" {
+			continue
+		}
+		decoded, err := base64.StdEncoding.DecodeString(value)
+		if err != nil {
+			return ""
+		}
+		return filepath.Base(string(decoded))
+	}
+	return ""
+}
+
+func createTusUpload(e *core.RequestEvent) error {
+	e.Response.Header().Set("Tus-Resumable", tusVersion)
+
+	length, err := strconv.ParseInt(e.Request.Header.Get("Upload-Length"), 10, 64)
+	if err != nil || length <= 0 {
+		return e.BadRequestError("Invalid Upload-Length", err)
+	}
+	if length > maxResumableUploadSize {
+		return e.Error(http.StatusRequestEntityTooLarge, "Upload is too large", nil)
+	}
+
+	filename := parseTusFilename(e.Request.Header.Get("Upload-Metadata"))
+	if filename == "" || filename == "." || filename == string(filepath.Separator) || tusReservedNames[filename] {
+		return e.BadRequestError("Upload-Metadata has no filename", nil)
+	}
+
+	id := security.RandomString(32)
+	dir := tusUploadDir(e.App, id)
+	if err := os.MkdirAll(dir, 0o755); err != nil {
+		return e.InternalServerError("Failed to create upload", err)
+	}
+
+	info, err := json.Marshal(tusUpload{Length: length, Filename: filename, Owner: e.Auth.Id})
+	if err != nil {
+		return e.InternalServerError("Failed to create upload", err)
+	}
+	if err := os.WriteFile(filepath.Join(dir, "info.json"), info, 0o644); err != nil {
+		return e.InternalServerError("Failed to create upload", err)
+	}
+	if err := os.WriteFile(filepath.Join(dir, "data"), nil, 0o644); err != nil {
+		return e.InternalServerError("Failed to create upload", err)
+	}
+
+	e.Response.Header().Set("Location", "/api/uploads/"+id)
+	return e.NoContent(http.StatusCreated)
+}
+
+// findTusUpload loads an upload of the current user and its received size.
+// Uploads of other users are reported as missing.
+func findTusUpload(e *core.RequestEvent, id string) (*tusUpload, int64, error) {
+	if !tusUploadIdPattern.MatchString(id) {
+		return nil, 0, e.NotFoundError("Upload not found", nil)
+	}
+
+	dir := tusUploadDir(e.App, id)
+	raw, err := os.ReadFile(filepath.Join(dir, "info.json"))
+	if errors.Is(err, fs.ErrNotExist) {
+		return nil, 0, e.NotFoundError("Upload not found", nil)
+	}
+	if err != nil {
+		return nil, 0, e.InternalServerError("Failed to read upload", err)
+	}
+
+	upload := &tusUpload{}
+	if err := json.Unmarshal(raw, upload); err != nil {
+		return nil, 0, e.InternalServerError("Failed to read upload", err)
+	}
+	if upload.Owner != e.Auth.Id {
+		return nil, 0, e.NotFoundError("Upload not found", nil)
+	}
+
+	stat, err := os.Stat(filepath.Join(dir, "data"))
+	if err != nil {
+		return nil, 0, e.InternalServerError("Failed to read upload", err)
+	}
+
+	return upload, stat.Size(), nil
+}
+
+func tusUploadOffset(e *core.RequestEvent) error {
+	e.Response.Header().Set("Tus-Resumable", tusVersion)
+
+	upload, offset, err := findTusUpload(e, e.Request.PathValue("id"))
+	if err != nil {
+		return err
+	}
+
+	e.Response.Header().Set("Cache-Control", "no-store")
+	e.Response.Header().Set("Upload-Offset", strconv.FormatInt(offset, 10))
+	e.Response.Header().Set("Upload-Length", strconv.FormatInt(upload.Length, 10))
+	return e.NoContent(http.StatusOK)
+}
+
+func appendTusUpload(e *core.RequestEvent) error {
+	e.Response.Header().Set("Tus-Resumable", tusVersion)
+
+	if e.Request.Header.Get("Content-Type") != "application/offset+octet-stream" {
+		return e.Error(http.StatusUnsupportedMediaType, "Invalid Content-Type", nil)
+	}
+
+	id := e.Request.PathValue("id")
+	lock, _ := tusUploadLocks.LoadOrStore(id, &sync.Mutex{})
+	lock.(*sync.Mutex).Lock()
+	defer lock.(*sync.Mutex).Unlock()
+
+	upload, offset, err := findTusUpload(e, id)
+	if err != nil {
+		return err
+	}
+
+	requestOffset, err := strconv.ParseInt(e.Request.Header.Get("Upload-Offset"), 10, 64)
+	if err != nil {
+		return e.BadRequestError("Invalid Upload-Offset", err)
+	}
+	if requestOffset != offset {
+		return e.Error(http.StatusConflict, "Upload-Offset does not match the upload", nil)
+	}
+
+	file, err := os.OpenFile(filepath.Join(tusUploadDir(e.App, id), "data"), os.O_WRONLY|os.O_APPEND, 0)
+	if err != nil {
+		return e.InternalServerError("Failed to open upload", err)
+	}
+	defer file.Close()
+
+	// Whatever arrived before the connection broke is kept, the client resumes from there
+	written, copyErr := io.Copy(file, io.LimitReader(e.Request.Body, upload.Length-offset))
+	if err := file.Sync(); err != nil {
+		return e.InternalServerError("Failed to store upload", err)
+	}
+	if copyErr != nil {
+		return e.BadRequestError("Failed to read upload data", copyErr)
+	}
+
+	e.Response.Header().Set("Upload-Offset", strconv.FormatInt(offset+written, 10))
+	return e.NoContent(http.StatusNoContent)
+}
+
+// attachTusUpload replaces the files of a build with a finished upload, as if
+// it had been sent in the "files" field of a record update
+func attachTusUpload(e *core.RequestEvent) error {
+	data := struct {
+		Upload string `json:"upload"`
+	}{}
+	if err := e.BindBody(&data); err != nil {
+		return e.BadRequestError("Failed to read request data", err)
+	}
+
+	build, err := e.App.FindRecordById("app_builds", e.Request.PathValue("id"))
+	if err != nil {
+		return e.NotFoundError("Build not found", err)
+	}
+
+	info, err := e.RequestInfo()
+	if err != nil {
+		return e.BadRequestError("Failed to read request", err)
+	}
+	canUpdate, err := e.App.CanAccessRecord(build, info, build.Collection().UpdateRule)
+	if !canUpdate {
+		return e.ForbiddenError("You are not allowed to update this build", err)
+	}
+
+	upload, offset, err := findTusUpload(e, data.Upload)
+	if err != nil {
+		return err
+	}
+	if offset != upload.Length {
+		return e.BadRequestError("Upload is not finished", nil)
+	}
+
+	// The stored file name comes from the name of the file on disk. It is a
+	// hard link so the upload stays attachable when saving the build fails.
+	dir := tusUploadDir(e.App, data.Upload)
+	path := filepath.Join(dir, upload.Filename)
+	if err := os.Remove(path); err != nil && !errors.Is(err, fs.ErrNotExist) {
+		return e.InternalServerError("Failed to read upload", err)
+	}
+	if err := os.Link(filepath.Join(dir, "data"), path); err != nil {
+		return e.InternalServerError("Failed to read upload", err)
+	}
+
+	file, err := filesystem.NewFileFromPath(path)
+	if err != nil {
+		return e.InternalServerError("Failed to read upload", err)
+	}
+	digest, err := hashFile(file)
+	if err != nil {
+		return e.InternalServerError("Failed to hash uploaded file", err)
+	}
+
+	build.Set("files", []any{file})
+	build.Set("hashes", map[string]string{file.Name: digest})
+	if err := e.App.Save(build); err != nil {
+		return e.BadRequestError("Failed to save build", err)
+	}
+
+	if err := os.RemoveAll(dir); err != nil {
+		e.App.Logger().Warn("Failed to remove attached upload", "id", data.Upload, "error", err)
+	}
+
+	return e.JSON(http.StatusOK, build)
+}
+
+func removeStaleTusUploads(app core.App) {
+	entries, err := os.ReadDir(tusUploadsDir(app))
+	if err != nil {
+		return
+	}
+
+	for _, entry := range entries {
+		stat, err := os.Stat(filepath.Join(tusUploadsDir(app), entry.Name(), "data"))
+		if err == nil && time.Since(stat.ModTime()) < tusUploadTTL {
+			continue
+		}
+		if err := os.RemoveAll(filepath.Join(tusUploadsDir(app), entry.Name())); err != nil {
+			app.Logger().Warn("Failed to remove stale upload", "id", entry.Name(), "error", err)
+		}
+	}
+}
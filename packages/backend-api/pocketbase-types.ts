@@ -140,21 +140,26 @@ export enum AppBuildsInstallRulesOptions {
 	"ungzip" = "ungzip",
 	"unzip" = "unzip",
 }
-export type AppBuildsRecord = {
+export type AppBuildsRecord<Thashes = unknown> = {
 	app: RecordIdString
 	arch: AppBuildsArchOptions
 	branch: RecordIdString
 	created: IsoAutoDateString
+	delta_base?: RecordIdString
+	delta_patch?: FileNameString
 	entrypoint: string
 	files?: FileNameString[]
+	hashes?: null | Thashes
 	id: string
 	install_rules: AppBuildsInstallRulesOptions[]
+	manifest?: FileNameString
 	os: AppBuildsOsOptions
 	updated: IsoAutoDateString
 }
 
 export type AppsRecord = {
 	created: IsoAutoDateString
+	crash_report_dsn?: string
 	default_branch?: RecordIdString
 	id: string
 	publisher: RecordIdString
@@ -210,7 +215,7 @@ export type MfasResponse<Texpand = unknown> = Required<MfasRecord> & BaseSystemF
 export type OtpsResponse<Texpand = unknown> = Required<OtpsRecord> & BaseSystemFields<Texpand>
 export type SuperusersResponse<Texpand = unknown> = Required<SuperusersRecord> & AuthSystemFields<Texpand>
 export type AppBranchesResponse<Texpand = unknown> = Required<AppBranchesRecord> & BaseSystemFields<Texpand>
-export type AppBuildsResponse<Texpand = unknown> = Required<AppBuildsRecord> & BaseSystemFields<Texpand>
+export type AppBuildsResponse<Thashes = unknown, Texpand = unknown> = Required<AppBuildsRecord<Thashes>> & BaseSystemFields<Texpand>
 export type AppsResponse<Texpand = unknown> = Required<AppsRecord> & BaseSystemFields<Texpand>
 export type AvBuildChecksResponse<Texpand = unknown> = Required<AvBuildChecksRecord> & BaseSystemFields<Texpand>
 export type PublishersResponse<Texpand = unknown> = Required<PublishersRecord> & BaseSystemFields<Texpand>